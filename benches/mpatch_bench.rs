@@ -1,9 +1,62 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
 use indoc::indoc;
 use mpatch::{
-    apply_patch_to_content, detect_patch, find_hunk_location_in_lines, parse_conflict_markers,
-    parse_diffs, parse_patches, ApplyOptions, Patch,
+    apply_patch_in_place, apply_patch_to_content, detect_patch, find_hunk_location_in_lines,
+    parse_conflict_markers, parse_diffs, parse_patches, ApplyOptions, Patch,
 };
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts heap allocations, so benchmarks can report how many a call makes.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Returns the result of `f` and the number of heap allocations it made.
+fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let result = f();
+    (result, ALLOCATIONS.load(Ordering::Relaxed) - before)
+}
+
+/// Builds a file of `4 * sections` lines and a raw diff that changes one line
+/// in every group of four, emitted as one `---`/`+++` section per hunk the way
+/// some tools generate them.
+fn machine_generated_diff(sections: usize) -> (String, String) {
+    let content: String = (0..sections * 4).map(|i| format!("line {}\n", i)).collect();
+    let diff: String = (0..sections)
+        .map(|i| {
+            let first = i * 4;
+            format!(
+                "--- a/generated.txt\n+++ b/generated.txt\n@@ -{0},3 +{0},3 @@\n line {1}\n-line {2}\n+LINE {2}\n line {3}\n",
+                first + 1,
+                first,
+                first + 1,
+                first + 2
+            )
+        })
+        .collect();
+    (content, diff)
+}
 
 // --- Detecting Benchmarks ---
 
@@ -95,6 +148,12 @@ fn parsing_benches(c: &mut Criterion) {
         b.iter(|| parse_diffs(black_box(&large_markdown)).unwrap())
     });
 
+    // Thousands of single-hunk sections for the same file, merged into one patch
+    let (_, generated_diff) = machine_generated_diff(5000);
+    group.bench_function("5000_sections_same_file", |b| {
+        b.iter(|| parse_patches(black_box(&generated_diff)).unwrap())
+    });
+
     let raw_diff = "--- a/file.txt\n+++ b/file.txt\n@@ -1 +1 @@\n-foo\n+bar\n";
     group.bench_function("raw_diff", |b| {
         b.iter(|| parse_patches(black_box(raw_diff)).unwrap())
@@ -272,6 +331,29 @@ fn applying_benches(c: &mut Criterion) {
         });
     });
 
+    // --- Benchmark 2b: The same patch applied in place to an owned buffer ---
+    // The buffer is cloned outside the timed section, so this measures only the
+    // splice, without re-splitting and re-joining the whole file.
+    let large_file_lines: Vec<String> = exact_large_setup
+        .initial_content
+        .lines()
+        .map(String::from)
+        .collect();
+    group.bench_function("exact_match_large_file_in_place", |b| {
+        b.iter_batched(
+            || large_file_lines.clone(),
+            |mut lines| {
+                criterion::black_box(apply_patch_in_place(
+                    black_box(&exact_large_setup.patch),
+                    &mut lines,
+                    &options_exact,
+                ));
+                lines
+            },
+            BatchSize::LargeInput,
+        );
+    });
+
     // --- Benchmark 3: Fuzzy Match on a large file (anchor found) ---
     let mut fuzzy_target_content = exact_large_setup.initial_content.clone();
     // Insert a line to break the exact match but keep anchors intact
@@ -362,6 +444,54 @@ fn applying_benches(c: &mut Criterion) {
         });
     });
 
+    // --- Benchmark 6: Thousands of hunks with accurate line hints ---
+    let (generated_content, generated_diff) = machine_generated_diff(5000);
+    let generated_setup = ApplyBenchSetup {
+        patch: parse_patches(&generated_diff).unwrap().remove(0),
+        initial_content: generated_content,
+    };
+    group.bench_function("5000_hinted_hunks", |b| {
+        b.iter(|| {
+            criterion::black_box(apply_patch_to_content(
+                black_box(&generated_setup.patch),
+                black_box(Some(&generated_setup.initial_content)),
+                &options_exact,
+            ));
+        });
+    });
+
+    // --- Benchmark 7: A small patch to a million-line file ---
+    // Untouched lines stay borrowed from the input, so the allocation count
+    // depends on the size of the patch, not of the file.
+    let million_lines: String = (0..1_000_000)
+        .map(|i| format!("This is line number {}\n", i))
+        .collect();
+    let million_patch = parse_patches(indoc! {"
+        --- a/huge.txt
+        +++ b/huge.txt
+        @@ -500000,3 +500000,3 @@
+         This is line number 499999
+        -This is line number 500000
+        +THIS LINE WAS CHANGED
+         This is line number 500001
+    "})
+    .unwrap()
+    .remove(0);
+    let (result, allocations) = count_allocations(|| {
+        apply_patch_to_content(&million_patch, Some(&million_lines), &options_exact)
+    });
+    assert!(result.report.all_applied_cleanly());
+    println!("small_patch_million_line_file: {} allocations", allocations);
+    group.bench_function("small_patch_million_line_file", |b| {
+        b.iter(|| {
+            criterion::black_box(apply_patch_to_content(
+                black_box(&million_patch),
+                black_box(Some(&million_lines)),
+                &options_exact,
+            ));
+        });
+    });
+
     group.finish();
 }
 
@@ -41,6 +41,18 @@ class Hunk:
         """The starting line number in the new file (1-based)."""
         ...
     @property
+    def raw_header(self) -> str | None:
+        """The `@@` header line exactly as it appeared in the parsed diff, if any."""
+        ...
+    @property
+    def old_count(self) -> int | None:
+        """The number of old lines declared by the `@@` header, if any."""
+        ...
+    @property
+    def new_count(self) -> int | None:
+        """The number of new lines declared by the `@@` header, if any."""
+        ...
+    @property
     def context_lines(self) -> list[str]:
         """Extracts the context lines from the hunk (lines starting with ' ')."""
         ...
@@ -66,9 +78,24 @@ class Hunk:
         deletions).
         """
         ...
+    def is_truncated(self) -> bool:
+        """Checks whether the hunk has fewer lines than its header declared."""
+        ...
     def invert(self) -> Hunk:
         """Creates a new Hunk that reverses the changes in this one."""
         ...
+    def trim_context(self, max_context: int) -> Hunk:
+        """
+        Creates a copy of this hunk with at most `max_context` context lines
+        around its changes. Context between changes is kept.
+
+        Args:
+            max_context (int): The number of context lines to keep on either side.
+
+        Returns:
+            Hunk: The trimmed hunk.
+        """
+        ...
     def __len__(self) -> int: ...
     @overload
     def __getitem__(self, idx: int) -> str: ...
@@ -131,6 +158,10 @@ class Patch:
     @file_path.setter
     def file_path(self, path: str | os.PathLike[Any]) -> None: ...
     @property
+    def new_file_path(self) -> pathlib.Path | None:
+        """The path the file is renamed to, or `None` if the patch does not rename it."""
+        ...
+    @property
     def hunks(self) -> list[Hunk]:
         """A list of hunks to be applied to the file."""
         ...
@@ -139,6 +170,14 @@ class Patch:
         """Indicates whether the file should end with a newline."""
         ...
     @property
+    def deletes_file(self) -> bool:
+        """Whether the patch deletes the file (its header names `/dev/null`)."""
+        ...
+    @property
+    def path_inferred(self) -> bool:
+        """Whether the file path was guessed from the prose before a block of bare hunks."""
+        ...
+    @property
     def is_creation(self) -> bool:
         """Checks if the patch represents a file creation."""
         ...
@@ -146,9 +185,69 @@ class Patch:
     def is_deletion(self) -> bool:
         """Checks if the patch represents a full file deletion."""
         ...
+    @property
+    def is_possibly_truncated(self) -> bool:
+        """Checks whether the patch came from a code block that was never closed."""
+        ...
     def invert(self) -> Patch:
         """Creates a new Patch that reverses the changes in this one."""
         ...
+    def dedup_hunks(self) -> None:
+        """Removes every hunk that repeats the lines of an earlier hunk, keeping the first copy."""
+        ...
+    def merge(self, other: Patch) -> Patch:
+        """
+        Combines this patch with another patch for the same file.
+
+        Hunks are sorted by their original start line; hunks without one go last.
+
+        Args:
+            other (Patch): The patch to merge into this one.
+
+        Returns:
+            Patch: The combined patch.
+
+        Raises:
+            ValueError: If the patches target different files or have overlapping hunks.
+        """
+        ...
+    def with_context(self, max_context: int) -> Patch:
+        """
+        Creates a copy of this patch where each hunk keeps at most
+        `max_context` context lines around its changes.
+
+        Args:
+            max_context (int): The number of context lines to keep on either side of each hunk.
+
+        Returns:
+            Patch: The patch with trimmed hunks.
+        """
+        ...
+    def validate(self) -> list[tuple[str, str]]:
+        """
+        Checks the patch for signs that it is malformed.
+
+        Returns:
+            list[tuple[str, str]]: A (severity, message) pair per problem, where severity
+                is 'warning' or 'error'. Empty for a well-formed patch.
+        """
+        ...
+    def semantic_eq(self, other: Patch, base: str | None = None) -> bool:
+        """
+        Checks whether two patches describe the same change.
+
+        Args:
+            other (Patch): The patch to compare against.
+            base (str | None, optional): Content both patches apply to. When given, the
+                patches are compared by the result they produce on it. Defaults to None.
+
+        Returns:
+            bool: True if the patches are semantically equal.
+        """
+        ...
+    def change_signature(self) -> str:
+        """Returns a canonical, context-free summary of the patch's line changes."""
+        ...
     def apply_to_file(
         self,
         target_dir: str | os.PathLike[Any],
@@ -195,6 +294,52 @@ class Patch:
     def __iter__(self) -> Iterator[Hunk]: ...
     def __bool__(self) -> bool: ...
     def __invert__(self) -> Patch: ...
+    @property
+    def index_lines(self) -> list[str]:
+        """The Git extended header lines (`diff --git`, `index`, ...) that preceded the file headers."""
+        ...
+    @property
+    def old_mode(self) -> int | None:
+        """The file mode before the patch (e.g. `0o100644`), if the Git header records one."""
+        ...
+    @property
+    def new_mode(self) -> int | None:
+        """The file mode after the patch (e.g. `0o100755`), if the Git header records one."""
+        ...
+    @property
+    def old_blob(self) -> str | None:
+        """The Git blob hash of the file before the patch, from the `index` line."""
+        ...
+    @property
+    def new_blob(self) -> str | None:
+        """The Git blob hash of the file after the patch, from the `index` line."""
+        ...
+    @property
+    def source_block(self) -> tuple[int, int, str] | None:
+        """Where the patch came from in the parsed input, as a
+        `(start_line, end_line, fence_info)` tuple of 1-based inclusive lines.
+        """
+        ...
+    @property
+    def binary_size(self) -> int | None:
+        """The size in bytes of the new content, if this is a `GIT binary patch`."""
+        ...
+    def to_string_with(
+        self, *, original_headers: bool = False, include_index_lines: bool = False
+    ) -> str:
+        """
+        Formats the patch as a unified diff.
+
+        Args:
+            original_headers (bool, optional): Keep each `@@` line exactly as parsed.
+                Defaults to False.
+            include_index_lines (bool, optional): Write the Git extended header lines.
+                Defaults to False.
+
+        Returns:
+            str: The formatted patch.
+        """
+        ...
     def __str__(self) -> str: ...
     def __repr__(self) -> str: ...
 
@@ -228,13 +373,25 @@ class HunkFailure:
         match.
         """
         ...
+    @property
+    def candidate_lines(self) -> list[list[str]] | None:
+        """The target lines of each rejected or tied fuzzy candidate, if the
+        error was a fuzzy match failure. Each list holds at most 50 lines.
+        """
+        ...
+    @property
+    def interference(self) -> int | None:
+        """The 1-based index of an earlier hunk whose fuzzy window overlapped this
+        hunk's region.
+        """
+        ...
     def __repr__(self) -> str: ...
 
 class HunkApplyStatus:
     """Status of a single hunk's application attempt."""
     @property
     def status(self) -> str:
-        """The status: 'Applied', 'Skipped', or 'Failed'."""
+        """The status: 'Applied', 'Skipped', 'AlreadyApplied', 'SkippedDuplicate', 'Failed', 'PartiallyApplied', or 'AppliedWithConflict'."""
         ...
     @property
     def location_start(self) -> int | None:
@@ -246,7 +403,7 @@ class HunkApplyStatus:
         ...
     @property
     def match_type(self) -> str | None:
-        """The type of match used ('Exact', 'ExactIgnoringWhitespace', or 'Fuzzy')."""
+        """The type of match used ('Exact', 'ExactIgnoringWhitespace', 'ExactIgnoringIndentation', 'ExactCanonicalized', 'ExactAtEof', or 'Fuzzy')."""
         ...
     @property
     def replaced_lines(self) -> list[str] | None:
@@ -254,7 +411,13 @@ class HunkApplyStatus:
         ...
     @property
     def error_reason(self) -> str | None:
-        """The error reason if the status is 'Failed'."""
+        """The error reason if the status is 'Failed' or 'AppliedWithConflict', or the first failed piece's reason if 'PartiallyApplied'."""
+        ...
+    @property
+    def interference(self) -> int | None:
+        """The 1-based index of a later hunk whose region this hunk's window
+        overlapped.
+        """
         ...
 
 class ApplyResult:
@@ -315,6 +478,24 @@ class PatchResult:
         is true).
         """
         ...
+    @property
+    def written(self) -> bool:
+        """Whether the patched content was written to disk (False for dry runs)."""
+        ...
+    @property
+    def reject_file(self) -> str | None:
+        """The reject file holding the hunks that failed to apply, if one was written."""
+        ...
+    @property
+    def removed(self) -> bool:
+        """Whether the patch removed the file from disk."""
+        ...
+    @property
+    def backup_path(self) -> str | None:
+        """Where the original content was copied before the file was changed,
+        if a backup was written.
+        """
+        ...
     def __bool__(self) -> bool: ...
 
 class BatchResult:
@@ -324,6 +505,10 @@ class BatchResult:
         """True if all patches in the batch were applied without hard errors."""
         ...
     @property
+    def all_applied_cleanly(self) -> bool:
+        """True if all patches were applied without hard errors and with every hunk applied."""
+        ...
+    @property
     def hard_failures(self) -> list[tuple[str, str]]:
         """A list of operations that resulted in a hard error."""
         ...
@@ -335,6 +520,23 @@ class BatchResult:
     def __getitem__(self, key: str) -> PatchResult | str: ...
     def __contains__(self, key: str) -> bool: ...
     def __bool__(self) -> bool: ...
+    def __str__(self) -> str: ...
+
+class MarkdownPatchScanner:
+    """Finds patches in Markdown that arrives in pieces, such as a streamed LLM response."""
+    def __init__(self) -> None: ...
+    def push_str(self, chunk: str) -> None:
+        """Adds the next piece of the input. It may end in the middle of a line."""
+        ...
+    def poll_patches(self) -> list[Patch]:
+        """Returns the patches of the code blocks closed since the last call."""
+        ...
+    def finish(self) -> list[Patch]:
+        """
+        Ends the input and returns the patches that have not been polled yet.
+        The scanner is reset and can be reused.
+        """
+        ...
 
 def detect_patch(diff: str) -> str:
     """
@@ -344,7 +546,7 @@ def detect_patch(diff: str) -> str:
         diff (str): The patch content.
 
     Returns:
-        str: 'Markdown', 'Unified', 'Conflict', or 'Unknown'.
+        str: 'Markdown', 'Unified', 'Context', 'Conflict', 'SearchReplace', 'OpenAi', or 'Unknown'.
     """
     ...
 
@@ -385,6 +587,42 @@ def parse_patches(diff: str) -> list[Patch]:
     """
     ...
 
+def parse_search_replace(diff: str) -> list[Patch]:
+    """
+    Parses Aider-style search/replace blocks (<<<<<<< SEARCH, =======, >>>>>>> REPLACE).
+
+    Args:
+        diff (str): The content containing the blocks, each preceded by a file path line.
+
+    Returns:
+        list[Patch]: A list of parsed patches, one per file.
+    """
+    ...
+
+def parse_openai_patch(diff: str) -> list[Patch]:
+    """
+    Parses OpenAI-style patches (*** Begin Patch, *** Update File:, ..., *** End Patch).
+
+    Args:
+        diff (str): The content containing the patch.
+
+    Returns:
+        list[Patch]: A list of parsed patches, one per file section.
+    """
+    ...
+
+def parse_context_diff(diff: str) -> list[Patch]:
+    """
+    Parses a context diff (the format produced by `diff -c`) into a list of patches.
+
+    Args:
+        diff (str): The context diff content.
+
+    Returns:
+        list[Patch]: A list of parsed patches, one per file.
+    """
+    ...
+
 def parse_conflict_markers(diff: str) -> list[Patch]:
     """
     Parses a string containing "Conflict Marker" style diffs (<<<<, ====, >>>>).
@@ -397,6 +635,34 @@ def parse_conflict_markers(diff: str) -> list[Patch]:
     """
     ...
 
+def parse_conflict_markers_for_file(diff: str, file_path: str) -> list[Patch]:
+    """
+    Parses conflict marker diffs (<<<<, ====, >>>>) as edits to the given file.
+
+    Args:
+        diff (str): The conflict marker content.
+        file_path (str): The path of the file the conflict block edits.
+
+    Returns:
+        list[Patch]: A list of parsed patches targeting `file_path`.
+    """
+    ...
+
+def coalesce_patches(patches: list[Patch]) -> list[Patch]:
+    """
+    Combines patches that target the same file into one patch per file.
+
+    Args:
+        patches (list[Patch]): The patches to combine.
+
+    Returns:
+        list[Patch]: One patch per distinct file path, in first-seen order.
+
+    Raises:
+        ValueError: If two patches for the same file have overlapping hunks.
+    """
+    ...
+
 def invert_patches(patches: list[Patch]) -> list[Patch]:
     """
     Inverts a list of patches (swaps additions and deletions).
@@ -409,6 +675,18 @@ def invert_patches(patches: list[Patch]) -> list[Patch]:
     """
     ...
 
+def describe_patches(patches: list[Patch]) -> str:
+    """
+    Describes a list of patches in plain language, one sentence per file.
+
+    Args:
+        patches (list[Patch]): The patches to describe.
+
+    Returns:
+        str: The description.
+    """
+    ...
+
 def create_unified_diff(
     file_path: str | os.PathLike[Any],
     old_text: str,
@@ -511,6 +789,25 @@ def apply_patch_to_content(
     """
     ...
 
+def preview_patch_to_content(
+    patch: Patch,
+    original: str | None = None,
+    *,
+    fuzz_factor: float = 0.7,
+) -> tuple[InMemoryResult, str]:
+    """
+    Applies a Patch object to a string content in memory and renders the change as a unified diff.
+
+    Args:
+        patch (Patch): The patch to apply.
+        original (str | None, optional): The original content. None for file creation. Defaults to None.
+        fuzz_factor (float, optional): Similarity threshold (0.0 to 1.0). Default is 0.7.
+
+    Returns:
+        tuple[InMemoryResult, str]: The result of the application and the diff of its changes.
+    """
+    ...
+
 def apply_patch_to_file(
     patch: Patch,
     target_dir: str | os.PathLike[Any],
@@ -552,3 +849,31 @@ def apply_patches_to_dir(
         BatchResult: The aggregated results of the applications.
     """
     ...
+
+def patches_from_dirs(
+    old_dir: str | os.PathLike[Any],
+    new_dir: str | os.PathLike[Any],
+    *,
+    context_len: int = 3,
+    ignore: list[str] | None = None,
+) -> list[Patch]:
+    """
+    Generates the patches that turn the files under `old_dir` into those under `new_dir`.
+
+    Files are paired by relative path. Changed files become modifications, files
+    only in `new_dir` become creations and files only in `old_dir` become deletions.
+    Symbolic links and binary files are skipped.
+
+    Args:
+        old_dir (str | os.PathLike): The directory holding the original tree.
+        new_dir (str | os.PathLike): The directory holding the modified tree.
+        context_len (int, optional): The number of context lines around changes. Default is 3.
+        ignore (list[str], optional): Glob patterns to leave out, such as "target/" or "*.log".
+
+    Returns:
+        list[Patch]: The patches, sorted by path.
+
+    Raises:
+        ApplyError: If a directory or file cannot be read.
+    """
+    ...
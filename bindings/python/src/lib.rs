@@ -52,6 +52,9 @@ impl PyHunk {
                 lines,
                 old_start_line,
                 new_start_line,
+                raw_header: None,
+                old_count: None,
+                new_count: None,
             },
         }
     }
@@ -74,6 +77,29 @@ impl PyHunk {
         self.inner.new_start_line
     }
 
+    #[getter]
+    /// The `@@` header line exactly as it appeared in the parsed diff, if any.
+    fn raw_header(&self) -> Option<String> {
+        self.inner.raw_header.clone()
+    }
+
+    #[getter]
+    /// The number of old lines declared by the `@@` header, if any.
+    fn old_count(&self) -> Option<usize> {
+        self.inner.old_count
+    }
+
+    #[getter]
+    /// The number of new lines declared by the `@@` header, if any.
+    fn new_count(&self) -> Option<usize> {
+        self.inner.new_count
+    }
+
+    /// Checks whether the hunk has fewer lines than its header declared.
+    fn is_truncated(&self) -> bool {
+        self.inner.is_truncated()
+    }
+
     #[getter]
     /// Extracts the context lines from the hunk (lines starting with ' ').
     fn context_lines(&self) -> Vec<String> {
@@ -137,6 +163,20 @@ impl PyHunk {
         }
     }
 
+    /// Creates a copy of this hunk with at most `max_context` context lines
+    /// around its changes. Context between changes is kept.
+    ///
+    /// Args:
+    ///     max_context (int): The number of context lines to keep on either side.
+    ///
+    /// Returns:
+    ///     Hunk: The trimmed hunk.
+    fn trim_context(&self, max_context: usize) -> Self {
+        Self {
+            inner: self.inner.trim_context(max_context),
+        }
+    }
+
     // --- Pythonic Dunder Methods ---
 
     fn __len__(&self) -> usize {
@@ -230,6 +270,12 @@ impl PyPatch {
                 file_path,
                 hunks: hunks.into_iter().map(|h| h.inner).collect(),
                 ends_with_newline,
+                index_lines: Vec::new(),
+                binary: None,
+                new_file_path: None,
+                deletes_file: false,
+                path_inferred: false,
+                source_block: None,
             },
         }
     }
@@ -278,6 +324,12 @@ impl PyPatch {
         self.inner.file_path = path;
     }
 
+    #[getter]
+    /// The path the file is renamed to, or `None` if the patch does not rename it.
+    fn new_file_path(&self) -> Option<PathBuf> {
+        self.inner.new_file_path.clone()
+    }
+
     #[getter]
     /// A list of hunks to be applied to the file.
     fn hunks(&self) -> Vec<PyHunk> {
@@ -294,6 +346,85 @@ impl PyPatch {
         self.inner.ends_with_newline
     }
 
+    #[getter]
+    /// Whether the patch deletes the file (its header names `/dev/null`).
+    fn deletes_file(&self) -> bool {
+        self.inner.deletes_file
+    }
+
+    #[getter]
+    /// Whether the file path was guessed from the prose before a block of bare hunks.
+    fn path_inferred(&self) -> bool {
+        self.inner.path_inferred
+    }
+
+    #[getter]
+    /// The Git extended header lines (`diff --git`, `index`, ...) that preceded the file headers.
+    fn index_lines(&self) -> Vec<String> {
+        self.inner.index_lines.clone()
+    }
+
+    #[getter]
+    /// The file mode before the patch (e.g. `0o100644`), if the Git header records one.
+    fn old_mode(&self) -> Option<u32> {
+        self.inner.old_mode()
+    }
+
+    #[getter]
+    /// The file mode after the patch (e.g. `0o100755`), if the Git header records one.
+    fn new_mode(&self) -> Option<u32> {
+        self.inner.new_mode()
+    }
+
+    #[getter]
+    /// The Git blob hash of the file before the patch, from the `index` line.
+    fn old_blob(&self) -> Option<String> {
+        self.inner.old_blob().map(str::to_string)
+    }
+
+    #[getter]
+    /// The Git blob hash of the file after the patch, from the `index` line.
+    fn new_blob(&self) -> Option<String> {
+        self.inner.new_blob().map(str::to_string)
+    }
+
+    #[getter]
+    /// Where the patch came from in the parsed input, as a
+    /// `(start_line, end_line, fence_info)` tuple of 1-based inclusive lines.
+    fn source_block(&self) -> Option<(usize, usize, String)> {
+        self.inner
+            .source_block
+            .as_ref()
+            .map(|o| (o.start_line, o.end_line, o.fence_info.clone()))
+    }
+
+    #[getter]
+    /// The size in bytes of the new content, if this is a `GIT binary patch`.
+    fn binary_size(&self) -> Option<usize> {
+        self.inner.binary.as_ref().map(|b| b.forward.size)
+    }
+
+    #[pyo3(signature = (*, original_headers=false, include_index_lines=false))]
+    /// Formats the patch as a unified diff.
+    ///
+    /// Args:
+    ///     original_headers (bool, optional): Keep each `@@` line exactly as parsed. Defaults to False.
+    ///     include_index_lines (bool, optional): Write the Git extended header lines. Defaults to False.
+    ///
+    /// Returns:
+    ///     str: The formatted patch.
+    fn to_string_with(&self, original_headers: bool, include_index_lines: bool) -> String {
+        let header = if original_headers {
+            ::mpatch::HeaderStyle::Original
+        } else {
+            ::mpatch::HeaderStyle::Recomputed
+        };
+        self.inner.to_string_with(::mpatch::PatchStyle {
+            header,
+            include_index_lines,
+        })
+    }
+
     #[getter]
     /// Checks if the patch represents a file creation.
     fn is_creation(&self) -> bool {
@@ -306,6 +437,12 @@ impl PyPatch {
         self.inner.is_deletion()
     }
 
+    #[getter]
+    /// Checks whether the patch came from a code block that was never closed.
+    fn is_possibly_truncated(&self) -> bool {
+        self.inner.is_possibly_truncated()
+    }
+
     /// Creates a new Patch that reverses the changes in this one.
     fn invert(&self) -> Self {
         Self {
@@ -313,6 +450,83 @@ impl PyPatch {
         }
     }
 
+    /// Removes every hunk that repeats the lines of an earlier hunk, keeping the first copy.
+    fn dedup_hunks(&mut self) {
+        self.inner.dedup_hunks();
+    }
+
+    /// Combines this patch with another patch for the same file.
+    ///
+    /// Hunks are sorted by their original start line; hunks without one go last.
+    ///
+    /// Args:
+    ///     other (Patch): The patch to merge into this one.
+    ///
+    /// Returns:
+    ///     Patch: The combined patch.
+    ///
+    /// Raises:
+    ///     ValueError: If the patches target different files or have overlapping hunks.
+    fn merge(&self, other: PyPatch) -> PyResult<Self> {
+        self.inner
+            .clone()
+            .merge(other.inner)
+            .map(|inner| Self { inner })
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+    }
+
+    /// Creates a copy of this patch where each hunk keeps at most
+    /// `max_context` context lines around its changes.
+    ///
+    /// Args:
+    ///     max_context (int): The number of context lines to keep on either side of each hunk.
+    ///
+    /// Returns:
+    ///     Patch: The patch with trimmed hunks.
+    fn with_context(&self, max_context: usize) -> Self {
+        Self {
+            inner: self.inner.with_context(max_context),
+        }
+    }
+
+    /// Checks the patch for signs that it is malformed.
+    ///
+    /// Returns:
+    ///     list[tuple[str, str]]: A (severity, message) pair per problem, where severity
+    ///         is 'warning' or 'error'. Empty for a well-formed patch.
+    fn validate(&self) -> Vec<(String, String)> {
+        self.inner
+            .validate()
+            .into_iter()
+            .map(|lint| {
+                let severity = match lint.severity() {
+                    ::mpatch::LintSeverity::Warning => "warning",
+                    ::mpatch::LintSeverity::Error => "error",
+                };
+                (severity.to_string(), lint.to_string())
+            })
+            .collect()
+    }
+
+    /// Checks whether two patches describe the same change.
+    ///
+    /// Args:
+    ///     other (Patch): The patch to compare against.
+    ///     base (str | None, optional): Content both patches apply to. When given, the
+    ///         patches are compared by the result they produce on it. Defaults to None.
+    ///
+    /// Returns:
+    ///     bool: True if the patches are semantically equal.
+    #[pyo3(signature = (other, base=None))]
+    fn semantic_eq(&self, other: &PyPatch, base: Option<&str>) -> bool {
+        self.inner.semantic_eq(&other.inner, base)
+    }
+
+    /// Returns a canonical, context-free summary of the patch's line changes.
+    fn change_signature(&self) -> String {
+        self.inner.change_signature()
+    }
+
     /// Applies the patch to a file on disk.
     ///
     /// Args:
@@ -444,22 +658,41 @@ impl PyHunkFailure {
         match &self.inner.reason {
             ::mpatch::HunkApplyError::ContextNotFound => "ContextNotFound".to_string(),
             ::mpatch::HunkApplyError::AmbiguousExactMatch(_) => "AmbiguousExactMatch".to_string(),
-            ::mpatch::HunkApplyError::AmbiguousFuzzyMatch(_) => "AmbiguousFuzzyMatch".to_string(),
+            ::mpatch::HunkApplyError::AmbiguousSurroundings { .. } => {
+                "AmbiguousSurroundings".to_string()
+            }
+            ::mpatch::HunkApplyError::AmbiguousFuzzyMatch { .. } => {
+                "AmbiguousFuzzyMatch".to_string()
+            }
             ::mpatch::HunkApplyError::FuzzyMatchBelowThreshold { .. } => {
                 "FuzzyMatchBelowThreshold".to_string()
             }
+            ::mpatch::HunkApplyError::LocationOverrideMismatch { .. } => {
+                "LocationOverrideMismatch".to_string()
+            }
+            ::mpatch::HunkApplyError::SearchBudgetExceeded { .. } => {
+                "SearchBudgetExceeded".to_string()
+            }
+            ::mpatch::HunkApplyError::BinaryPatch => "BinaryPatch".to_string(),
+            ::mpatch::HunkApplyError::OutsideRegion { .. } => "OutsideRegion".to_string(),
+            ::mpatch::HunkApplyError::InsufficientContext { .. } => {
+                "InsufficientContext".to_string()
+            }
+            ::mpatch::HunkApplyError::TruncatedHunk { .. } => "TruncatedHunk".to_string(),
         }
     }
 
     #[getter]
     /// The best score found, if the error was a fuzzy match failure.
     fn best_score(&self) -> Option<f64> {
-        if let ::mpatch::HunkApplyError::FuzzyMatchBelowThreshold { best_score, .. } =
-            &self.inner.reason
-        {
-            Some(*best_score)
-        } else {
-            None
+        match &self.inner.reason {
+            ::mpatch::HunkApplyError::FuzzyMatchBelowThreshold { best_score, .. } => {
+                Some(*best_score)
+            }
+            ::mpatch::HunkApplyError::SearchBudgetExceeded {
+                best_score_so_far, ..
+            } => *best_score_so_far,
+            _ => None,
         }
     }
 
@@ -480,14 +713,37 @@ impl PyHunkFailure {
     /// The ambiguous line indices found, if the error was due to an ambiguous match.
     fn ambiguous_matches(&self) -> Option<Vec<usize>> {
         match &self.inner.reason {
-            ::mpatch::HunkApplyError::AmbiguousExactMatch(lines) => Some(lines.clone()),
-            ::mpatch::HunkApplyError::AmbiguousFuzzyMatch(locs) => {
-                Some(locs.iter().map(|(start, _)| *start).collect())
+            ::mpatch::HunkApplyError::AmbiguousExactMatch(lines)
+            | ::mpatch::HunkApplyError::AmbiguousSurroundings {
+                candidates: lines, ..
+            } => Some(lines.clone()),
+            ::mpatch::HunkApplyError::AmbiguousFuzzyMatch { locations, .. } => {
+                Some(locations.iter().map(|(start, _)| *start).collect())
             }
             _ => None,
         }
     }
 
+    #[getter]
+    /// The target lines of each rejected or tied fuzzy candidate, if the error was a fuzzy match failure.
+    fn candidate_lines(&self) -> Option<Vec<Vec<String>>> {
+        match &self.inner.reason {
+            ::mpatch::HunkApplyError::FuzzyMatchBelowThreshold {
+                candidate_lines, ..
+            } => Some(vec![candidate_lines.clone()]),
+            ::mpatch::HunkApplyError::AmbiguousFuzzyMatch {
+                candidate_lines, ..
+            } => Some(candidate_lines.clone()),
+            _ => None,
+        }
+    }
+
+    #[getter]
+    /// The 1-based index of an earlier hunk whose fuzzy window overlapped this hunk's region.
+    fn interference(&self) -> Option<usize> {
+        self.inner.interference
+    }
+
     fn __repr__(&self) -> String {
         format!(
             "<HunkFailure hunk_index={} reason=\"{}\">",
@@ -506,6 +762,7 @@ pub struct PyHunkApplyStatus {
     match_type: Option<String>,
     replaced_lines: Option<Vec<String>>,
     error_reason: Option<String>,
+    interference: Option<usize>,
 }
 
 #[pymethods]
@@ -529,7 +786,7 @@ impl PyHunkApplyStatus {
     }
 
     #[getter]
-    /// The type of match used ('Exact', 'ExactIgnoringWhitespace', or 'Fuzzy').
+    /// The type of match used ('Exact', 'ExactIgnoringWhitespace', 'ExactIgnoringIndentation', 'ExactCanonicalized', or 'Fuzzy').
     fn match_type(&self) -> Option<String> {
         self.match_type.clone()
     }
@@ -546,6 +803,12 @@ impl PyHunkApplyStatus {
         self.error_reason.clone()
     }
 
+    #[getter]
+    /// The 1-based index of a later hunk whose region this hunk's window overlapped.
+    fn interference(&self) -> Option<usize> {
+        self.interference
+    }
+
     fn __repr__(&self) -> String {
         format!("<HunkApplyStatus status=\"{}\">", self.status)
     }
@@ -605,10 +868,15 @@ impl PyApplyResult {
                     location,
                     match_type,
                     replaced_lines,
+                    interference,
+                    ..
                 } => {
                     let match_str = match match_type {
                         ::mpatch::MatchType::Exact => "Exact",
                         ::mpatch::MatchType::ExactIgnoringWhitespace => "ExactIgnoringWhitespace",
+                        ::mpatch::MatchType::ExactIgnoringIndentation => "ExactIgnoringIndentation",
+                        ::mpatch::MatchType::ExactCanonicalized => "ExactCanonicalized",
+                        ::mpatch::MatchType::ExactAtEof => "ExactAtEof",
                         ::mpatch::MatchType::Fuzzy { .. } => "Fuzzy",
                     };
                     PyHunkApplyStatus {
@@ -618,6 +886,7 @@ impl PyApplyResult {
                         match_type: Some(match_str.to_string()),
                         replaced_lines: Some(replaced_lines.clone()),
                         error_reason: None,
+                        interference: *interference,
                     }
                 }
                 ::mpatch::HunkApplyStatus::SkippedNoChanges => PyHunkApplyStatus {
@@ -627,6 +896,25 @@ impl PyApplyResult {
                     match_type: None,
                     replaced_lines: None,
                     error_reason: None,
+                    interference: None,
+                },
+                ::mpatch::HunkApplyStatus::AlreadyApplied { location } => PyHunkApplyStatus {
+                    status: "AlreadyApplied".to_string(),
+                    location_start: Some(location.start_index),
+                    location_length: Some(location.length),
+                    match_type: None,
+                    replaced_lines: None,
+                    error_reason: None,
+                    interference: None,
+                },
+                ::mpatch::HunkApplyStatus::SkippedDuplicate { .. } => PyHunkApplyStatus {
+                    status: "SkippedDuplicate".to_string(),
+                    location_start: None,
+                    location_length: None,
+                    match_type: None,
+                    replaced_lines: None,
+                    error_reason: None,
+                    interference: None,
                 },
                 ::mpatch::HunkApplyStatus::Failed(err) => PyHunkApplyStatus {
                     status: "Failed".to_string(),
@@ -635,6 +923,30 @@ impl PyApplyResult {
                     match_type: None,
                     replaced_lines: None,
                     error_reason: Some(err.to_string()),
+                    interference: None,
+                },
+                ::mpatch::HunkApplyStatus::AppliedWithConflict { location, reason } => {
+                    PyHunkApplyStatus {
+                        status: "AppliedWithConflict".to_string(),
+                        location_start: Some(location.start_index),
+                        location_length: Some(location.length),
+                        match_type: None,
+                        replaced_lines: None,
+                        error_reason: Some(reason.to_string()),
+                        interference: None,
+                    }
+                }
+                ::mpatch::HunkApplyStatus::PartiallyApplied { subhunks } => PyHunkApplyStatus {
+                    status: "PartiallyApplied".to_string(),
+                    location_start: None,
+                    location_length: None,
+                    match_type: None,
+                    replaced_lines: None,
+                    error_reason: subhunks.iter().find_map(|sub| match sub {
+                        ::mpatch::HunkApplyStatus::Failed(err) => Some(err.to_string()),
+                        _ => None,
+                    }),
+                    interference: None,
                 },
             })
             .collect()
@@ -747,6 +1059,30 @@ impl PyPatchResult {
         self.inner.diff.clone()
     }
 
+    #[getter]
+    /// Whether the patched content was written to disk (False for dry runs).
+    fn written(&self) -> bool {
+        self.inner.written
+    }
+
+    #[getter]
+    /// The reject file holding the hunks that failed to apply, if one was written.
+    fn reject_file(&self) -> Option<PathBuf> {
+        self.inner.reject_file.clone()
+    }
+
+    #[getter]
+    /// Whether the patch removed the file from disk.
+    fn removed(&self) -> bool {
+        self.inner.removed
+    }
+
+    #[getter]
+    /// Where the original content was copied before the file was changed, if a backup was written.
+    fn backup_path(&self) -> Option<PathBuf> {
+        self.inner.backup_path.clone()
+    }
+
     fn __bool__(&self) -> bool {
         self.inner.report.all_applied_cleanly()
     }
@@ -760,6 +1096,44 @@ impl PyPatchResult {
     }
 }
 
+/// Finds patches in Markdown that arrives in pieces, such as a streamed LLM response.
+#[pyclass(module = "mpatch", name = "MarkdownPatchScanner")]
+pub struct PyMarkdownPatchScanner {
+    inner: ::mpatch::MarkdownPatchScanner,
+}
+
+#[pymethods]
+impl PyMarkdownPatchScanner {
+    #[new]
+    fn py_new() -> Self {
+        PyMarkdownPatchScanner {
+            inner: ::mpatch::MarkdownPatchScanner::new(),
+        }
+    }
+
+    /// Adds the next piece of the input. It may end in the middle of a line.
+    fn push_str(&mut self, chunk: &str) {
+        self.inner.push_str(chunk);
+    }
+
+    /// Returns the patches of the code blocks closed since the last call.
+    fn poll_patches(&mut self) -> PyResult<Vec<PyPatch>> {
+        self.inner
+            .poll_patches()
+            .map_err(map_parse_err)
+            .map(|patches| patches.into_iter().map(|p| PyPatch { inner: p }).collect())
+    }
+
+    /// Ends the input and returns the patches that have not been polled yet.
+    /// The scanner is reset and can be reused.
+    fn finish(&mut self) -> PyResult<Vec<PyPatch>> {
+        std::mem::take(&mut self.inner)
+            .finish()
+            .map_err(map_parse_err)
+            .map(|patches| patches.into_iter().map(|p| PyPatch { inner: p }).collect())
+    }
+}
+
 /// Result from batch-applying patches to a directory on disk.
 #[pyclass(module = "mpatch", name = "BatchResult")]
 pub struct PyBatchResult {
@@ -774,6 +1148,12 @@ impl PyBatchResult {
         self.inner.all_succeeded()
     }
 
+    #[getter]
+    /// True if all patches were applied without hard errors and with every hunk applied.
+    fn all_applied_cleanly(&self) -> bool {
+        self.inner.all_applied_cleanly()
+    }
+
     #[getter]
     /// A list of operations that resulted in a hard error.
     fn hard_failures(&self) -> Vec<(String, String)> {
@@ -845,6 +1225,10 @@ impl PyBatchResult {
         self.inner.results.iter().any(|(path, _)| path == &target)
     }
 
+    fn __str__(&self) -> String {
+        self.inner.to_string()
+    }
+
     fn __repr__(&self) -> String {
         format!(
             "<BatchResult all_succeeded={} hard_failures={}>",
@@ -864,12 +1248,15 @@ impl PyBatchResult {
 ///     diff (str): The patch content.
 ///
 /// Returns:
-///     str: 'Markdown', 'Unified', 'Conflict', or 'Unknown'.
+///     str: 'Markdown', 'Unified', 'Context', 'Conflict', 'SearchReplace', 'OpenAi', or 'Unknown'.
 fn detect_patch(diff: &str) -> String {
     match ::mpatch::detect_patch(diff) {
         ::mpatch::PatchFormat::Markdown => "Markdown".to_string(),
         ::mpatch::PatchFormat::Unified => "Unified".to_string(),
+        ::mpatch::PatchFormat::Context => "Context".to_string(),
         ::mpatch::PatchFormat::Conflict => "Conflict".to_string(),
+        ::mpatch::PatchFormat::SearchReplace => "SearchReplace".to_string(),
+        ::mpatch::PatchFormat::OpenAi => "OpenAi".to_string(),
         ::mpatch::PatchFormat::Unknown => "Unknown".to_string(),
         _ => "Unknown".to_string(),
     }
@@ -929,6 +1316,24 @@ fn parse_patches(py: Python<'_>, diff: &str) -> PyResult<Vec<PyPatch>> {
     })
 }
 
+#[pyfunction]
+#[pyo3(signature = (diff))]
+/// Parses a context diff (the format produced by `diff -c`) into a list of patches.
+///
+/// Args:
+///     diff (str): The context diff content.
+///
+/// Returns:
+///     list[Patch]: A list of parsed patches, one per file.
+fn parse_context_diff(py: Python<'_>, diff: &str) -> PyResult<Vec<PyPatch>> {
+    let diff_str = diff.to_string();
+    py.detach(move || {
+        ::mpatch::parse_context_diff(&diff_str)
+            .map_err(map_parse_err)
+            .map(|patches| patches.into_iter().map(|p| PyPatch { inner: p }).collect())
+    })
+}
+
 #[pyfunction]
 #[pyo3(signature = (diff))]
 /// Parses a string containing "Conflict Marker" style diffs (<<<<, ====, >>>>).
@@ -948,6 +1353,62 @@ fn parse_conflict_markers(py: Python<'_>, diff: &str) -> Vec<PyPatch> {
     })
 }
 
+#[pyfunction]
+#[pyo3(signature = (diff, file_path))]
+/// Parses conflict marker diffs (<<<<, ====, >>>>) as edits to the given file.
+///
+/// Args:
+///     diff (str): The conflict marker content.
+///     file_path (str): The path of the file the conflict block edits.
+///
+/// Returns:
+///     list[Patch]: A list of parsed patches targeting `file_path`.
+fn parse_conflict_markers_for_file(py: Python<'_>, diff: &str, file_path: PathBuf) -> Vec<PyPatch> {
+    let diff_str = diff.to_string();
+    py.detach(move || {
+        ::mpatch::parse_conflict_markers_for_file(&diff_str, file_path)
+            .into_iter()
+            .map(|p| PyPatch { inner: p })
+            .collect()
+    })
+}
+
+#[pyfunction]
+#[pyo3(signature = (diff))]
+/// Parses Aider-style search/replace blocks (<<<<<<< SEARCH, =======, >>>>>>> REPLACE).
+///
+/// Args:
+///     diff (str): The content containing the blocks, each preceded by a file path line.
+///
+/// Returns:
+///     list[Patch]: A list of parsed patches, one per file.
+fn parse_search_replace(py: Python<'_>, diff: &str) -> PyResult<Vec<PyPatch>> {
+    let diff_str = diff.to_string();
+    py.detach(move || {
+        ::mpatch::parse_search_replace(&diff_str)
+            .map_err(map_parse_err)
+            .map(|patches| patches.into_iter().map(|p| PyPatch { inner: p }).collect())
+    })
+}
+
+#[pyfunction]
+#[pyo3(signature = (diff))]
+/// Parses OpenAI-style patches (*** Begin Patch, *** Update File:, ..., *** End Patch).
+///
+/// Args:
+///     diff (str): The content containing the patch.
+///
+/// Returns:
+///     list[Patch]: A list of parsed patches, one per file section.
+fn parse_openai_patch(py: Python<'_>, diff: &str) -> PyResult<Vec<PyPatch>> {
+    let diff_str = diff.to_string();
+    py.detach(move || {
+        ::mpatch::parse_openai_patch(&diff_str)
+            .map_err(map_parse_err)
+            .map(|patches| patches.into_iter().map(|p| PyPatch { inner: p }).collect())
+    })
+}
+
 #[pyfunction]
 #[pyo3(signature = (patches))]
 /// Inverts a list of patches (swaps additions and deletions).
@@ -961,6 +1422,38 @@ fn invert_patches(patches: Vec<PyPatch>) -> Vec<PyPatch> {
     patches.into_iter().map(|p| p.invert()).collect()
 }
 
+#[pyfunction]
+#[pyo3(signature = (patches))]
+/// Combines patches that target the same file into one patch per file.
+///
+/// Args:
+///     patches (list[Patch]): The patches to combine.
+///
+/// Returns:
+///     list[Patch]: One patch per distinct file path, in first-seen order.
+///
+/// Raises:
+///     ValueError: If two patches for the same file have overlapping hunks.
+fn coalesce_patches(patches: Vec<PyPatch>) -> PyResult<Vec<PyPatch>> {
+    ::mpatch::coalesce_patches(patches.into_iter().map(|p| p.inner).collect())
+        .map(|patches| patches.into_iter().map(|p| PyPatch { inner: p }).collect())
+        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+}
+
+#[pyfunction]
+#[pyo3(signature = (patches))]
+/// Describes a list of patches in plain language, one sentence per file.
+///
+/// Args:
+///     patches (list[Patch]): The patches to describe.
+///
+/// Returns:
+///     str: The description.
+fn describe_patches(patches: Vec<PyPatch>) -> String {
+    let patches: Vec<_> = patches.into_iter().map(|p| p.inner).collect();
+    ::mpatch::describe_patches(&patches)
+}
+
 #[pyfunction]
 #[pyo3(signature = (file_path, old_text, new_text, *, context_len=3))]
 /// Creates a unified diff string by comparing two texts.
@@ -1025,7 +1518,8 @@ fn patch_content(
     let options = ApplyOptions::builder()
         .fuzz_factor(fuzz_factor)
         .dry_run(dry_run)
-        .build();
+        .try_build()
+        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
 
     let diff_str = diff.to_string();
     let orig_str = original.map(String::from);
@@ -1058,7 +1552,8 @@ fn apply_directory(
     let options = ApplyOptions::builder()
         .fuzz_factor(fuzz_factor)
         .dry_run(dry_run)
-        .build();
+        .try_build()
+        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
 
     let diff_str = diff.to_string();
 
@@ -1105,6 +1600,39 @@ fn apply_patch_to_content(
     PyInMemoryResult { inner: res }
 }
 
+#[pyfunction]
+#[pyo3(signature = (patch, original=None, *, fuzz_factor=0.7))]
+/// Applies a Patch object to a string content in memory and renders the change as a unified diff.
+///
+/// Args:
+///     patch (Patch): The patch to apply.
+///     original (str | None, optional): The original content. None for file creation. Defaults to None.
+///     fuzz_factor (float, optional): Similarity threshold (0.0 to 1.0). Default is 0.7.
+///
+/// Returns:
+///     tuple[InMemoryResult, str]: The result of the application and the diff of its changes.
+fn preview_patch_to_content(
+    py: Python<'_>,
+    patch: &PyPatch,
+    original: Option<&str>,
+    fuzz_factor: f32,
+) -> (PyInMemoryResult, String) {
+    let options = ApplyOptions::builder().fuzz_factor(fuzz_factor).build();
+
+    let patch_inner = patch.inner.clone();
+    let original_str = original.map(String::from);
+
+    let preview = py.detach(move || {
+        ::mpatch::preview_patch_to_content(&patch_inner, original_str.as_deref(), &options)
+    });
+
+    let result = ::mpatch::InMemoryResult {
+        new_content: preview.new_content,
+        report: preview.report,
+    };
+    (PyInMemoryResult { inner: result }, preview.diff)
+}
+
 #[pyfunction]
 #[pyo3(signature = (patch, target_dir, *, fuzz_factor=0.7, dry_run=false))]
 /// Applies a Patch object to a file on disk.
@@ -1127,7 +1655,8 @@ fn apply_patch_to_file(
     let options = ApplyOptions::builder()
         .fuzz_factor(fuzz_factor)
         .dry_run(dry_run)
-        .build();
+        .try_build()
+        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
 
     let patch_inner = patch.inner.clone();
 
@@ -1158,24 +1687,63 @@ fn apply_patch_to_file(
 ///
 /// Returns:
 ///     BatchResult: The aggregated results of the applications.
+///
+/// Raises:
+///     ValueError: If `fuzz_factor` is not between 0.0 and 1.0.
 fn apply_patches_to_dir(
     py: Python<'_>,
     patches: Vec<PyPatch>,
     target_dir: PathBuf,
     fuzz_factor: f32,
     dry_run: bool,
-) -> PyBatchResult {
+) -> PyResult<PyBatchResult> {
     let options = ApplyOptions::builder()
         .fuzz_factor(fuzz_factor)
         .dry_run(dry_run)
-        .build();
+        .try_build()
+        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
 
     let patches_inner: Vec<::mpatch::Patch> = patches.into_iter().map(|p| p.inner).collect();
 
     let result =
         py.detach(move || ::mpatch::apply_patches_to_dir(&patches_inner, &target_dir, options));
 
-    PyBatchResult { inner: result }
+    Ok(PyBatchResult { inner: result })
+}
+
+#[pyfunction]
+#[pyo3(signature = (old_dir, new_dir, *, context_len=3, ignore=None))]
+/// Generates the patches that turn the files under `old_dir` into those under `new_dir`.
+///
+/// Files are paired by relative path. Changed files become modifications, files
+/// only in `new_dir` become creations and files only in `old_dir` become deletions.
+/// Symbolic links and binary files are skipped.
+///
+/// Args:
+///     old_dir (str | os.PathLike): The directory holding the original tree.
+///     new_dir (str | os.PathLike): The directory holding the modified tree.
+///     context_len (int, optional): The number of context lines around changes. Default is 3.
+///     ignore (list[str], optional): Glob patterns to leave out, such as "target/" or "*.log".
+///
+/// Returns:
+///     list[Patch]: The patches, sorted by path.
+///
+/// Raises:
+///     ApplyError: If a directory or file cannot be read.
+fn patches_from_dirs(
+    py: Python<'_>,
+    old_dir: PathBuf,
+    new_dir: PathBuf,
+    context_len: usize,
+    ignore: Option<Vec<String>>,
+) -> PyResult<Vec<PyPatch>> {
+    let options = ::mpatch::DirDiffOptions {
+        ignore: ignore.unwrap_or_default(),
+        ..Default::default()
+    };
+    py.detach(move || ::mpatch::patches_from_dirs(&old_dir, &new_dir, context_len, options))
+        .map(|patches| patches.into_iter().map(|p| PyPatch { inner: p }).collect())
+        .map_err(|e| ApplyError::new_err(e.to_string()))
 }
 
 #[pymodule]
@@ -1193,6 +1761,7 @@ fn mpatch(py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<PyInMemoryResult>()?;
     m.add_class::<PyPatchResult>()?;
     m.add_class::<PyBatchResult>()?;
+    m.add_class::<PyMarkdownPatchScanner>()?;
 
     m.add_function(wrap_pyfunction!(patch_content, m)?)?;
     m.add_function(wrap_pyfunction!(apply_directory, m)?)?;
@@ -1200,12 +1769,20 @@ fn mpatch(py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(parse_auto, m)?)?;
     m.add_function(wrap_pyfunction!(parse_diffs, m)?)?;
     m.add_function(wrap_pyfunction!(parse_patches, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_context_diff, m)?)?;
     m.add_function(wrap_pyfunction!(parse_conflict_markers, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_conflict_markers_for_file, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_search_replace, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_openai_patch, m)?)?;
     m.add_function(wrap_pyfunction!(invert_patches, m)?)?;
+    m.add_function(wrap_pyfunction!(coalesce_patches, m)?)?;
+    m.add_function(wrap_pyfunction!(describe_patches, m)?)?;
     m.add_function(wrap_pyfunction!(create_unified_diff, m)?)?;
     m.add_function(wrap_pyfunction!(apply_patch_to_content, m)?)?;
+    m.add_function(wrap_pyfunction!(preview_patch_to_content, m)?)?;
     m.add_function(wrap_pyfunction!(apply_patch_to_file, m)?)?;
     m.add_function(wrap_pyfunction!(apply_patches_to_dir, m)?)?;
+    m.add_function(wrap_pyfunction!(patches_from_dirs, m)?)?;
 
     // Add library version
     m.add("VERSION", env!("CARGO_PKG_VERSION"))?;
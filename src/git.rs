@@ -0,0 +1,317 @@
+//! Recording an applied patch set as a Git commit.
+//!
+//! This module is only available with the `git` feature enabled. It takes the
+//! [`BatchResult`] of [`apply_patches_to_dir()`](crate::apply_patches_to_dir)
+//! and turns exactly the files that batch touched into a single commit, using
+//! [`git2`] so that no `git` executable is required.
+
+use crate::{BatchResult, Patch};
+use git2::{Oid, Repository, Signature};
+use log::{debug, info};
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Options for [`commit_batch()`].
+///
+/// # Examples
+///
+/// ```
+/// use mpatch::CommitOptions;
+///
+/// let options = CommitOptions {
+///     allow_partial: false,
+///     author_name: Some("Patch Bot".to_string()),
+///     author_email: Some("bot@example.com".to_string()),
+/// };
+/// assert!(!options.allow_partial);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommitOptions {
+    /// Commit even if some patches in the batch failed or were only partially
+    /// applied. Only the files that were actually written are staged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mpatch::CommitOptions;
+    /// let options = CommitOptions { allow_partial: true, ..Default::default() };
+    /// assert!(options.allow_partial);
+    /// ```
+    pub allow_partial: bool,
+    /// The author name. If either this or [`author_email`](Self::author_email)
+    /// is `None`, the repository's configured `user.name` and `user.email` are
+    /// used instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mpatch::CommitOptions;
+    /// let options = CommitOptions { author_name: Some("Patch Bot".to_string()), ..Default::default() };
+    /// assert_eq!(options.author_name.as_deref(), Some("Patch Bot"));
+    /// ```
+    pub author_name: Option<String>,
+    /// The author email. See [`author_name`](Self::author_name).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mpatch::CommitOptions;
+    /// let options = CommitOptions { author_email: Some("bot@example.com".to_string()), ..Default::default() };
+    /// assert_eq!(options.author_email.as_deref(), Some("bot@example.com"));
+    /// ```
+    pub author_email: Option<String>,
+}
+
+/// Represents errors that can occur while committing a batch with [`commit_batch()`].
+///
+/// # Examples
+///
+/// ```
+/// use mpatch::GitCommitError;
+///
+/// let err = GitCommitError::NothingToCommit;
+/// assert_eq!(err.to_string(), "No files were written by the batch; nothing to commit");
+/// ```
+#[derive(Error, Debug)]
+pub enum GitCommitError {
+    /// Some patches in the batch failed or were only partially applied, and
+    /// [`CommitOptions::allow_partial`] was not set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mpatch::GitCommitError;
+    /// use std::path::PathBuf;
+    /// let err = GitCommitError::PartialBatch { failed: vec![PathBuf::from("src/main.rs")] };
+    /// ```
+    #[error("Refusing to commit: {} patch(es) did not apply cleanly: {failed:?}", failed.len())]
+    PartialBatch {
+        /// The files whose patches failed or were only partially applied.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use mpatch::GitCommitError;
+        /// use std::path::PathBuf;
+        /// let err = GitCommitError::PartialBatch { failed: vec![PathBuf::from("a.txt")] };
+        /// if let GitCommitError::PartialBatch { failed } = err {
+        ///     assert_eq!(failed.len(), 1);
+        /// }
+        /// ```
+        failed: Vec<PathBuf>,
+    },
+    /// The batch and the patch slice do not describe the same files.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mpatch::GitCommitError;
+    /// let err = GitCommitError::MismatchedBatch;
+    /// ```
+    #[error("The batch result does not correspond to the given patches")]
+    MismatchedBatch,
+    /// No file was written by the batch (for example, it was a dry run).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mpatch::GitCommitError;
+    /// let err = GitCommitError::NothingToCommit;
+    /// ```
+    #[error("No files were written by the batch; nothing to commit")]
+    NothingToCommit,
+    /// A patched file is not inside the repository's working directory, or the
+    /// repository is bare.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mpatch::GitCommitError;
+    /// use std::path::PathBuf;
+    /// let err = GitCommitError::OutsideWorkdir(PathBuf::from("/tmp/elsewhere.txt"));
+    /// ```
+    #[error("Path '{0}' is not inside the repository's working directory")]
+    OutsideWorkdir(PathBuf),
+    /// An error reported by libgit2.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mpatch::GitCommitError;
+    /// let err = GitCommitError::Git(git2::Error::from_str("boom"));
+    /// ```
+    #[error("Git error: {0}")]
+    Git(#[from] git2::Error),
+    /// An I/O error occurred while resolving paths.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mpatch::GitCommitError;
+    /// use std::io;
+    /// let err = GitCommitError::Io(io::Error::new(io::ErrorKind::Other, "disk full"));
+    /// ```
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Commits the files changed by an applied batch to a Git repository.
+///
+/// `batch` must be the result of applying `patches` to `repo_path` with
+/// [`apply_patches_to_dir()`](crate::apply_patches_to_dir). Exactly the files
+/// the batch wrote are staged: files that exist afterwards are added, and files
+/// the batch deleted are removed from the index. Both paths of a rename are
+/// staged. Dry-run results and patches
+/// held back by [`ApplyOptions::all_or_nothing`](crate::ApplyOptions::all_or_nothing)
+/// are never staged. Other changes in the working tree are left alone.
+///
+/// `repo_path` may be the repository root or any directory inside it. The new
+/// commit is created on `HEAD`, which may be an unborn branch.
+///
+/// # Arguments
+///
+/// * `repo_path` - The directory the batch was applied to.
+/// * `batch` - The result of applying `patches`.
+/// * `patches` - The patches that produced `batch`, in the same order.
+/// * `message` - The commit message.
+/// * `options` - Whether to allow partial batches, and the commit author.
+///
+/// # Returns
+///
+/// The [`Oid`] of the new commit.
+///
+/// # Errors
+///
+/// Returns [`GitCommitError::PartialBatch`] if any patch failed or applied only
+/// partially and [`CommitOptions::allow_partial`] is not set,
+/// [`GitCommitError::NothingToCommit`] if no file was written, and
+/// [`GitCommitError::Git`] for errors from libgit2, such as `repo_path` not
+/// being inside a repository or no author being configured.
+///
+/// # Examples
+///
+/// ````rust
+/// use mpatch::{apply_patches_to_dir, commit_batch, parse_auto, ApplyOptions, CommitOptions};
+/// use std::fs;
+/// # use tempfile::tempdir;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let dir = tempdir()?;
+/// let repo = git2::Repository::init(dir.path())?;
+/// fs::write(dir.path().join("hello.txt"), "hello\n")?;
+///
+/// let diff = r#"
+/// ```diff
+/// --- a/hello.txt
+/// +++ b/hello.txt
+/// @@ -1 +1 @@
+/// -hello
+/// +goodbye
+/// ```
+/// "#;
+/// let patches = parse_auto(diff)?;
+/// let batch = apply_patches_to_dir(&patches, dir.path(), ApplyOptions::new());
+///
+/// let options = CommitOptions {
+///     author_name: Some("Patch Bot".to_string()),
+///     author_email: Some("bot@example.com".to_string()),
+///     ..Default::default()
+/// };
+/// let oid = commit_batch(dir.path(), &batch, &patches, "Say goodbye", options)?;
+///
+/// let commit = repo.find_commit(oid)?;
+/// assert_eq!(commit.message(), Some("Say goodbye"));
+/// assert_eq!(commit.author().name(), Some("Patch Bot"));
+/// # Ok(())
+/// # }
+/// ````
+pub fn commit_batch(
+    repo_path: &Path,
+    batch: &BatchResult,
+    patches: &[Patch],
+    message: &str,
+    options: CommitOptions,
+) -> Result<Oid, GitCommitError> {
+    if batch.results.len() != patches.len()
+        || batch.results.iter().zip(patches).any(|((path, _), patch)| {
+            *path != patch.file_path && path.as_path() != patch.destination_path()
+        })
+    {
+        return Err(GitCommitError::MismatchedBatch);
+    }
+
+    let failed: Vec<PathBuf> = batch
+        .results
+        .iter()
+        .filter(|(_, result)| {
+            result
+                .as_ref()
+                .map_or(true, |r| !r.report.all_applied_cleanly())
+        })
+        .map(|(path, _)| path.clone())
+        .collect();
+    if !failed.is_empty() {
+        if !options.allow_partial {
+            return Err(GitCommitError::PartialBatch { failed });
+        }
+        debug!(
+            "Committing a partial batch; {} patch(es) failed.",
+            failed.len()
+        );
+    }
+
+    // A rename touches both of its paths, so both are staged.
+    let written: Vec<&Path> = batch
+        .results
+        .iter()
+        .zip(patches)
+        .filter(|((_, result), _)| result.as_ref().is_ok_and(|r| r.written))
+        .flat_map(|((path, _), patch)| match &patch.new_file_path {
+            Some(new_path) => vec![patch.file_path.as_path(), new_path.as_path()],
+            None => vec![path.as_path()],
+        })
+        .collect();
+    if written.is_empty() {
+        return Err(GitCommitError::NothingToCommit);
+    }
+
+    let repo = Repository::discover(repo_path)?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| GitCommitError::OutsideWorkdir(repo_path.to_path_buf()))?;
+    let workdir = fs::canonicalize(workdir)?;
+    let base = fs::canonicalize(repo_path)?;
+    let prefix = base
+        .strip_prefix(&workdir)
+        .map_err(|_| GitCommitError::OutsideWorkdir(base.clone()))?
+        .to_path_buf();
+
+    let mut index = repo.index()?;
+    for path in written {
+        let repo_relative = prefix.join(path);
+        if workdir.join(&repo_relative).exists() {
+            debug!("  Staging '{}'", repo_relative.display());
+            index.add_path(&repo_relative)?;
+        } else {
+            debug!("  Staging removal of '{}'", repo_relative.display());
+            index.remove_path(&repo_relative)?;
+        }
+    }
+    index.write()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+
+    let author = match (&options.author_name, &options.author_email) {
+        (Some(name), Some(email)) => Signature::now(name, email)?,
+        _ => repo.signature()?,
+    };
+    let parent = match repo.head() {
+        Ok(head) => Some(head.peel_to_commit()?),
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch => None,
+        Err(e) => return Err(e.into()),
+    };
+    let parents: Vec<&git2::Commit> = parent.iter().collect();
+
+    let oid = repo.commit(Some("HEAD"), &author, &author, message, &tree, &parents)?;
+    info!("Created commit {}", oid);
+    Ok(oid)
+}
@@ -2,37 +2,72 @@ use anyhow::{anyhow, Context, Result};
 use clap::Parser;
 use colored::Colorize;
 use env_logger::Builder;
-use log::{error, info, warn, Level, LevelFilter};
-use mpatch::{apply_patches_to_dir, parse_auto, Patch};
-use std::fmt::Write as FmtWrite;
+use log::{debug, error, info, warn, Level, LevelFilter};
+use mpatch::{
+    apply_patches_to_dir, apply_patches_to_output_dir, parse_auto_with_report, patch_content_str,
+    ApplyResult, BackupMode, BatchEntrySummary, BatchResult, HunkApplier, HunkApplyError,
+    HunkApplyStatus, HunkPreview, MatchType, OneShotError, Patch, PathStrip, ScoringMode,
+    StrictApplyError,
+};
 use std::fs::{self, File};
-use std::io::{self, Write};
-use std::path::PathBuf;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::{collections::HashMap, time::SystemTime, time::UNIX_EPOCH};
 
 const DEFAULT_FUZZ_THRESHOLD: f32 = 0.7;
 
+/// Exit code when every patch applied cleanly.
+const EXIT_SUCCESS: i32 = 0;
+/// Exit code for usage errors and input that could not be read or parsed.
+const EXIT_USAGE: i32 = 1;
+/// Exit code when some hunks failed to apply.
+const EXIT_PARTIAL: i32 = 2;
+/// Exit code when a patch could not be applied at all, e.g. a missing target
+/// file or a path outside the target directory.
+const EXIT_HARD_ERROR: i32 = 3;
+
 // --- Main Application Entry Point ---
 
 fn main() {
     // 1. Parse command-line arguments using `clap`.
-    let args = Args::parse();
+    //    Usage errors exit with EXIT_USAGE instead of clap's default of 2,
+    //    which means a partial failure here.
+    let args = match Args::try_parse() {
+        Ok(args) => args,
+        Err(e) if e.use_stderr() => {
+            let _ = e.print();
+            std::process::exit(EXIT_USAGE);
+        }
+        Err(e) => e.exit(),
+    };
+    let report_format = args.report_format;
 
     // 2. Call the main logic function.
     //    All complex logic and error handling is inside `run`.
-    if let Err(e) = run(args) {
-        // 3. If `run` returns an error, it has already been logged by the time it gets here
-        //    (unless the logger itself failed). We just need to print a user-facing
-        //    message and set the exit code.
-        //    Using {:?} ensures the full error chain from `anyhow` is printed.
-        eprintln!("{} {:?}", "error:".red().bold(), e);
-        std::process::exit(1);
+    match run(args) {
+        Ok(summary) => {
+            if report_format == ReportFormat::Json {
+                println!("{}", summary.to_json());
+            }
+            if let Some(message) = summary.failure_message() {
+                eprintln!("{} {}", "error:".red().bold(), message);
+            }
+            std::process::exit(summary.exit_code());
+        }
+        Err(e) => {
+            // 3. If `run` returns an error, it has already been logged by the time it gets here
+            //    (unless the logger itself failed). We just need to print a user-facing
+            //    message and set the exit code.
+            //    Using {:?} ensures the full error chain from `anyhow` is printed.
+            eprintln!("{} {:?}", "error:".red().bold(), e);
+            std::process::exit(error_exit_code(&e));
+        }
     }
 }
 
 /// Contains the primary logic of the application.
-fn run(args: Args) -> Result<()> {
+fn run(args: Args) -> Result<RunSummary> {
     // We re-bind `args` as mutable and allow `unused_mut`.
     // When the `clipboard` feature is disabled, `args` is never mutated,
     // which triggers an `unused_mut` warning. When enabled, it MUST be mutable.
@@ -40,6 +75,12 @@ fn run(args: Args) -> Result<()> {
     #[allow(unused_mut)]
     let mut args = args;
 
+    if args.report_format == ReportFormat::Json && !cfg!(feature = "serde") {
+        return Err(anyhow!(
+            "JSON reports need mpatch to be built with the `serde` feature"
+        ));
+    }
+
     // --- File Parsing & Clipboard ---
     #[cfg(feature = "clipboard")]
     let use_clipboard = args.clipboard;
@@ -74,11 +115,15 @@ fn run(args: Args) -> Result<()> {
             .input_file
             .as_ref()
             .expect("input_file is required unless --clipboard is used");
-        let content = fs::read_to_string(input_file)
-            .with_context(|| format!("Failed to read input file '{}'", input_file.display()))?;
-        content
+        read_input(input_file)
+            .with_context(|| format!("Failed to read input file '{}'", input_file.display()))?
     };
 
+    if let Some(file) = &args.file {
+        run_single_file(&args, &content, file)?;
+        return Ok(RunSummary::default());
+    }
+
     let actual_target_dir = args.target_dir.as_ref().unwrap().clone();
 
     // --- Argument Validation ---
@@ -88,18 +133,31 @@ fn run(args: Args) -> Result<()> {
             actual_target_dir.display()
         ));
     }
-    if !(0.0..=1.0).contains(&args.fuzz_factor) {
-        return Err(anyhow!("Fuzz factor must be between 0.0 and 1.0."));
+    mpatch::FuzzFactor::new(args.fuzz_factor)?;
+    if args.interactive {
+        if args.input_file.as_deref().is_some_and(is_stdin) {
+            return Err(anyhow!(
+                "--interactive reads its answers from stdin, so the patch cannot be read from stdin."
+            ));
+        }
+        // MPATCH_ASSUME_TTY lets scripts answer the prompts through a pipe.
+        if !io::stdin().is_terminal() && std::env::var_os("MPATCH_ASSUME_TTY").is_none() {
+            return Err(anyhow!(
+                "--interactive needs a terminal on stdin to ask which hunks to apply. Set MPATCH_ASSUME_TTY=1 to read the answers from a pipe."
+            ));
+        }
     }
 
-    let mut all_patches = parse_auto(&content)?;
+    let (all_patches, parse_report) = parse_auto_with_report(&content)?;
 
-    if args.reverse {
-        info!(
-            "Reversing {} patch(es) before application...",
-            all_patches.len()
-        );
-        all_patches = mpatch::invert_patches(&all_patches);
+    if args.describe {
+        let described = if args.reverse {
+            mpatch::invert_patches(&all_patches)
+        } else {
+            all_patches.clone()
+        };
+        println!("{}", mpatch::describe_patches(&described));
+        return Ok(RunSummary::default());
     }
 
     // --- Setup Logging and Reporting ---
@@ -124,19 +182,42 @@ fn run(args: Args) -> Result<()> {
             write_report_footer(arc, &args, &all_patches, batch_result, contents, anon);
         }
     };
+    info!("Parse summary: {}", parse_report);
+    for warning in &parse_report.warnings {
+        info!("  Parse warning: {}", warning);
+    }
+
     // --- Core Patching Logic ---
     if all_patches.is_empty() {
         info!("No valid patches found or processed in the input file.");
-        return Ok(());
+        if parse_report.blocks_skipped > 0 {
+            info!(
+                "{} of {} code block(s) were skipped because they contained no patch markers (e.g. '--- a/path' headers).",
+                parse_report.blocks_skipped, parse_report.blocks_found
+            );
+        }
+        return Ok(RunSummary {
+            dry_run: args.dry_run,
+            ..Default::default()
+        });
     }
 
     let options = mpatch::ApplyOptions {
         dry_run: args.dry_run,
         fuzz_factor: args.fuzz_factor,
+        reverse: args.reverse,
+        path_strip: args.strip,
+        // Each run backs up the files as they were before it.
+        backup: args.backup_dir.clone().map(BackupMode::Directory),
+        overwrite_existing_backup: true,
+        ..Default::default()
     };
 
     info!(""); // Vertical spacing for readability
     info!("Found {} patch operation(s) to perform.", all_patches.len());
+    if options.reverse {
+        info!("Reverse mode: the patches will be un-applied.");
+    }
     if options.fuzz_factor > 0.0 {
         info!(
             "Fuzzy matching enabled with threshold: {:.2}",
@@ -146,72 +227,702 @@ fn run(args: Args) -> Result<()> {
         info!("Fuzzy matching disabled.");
     }
 
-    let mut success_count = 0;
-    let mut fail_count = 0;
-
+    // In interactive mode only the hunks the user accepted are applied, so
+    // the results line up with the trimmed patches instead of the parsed ones.
+    let mut session = None;
     // Use the new high-level batch application function.
-    let batch_result = apply_patches_to_dir(&all_patches, &actual_target_dir, options);
+    let batch_result = match &args.output_dir {
+        Some(output_dir) => {
+            info!("Writing patched files to '{}'.", output_dir.display());
+            apply_patches_to_output_dir(
+                &all_patches,
+                &actual_target_dir,
+                output_dir,
+                options.clone(),
+            )
+        }
+        None => {
+            if args.interactive {
+                let reviewed = run_interactive(&all_patches, &actual_target_dir, &options)?;
+                let batch_result = reviewed.batch_result;
+                session = Some((reviewed.accepted, reviewed.declined));
+                batch_result
+            } else {
+                apply_patches_to_dir(&all_patches, &actual_target_dir, options.clone())
+            }
+        }
+    };
+    let patches: &[Patch] = session
+        .as_ref()
+        .map_or(&all_patches, |(accepted, _)| accepted);
+    let mut summary = RunSummary::new(&batch_result, args.dry_run);
+    if let Some((_, declined)) = &session {
+        summary.mark_declined(declined);
+    }
+    if let (Some(reject_dir), false) = (&args.reject_dir, args.dry_run) {
+        write_rejects(
+            &mut summary,
+            &batch_result,
+            patches,
+            reject_dir,
+            args.reverse,
+        )?;
+    }
+
     let num_ops = batch_result.results.len();
+    let input_name = args.input_file.as_ref().map_or_else(
+        || "the clipboard".to_string(),
+        |p| {
+            if is_stdin(p) {
+                "stdin".to_string()
+            } else {
+                p.display().to_string()
+            }
+        },
+    );
+    // Tells the user which part of their input a failing patch came from.
+    let origin_note = |i: usize| match summary.files[i].origin_line {
+        Some(line) => format!(" (from block starting at line {} of {})", line, input_name),
+        None => String::new(),
+    };
 
     // Iterate through the results to provide detailed CLI feedback.
-    for (i, ((path, result), patch)) in batch_result.results.iter().zip(&all_patches).enumerate() {
+    for (i, ((path, result), patch)) in batch_result.results.iter().zip(patches).enumerate() {
         info!(""); // Vertical spacing
         info!(">>> Operation {}/{}", i + 1, num_ops);
         match result {
             Ok(patch_result) => {
-                if let Some(diff) = &patch_result.diff {
+                // In JSON mode stdout holds only the report.
+                if let (Some(diff), ReportFormat::Text) = (&patch_result.diff, args.report_format) {
                     println!("----- Proposed Changes for {} -----", path.display());
-                    print!("{}", diff);
+                    print_dry_run_diff(diff, &patch_result.hunk_previews, patch.hunks.len());
                     println!("------------------------------------");
                 }
-                if patch_result.report.all_applied_cleanly() {
-                    success_count += 1;
-                } else {
-                    fail_count += 1;
-                    error!("--- FAILED to apply patch for: {}", path.display());
+                if !patch_result.report.all_applied_cleanly() {
+                    error!(
+                        "--- FAILED to apply patch for: {}{}",
+                        path.display(),
+                        origin_note(i)
+                    );
                     log_failed_hunks(&patch_result.report, patch);
+                    log_near_misses(&patch_result.report, patch, &actual_target_dir, &options);
                 }
             }
             Err(e) => {
                 // A "hard" error occurred (e.g., I/O error, path traversal).
-                // This is fatal, so we stop and return the error.
-                finalize_report(Some(&batch_result));
-                // Since `e` is a reference from `.iter()`, we create a new error from its display representation.
-                return Err(anyhow!("{}", e)).with_context(|| {
-                    format!(
-                        "A fatal error occurred while applying patch for: {}",
-                        path.display()
-                    )
-                });
+                error!(
+                    "A fatal error occurred while applying patch for: {}{}: {}",
+                    path.display(),
+                    origin_note(i),
+                    e
+                );
             }
         }
     }
 
     // --- Final Summary ---
     info!("\n--- Summary ---");
-    info!("Successful operations: {}", success_count);
-    info!("Failed operations:     {}", fail_count);
+    for line in batch_result.to_string().lines() {
+        info!("{}", line);
+    }
+    info!(
+        "Hunks applied: {}/{}",
+        batch_result.applied_hunks(),
+        batch_result.total_hunks()
+    );
+    if let Some((_, declined)) = &session {
+        info!(
+            "Hunks declined: {}",
+            declined.iter().map(Vec::len).sum::<usize>()
+        );
+    }
     if args.dry_run {
         info!("DRY RUN completed. No files were modified.");
+    } else if let Some(output_dir) = &args.output_dir {
+        info!(
+            "Patched files were written to '{}'. The target directory was not modified.",
+            output_dir.display()
+        );
     }
-
-    if fail_count > 0 {
+    if summary.exit_code() != EXIT_SUCCESS {
         warn!("Review the log for errors. Some files may be in a partially patched state.");
-        finalize_report(Some(&batch_result));
+    }
 
-        // Return an error to set a non-zero exit code.
+    finalize_report(Some(&batch_result));
+    Ok(summary)
+}
+
+/// Applies the patch in `patch_text` to a single file in memory and writes the
+/// result to `--output`, or to stdout.
+///
+/// The patch must hold exactly one file's changes. If any hunk fails, nothing
+/// is written and an error is returned, so the exit code is 2 (or 3 if the
+/// patch could not be applied at all).
+fn run_single_file(args: &Args, patch_text: &str, file: &Path) -> Result<()> {
+    setup_logging_and_reporting(args, patch_text, &[])?;
+    mpatch::FuzzFactor::new(args.fuzz_factor)?;
+    if is_stdin(file) && args.input_file.as_deref().is_some_and(is_stdin) {
         return Err(anyhow!(
-            "Completed with {} failed patch operations.",
-            fail_count
+            "The patch and the file to patch cannot both be read from stdin."
         ));
     }
 
-    finalize_report(Some(&batch_result));
+    let original = if is_stdin(file) || file.exists() {
+        Some(
+            read_input(file)
+                .with_context(|| format!("Failed to read file '{}'", file.display()))?,
+        )
+    } else {
+        info!(
+            "'{}' does not exist; treating the patch as a file creation.",
+            file.display()
+        );
+        None
+    };
+    let options = mpatch::ApplyOptions {
+        fuzz_factor: args.fuzz_factor,
+        reverse: args.reverse,
+        ..Default::default()
+    };
+    let patched = patch_content_str(patch_text, original.as_deref(), &options)
+        .with_context(|| format!("Failed to patch '{}'", file.display()))?;
+
+    match args.output.as_deref() {
+        Some(output) if !is_stdin(output) => fs::write(output, &patched)
+            .with_context(|| format!("Failed to write '{}'", output.display()))?,
+        _ => io::stdout()
+            .write_all(patched.as_bytes())
+            .context("Failed to write the patched content to stdout")?,
+    }
+    Ok(())
+}
+
+/// Whether `path` is `-`, which stands for stdin (or stdout for `--output`).
+fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Reads the whole of `path`, or of stdin if it is `-`.
+fn read_input(path: &Path) -> io::Result<String> {
+    if is_stdin(path) {
+        io::read_to_string(io::stdin())
+    } else {
+        fs::read_to_string(path)
+    }
+}
+
+// --- Run Summary ---
+
+/// What happened to every patch operation of a run. It is gathered before
+/// anything is reported, so the log, the JSON report and the exit code agree.
+#[derive(Debug, Default)]
+struct RunSummary {
+    files: Vec<FileSummary>,
+    /// Only reported in JSON.
+    #[cfg_attr(not(feature = "serde"), allow(dead_code))]
+    dry_run: bool,
+}
+
+/// The outcome of one patch operation.
+///
+/// The JSON report is the library's [`BatchEntrySummary`] for the patch, with
+/// the details only the CLI knows added alongside its fields.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+struct FileSummary {
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    entry: BatchEntrySummary,
+    status: FileStatus,
+    /// The input line the patch's code block started at, if it came from Markdown.
+    origin_line: Option<usize>,
+    /// Where `--reject-dir` saved the hunks that failed.
+    reject_file: Option<PathBuf>,
+    /// The 1-based indices of the hunks declined in interactive mode. The
+    /// report only covers the hunks that were accepted.
+    declined_hunks: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+enum FileStatus {
+    Applied,
+    Partial,
+    Error,
+}
+
+impl RunSummary {
+    fn new(batch_result: &BatchResult, dry_run: bool) -> Self {
+        let files = batch_result
+            .to_summary()
+            .entries
+            .into_iter()
+            .zip(&batch_result.origins)
+            .map(|(entry, origin)| {
+                let status = match &entry.report {
+                    None => FileStatus::Error,
+                    Some(report) if !report.failures().is_empty() => FileStatus::Partial,
+                    Some(_) => FileStatus::Applied,
+                };
+                FileSummary {
+                    entry,
+                    status,
+                    origin_line: origin.as_ref().map(|origin| origin.start_line),
+                    reject_file: None,
+                    declined_hunks: Vec::new(),
+                }
+            })
+            .collect();
+        RunSummary { files, dry_run }
+    }
+
+    /// Records the hunks declined in interactive mode. `declined` holds the
+    /// 1-based indices of the declined hunks of each file, in the original patch.
+    fn mark_declined(&mut self, declined: &[Vec<usize>]) {
+        for (file, declined) in self.files.iter_mut().zip(declined) {
+            file.declined_hunks = declined.clone();
+        }
+    }
+
+    fn count(&self, status: FileStatus) -> usize {
+        self.files.iter().filter(|f| f.status == status).count()
+    }
+
+    /// The worst outcome of the run, as a process exit code.
+    fn exit_code(&self) -> i32 {
+        if self.count(FileStatus::Error) > 0 {
+            EXIT_HARD_ERROR
+        } else if self.count(FileStatus::Partial) > 0 {
+            EXIT_PARTIAL
+        } else {
+            EXIT_SUCCESS
+        }
+    }
+
+    /// The message to print when the run did not apply cleanly.
+    fn failure_message(&self) -> Option<String> {
+        let (errors, partial) = (
+            self.count(FileStatus::Error),
+            self.count(FileStatus::Partial),
+        );
+        match (errors, partial) {
+            (0, 0) => None,
+            (0, _) => Some(format!(
+                "Completed with {} failed patch operations.",
+                partial
+            )),
+            _ => Some(format!(
+                "Completed with {} fatal error(s) and {} failed patch operations.",
+                errors, partial
+            )),
+        }
+    }
+
+    /// Renders the summary as a single line of JSON.
+    #[cfg(feature = "serde")]
+    fn to_json(&self) -> String {
+        let status = match self.exit_code() {
+            EXIT_SUCCESS => "clean",
+            EXIT_PARTIAL => "partial",
+            _ => "error",
+        };
+        serde_json::json!({
+            "status": status,
+            "exit_code": self.exit_code(),
+            "dry_run": self.dry_run,
+            "files": self.files,
+        })
+        .to_string()
+    }
+
+    /// JSON reports are rejected before anything is applied without `serde`.
+    #[cfg(not(feature = "serde"))]
+    fn to_json(&self) -> String {
+        unreachable!()
+    }
+}
+
+/// The exit code for an error that ended the run early: a single-file patch
+/// that failed maps to the same codes as a batch, anything else is a usage error.
+fn error_exit_code(error: &anyhow::Error) -> i32 {
+    match error.downcast_ref::<OneShotError>() {
+        Some(OneShotError::Apply(StrictApplyError::PartialApply { .. })) => EXIT_PARTIAL,
+        Some(OneShotError::Apply(StrictApplyError::Patch(_))) => EXIT_HARD_ERROR,
+        _ => EXIT_USAGE,
+    }
+}
+
+/// Writes the hunks that failed to apply to `.rej` files under `reject_dir`,
+/// mirroring each file's path relative to the target directory.
+fn write_rejects(
+    summary: &mut RunSummary,
+    batch_result: &BatchResult,
+    patches: &[Patch],
+    reject_dir: &Path,
+    reverse: bool,
+) -> Result<()> {
+    for ((file, (_, result)), patch) in summary
+        .files
+        .iter_mut()
+        .zip(&batch_result.results)
+        .zip(patches)
+    {
+        let Ok(patch_result) = result else {
+            continue;
+        };
+        let Some(rejected) = patch.rejected_hunks(&patch_result.report, reverse) else {
+            continue;
+        };
+        let relative: PathBuf = file
+            .entry
+            .path
+            .components()
+            .skip(patch_result.strip_level)
+            .collect();
+        let mut reject_path = reject_dir.join(&relative).into_os_string();
+        reject_path.push(".rej");
+        let reject_path = PathBuf::from(reject_path);
+        if let Some(parent) = reject_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create '{}'", parent.display()))?;
+        }
+        let mut text = rejected.to_string();
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        fs::write(&reject_path, text)
+            .with_context(|| format!("Failed to write '{}'", reject_path.display()))?;
+        warn!(
+            "Saved {} failed hunk(s) for '{}' to '{}'",
+            rejected.hunks.len(),
+            file.entry.path.display(),
+            reject_path.display()
+        );
+        file.reject_file = Some(reject_path);
+    }
     Ok(())
 }
 
 // --- Helper Structs and Functions ---
 
+/// Prints a dry-run diff hunk by hunk, each preceded by a comment line saying
+/// how the hunk matched.
+///
+/// The file headers come from the full diff. When there are no previews (for
+/// a pure rename, or a patch that was held back), the full diff is printed as is.
+fn print_dry_run_diff(diff: &str, previews: &[HunkPreview], total_hunks: usize) {
+    if previews.is_empty() {
+        print!("{}", diff);
+        return;
+    }
+    let header_end = diff
+        .match_indices("@@")
+        .find(|(i, _)| *i == 0 || diff.as_bytes()[i - 1] == b'\n')
+        .map_or(diff.len(), |(i, _)| i);
+    print!("{}", &diff[..header_end]);
+    for preview in previews {
+        println!(
+            "# hunk {}/{}: {} at {}",
+            preview.hunk_index,
+            total_hunks,
+            describe_match(&preview.match_type),
+            preview.location
+        );
+        print!("{}", preview.diff_text);
+    }
+}
+
+/// How a hunk matched, in words.
+fn describe_match(match_type: &MatchType) -> String {
+    match match_type {
+        MatchType::Exact => "exact match".to_string(),
+        MatchType::ExactIgnoringWhitespace => {
+            "exact match ignoring trailing whitespace".to_string()
+        }
+        MatchType::ExactIgnoringIndentation => "exact match ignoring indentation".to_string(),
+        MatchType::ExactCanonicalized => "exact match after canonicalization".to_string(),
+        MatchType::ExactAtEof => "exact match at end of file".to_string(),
+        MatchType::Fuzzy {
+            score,
+            mode: ScoringMode::PerLine,
+        } => format!("fuzzy match (per-line score {:.2})", score),
+        MatchType::Fuzzy { score, .. } => format!("fuzzy match (score {:.2})", score),
+    }
+}
+
+// --- Interactive Mode ---
+
+/// The outcome of an `--interactive` run.
+struct InteractiveSession {
+    batch_result: BatchResult,
+    /// Each reviewed patch, with only the hunks the user accepted.
+    accepted: Vec<Patch>,
+    /// The 1-based indices of the hunks the user declined, for each patch.
+    declined: Vec<Vec<usize>>,
+}
+
+/// What the user decided for one patch.
+enum Decision {
+    /// Apply the hunks marked `true`.
+    Apply(Vec<bool>),
+    /// Leave the file untouched. Only asked for patches without hunks.
+    Skip,
+    /// Leave this file and all the following ones untouched.
+    Quit,
+}
+
+/// An answer to the interactive prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Answer {
+    Yes,
+    No,
+    All,
+    Quit,
+}
+
+/// Asks about every hunk of `patches` on stdin, then applies the accepted
+/// ones. Each file is written only once all of its hunks have been decided,
+/// so quitting leaves the file being reviewed, and all later ones, untouched.
+fn run_interactive(
+    patches: &[Patch],
+    target_dir: &Path,
+    options: &mpatch::ApplyOptions,
+) -> Result<InteractiveSession> {
+    let mut input = io::stdin().lock();
+    let mut session = InteractiveSession {
+        batch_result: BatchResult {
+            results: Vec::new(),
+            origins: Vec::new(),
+        },
+        accepted: Vec::new(),
+        declined: Vec::new(),
+    };
+    let mut quit = false;
+    for patch in patches {
+        let decision = if quit {
+            Decision::Quit
+        } else {
+            review_patch(patch, target_dir, options, &mut input)?
+        };
+        let accepted = match decision {
+            Decision::Apply(accepted) => accepted,
+            Decision::Quit | Decision::Skip if patch.hunks.is_empty() => {
+                quit |= matches!(decision, Decision::Quit);
+                info!("Skipped '{}'.", patch.file_path.display());
+                continue;
+            }
+            Decision::Quit | Decision::Skip => {
+                quit |= matches!(decision, Decision::Quit);
+                vec![false; patch.hunks.len()]
+            }
+        };
+
+        let mut kept = patch.clone();
+        kept.hunks = patch
+            .hunks
+            .iter()
+            .zip(&accepted)
+            .filter(|(_, &accept)| accept)
+            .map(|(hunk, _)| hunk.clone())
+            .collect();
+        let declined: Vec<usize> = (1..=accepted.len())
+            .filter(|&index| !accepted[index - 1])
+            .collect();
+        let result = if kept.hunks.is_empty() && !patch.hunks.is_empty() {
+            Ok(untouched_result(options))
+        } else {
+            mpatch::apply_patch_to_file(&kept, target_dir, options.clone())
+        };
+        let path = if options.reverse {
+            patch.file_path.clone()
+        } else {
+            patch.destination_path().to_path_buf()
+        };
+        session.batch_result.results.push((path, result));
+        session
+            .batch_result
+            .origins
+            .push(patch.source_block.clone());
+        session.accepted.push(kept);
+        session.declined.push(declined);
+    }
+    Ok(session)
+}
+
+/// Shows each hunk of `patch` where it would land in its file, and asks
+/// whether to apply it.
+///
+/// The hunks are applied in memory as they are accepted, so each one is
+/// located the way it would be after the earlier ones, and undone when
+/// declined. A hunk that cannot be applied is shown without a prompt and
+/// left in, so that it is reported as failed.
+///
+/// Everything is shown on stderr, so that stdout keeps only the report. The
+/// file is read with [`mpatch::read_patch_target()`], so hunks are located
+/// the same way they are when the patch is applied.
+fn review_patch(
+    patch: &Patch,
+    target_dir: &Path,
+    options: &mpatch::ApplyOptions,
+    input: &mut impl io::BufRead,
+) -> Result<Decision> {
+    // Reversing a rename patches the file at its new path.
+    let path = if options.reverse {
+        patch.destination_path()
+    } else {
+        patch.file_path.as_path()
+    };
+    eprintln!("{}", format!("--- {}", path.display()).bold());
+    if patch.hunks.is_empty() {
+        return Ok(match ask("Apply this change", input)? {
+            Answer::Yes | Answer::All => Decision::Apply(Vec::new()),
+            Answer::No => Decision::Skip,
+            Answer::Quit => Decision::Quit,
+        });
+    }
+
+    let original: Option<Vec<String>> = mpatch::read_patch_target(patch, target_dir, options)
+        .ok()
+        .flatten()
+        .map(|content| content.lines().map(str::to_string).collect());
+    let mut applier = HunkApplier::new(patch, original.as_deref(), options);
+    let total = patch.hunks.len();
+    let mut accepted = Vec::with_capacity(total);
+    for (i, hunk) in patch.hunks.iter().enumerate() {
+        let Some(status) = applier.next() else {
+            break;
+        };
+        let shown = if options.reverse {
+            hunk.invert()
+        } else {
+            hunk.clone()
+        };
+        print_colored_hunk(&shown);
+        let found = match &status {
+            HunkApplyStatus::Applied {
+                location,
+                match_type,
+                ..
+            } => format!("{} at {}", describe_match(match_type), location),
+            HunkApplyStatus::AppliedWithConflict { location, reason } => {
+                format!("conflict markers at {} ({})", location, reason)
+            }
+            HunkApplyStatus::PartiallyApplied { .. } => {
+                "partial match, some of its changes would not apply".to_string()
+            }
+            HunkApplyStatus::Failed(reason) => {
+                eprintln!("# hunk {}/{}: cannot be applied: {}", i + 1, total, reason);
+                accepted.push(true);
+                continue;
+            }
+            HunkApplyStatus::SkippedNoChanges | HunkApplyStatus::AlreadyApplied { .. } => {
+                eprintln!("# hunk {}/{}: nothing to change", i + 1, total);
+                accepted.push(true);
+                continue;
+            }
+            HunkApplyStatus::SkippedDuplicate {
+                first_applied_index,
+            } => {
+                eprintln!(
+                    "# hunk {}/{}: repeats hunk {}, nothing to change",
+                    i + 1,
+                    total,
+                    first_applied_index
+                );
+                accepted.push(true);
+                continue;
+            }
+        };
+        eprintln!("# hunk {}/{}: would apply with {}", i + 1, total, found);
+        match ask("Apply this hunk", input)? {
+            Answer::Yes => accepted.push(true),
+            Answer::No => {
+                applier
+                    .undo_last()
+                    .context("Failed to undo a declined hunk")?;
+                accepted.push(false);
+            }
+            Answer::All => {
+                accepted.resize(total, true);
+                break;
+            }
+            Answer::Quit => return Ok(Decision::Quit),
+        }
+    }
+    Ok(Decision::Apply(accepted))
+}
+
+/// Prints `hunk` to stderr as a unified diff, with its additions and removals
+/// colored.
+fn print_colored_hunk(hunk: &mpatch::Hunk) {
+    for line in hunk.to_string().lines() {
+        let colored = if line.starts_with("@@") {
+            line.cyan()
+        } else if line.starts_with('+') {
+            line.green()
+        } else if line.starts_with('-') {
+            line.red()
+        } else {
+            line.normal()
+        };
+        eprintln!("{}", colored);
+    }
+}
+
+/// Prompts with `question` on stderr until a valid answer is read. The end of
+/// the input counts as quitting.
+fn ask(question: &str, input: &mut impl io::BufRead) -> Result<Answer> {
+    loop {
+        eprint!(
+            "{}? [y]es / [n]o / [a]ll for file / [q]uit: ",
+            question.blue().bold()
+        );
+        io::stderr().flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            eprintln!();
+            return Ok(Answer::Quit);
+        }
+        match line.trim().to_ascii_lowercase().as_str() {
+            "y" | "yes" => return Ok(Answer::Yes),
+            "n" | "no" => return Ok(Answer::No),
+            "a" | "all" => return Ok(Answer::All),
+            "q" | "quit" => return Ok(Answer::Quit),
+            _ => eprintln!("Please answer y, n, a or q."),
+        }
+    }
+}
+
+/// The result recorded for a file whose hunks were all declined.
+fn untouched_result(options: &mpatch::ApplyOptions) -> mpatch::PatchResult {
+    mpatch::PatchResult {
+        report: ApplyResult {
+            hunk_results: Vec::new(),
+            stats: None,
+            line_map: None,
+        },
+        diff: None,
+        written: false,
+        reject_file: None,
+        removed: false,
+        strip_level: 0,
+        hunk_previews: Vec::new(),
+        backup_path: None,
+        options_used: options.clone(),
+    }
+}
+
+/// Parses the value of `-p`: a number of components, or `auto`.
+fn parse_path_strip(value: &str) -> Result<PathStrip, String> {
+    if value.eq_ignore_ascii_case("auto") {
+        return Ok(PathStrip::Auto);
+    }
+    value
+        .parse()
+        .map(PathStrip::Levels)
+        .map_err(|_| format!("expected a number or 'auto', got '{}'", value))
+}
+
 #[derive(Clone)]
 struct Anonymizer {
     replacements: Vec<(String, String)>,
@@ -301,6 +1012,12 @@ fn log_failed_hunks(apply_result: &mpatch::ApplyResult, patch: &Patch) {
     }
     for failure in apply_result.failures() {
         warn!("  - Hunk {} failed: {}", failure.hunk_index, failure.reason);
+        if let Some(culprit) = failure.interference {
+            warn!(
+                "    Note: Hunk {} was applied over part of this hunk's context, which likely caused this failure.",
+                culprit
+            );
+        }
         // hunk_index is 1-based, so we need to subtract 1 for indexing.
         if let Some(hunk) = patch.hunks.get(failure.hunk_index - 1) {
             warn!("    Failed Hunk Content:");
@@ -308,6 +1025,74 @@ fn log_failed_hunks(apply_result: &mpatch::ApplyResult, patch: &Patch) {
                 warn!("      {}", line);
             }
         }
+        let candidates: Vec<(usize, &Vec<String>)> = match &failure.reason {
+            HunkApplyError::FuzzyMatchBelowThreshold {
+                location,
+                candidate_lines,
+                ..
+            } => vec![(location.start_index, candidate_lines)],
+            HunkApplyError::AmbiguousFuzzyMatch {
+                locations,
+                candidate_lines,
+            } => locations
+                .iter()
+                .map(|&(start, _)| start)
+                .zip(candidate_lines)
+                .collect(),
+            _ => Vec::new(),
+        };
+        for (start, lines) in candidates {
+            warn!("    Closest Candidate (line {}):", start + 1);
+            for line in lines {
+                warn!("      {}", line);
+            }
+        }
+    }
+}
+
+/// At `-vv` and above, shows what each failed hunk came closest to matching in
+/// its target file, as a diff from the hunk's expected lines to the lines found.
+fn log_near_misses(
+    apply_result: &mpatch::ApplyResult,
+    patch: &Patch,
+    target_dir: &Path,
+    options: &mpatch::ApplyOptions,
+) {
+    if !log::log_enabled!(log::Level::Debug) {
+        return;
+    }
+    let failures = apply_result.failures();
+    if failures.is_empty() {
+        return;
+    }
+    let Some(content) = mpatch::read_patch_target(patch, target_dir, options)
+        .ok()
+        .flatten()
+    else {
+        return;
+    };
+    let target_lines: Vec<&str> = content.lines().collect();
+    for failure in failures {
+        // hunk_index is 1-based, so we need to subtract 1 for indexing.
+        let Some(hunk) = patch.hunks.get(failure.hunk_index - 1) else {
+            continue;
+        };
+        let hunk = if options.reverse {
+            hunk.invert()
+        } else {
+            hunk.clone()
+        };
+        let explanation = mpatch::explain_hunk_failure(&hunk, &target_lines, options);
+        let Some(best) = explanation.best() else {
+            continue;
+        };
+        debug!(
+            "  Hunk {} came closest to matching at {} (similarity {:.2}, threshold {:.2}). Expected (-) vs found (+):",
+            failure.hunk_index, best.location, best.score, explanation.threshold
+        );
+        for line in &explanation.diff {
+            debug!("      {}", line);
+        }
     }
 }
 
@@ -321,16 +1106,40 @@ fn log_failed_hunks(apply_result: &mpatch::ApplyResult, patch: &Patch) {
 )]
 struct Args {
     /// Path to the input file containing the patch (Markdown, Unified Diff, or Conflict Markers).
+    /// Use `-` to read the patch from stdin.
     /// If --clipboard is used, the first positional argument becomes the target directory.
     #[cfg_attr(feature = "clipboard", arg(required_unless_present = "clipboard"))]
     #[cfg_attr(not(feature = "clipboard"), arg(required = true))]
     input_file: Option<PathBuf>,
 
     /// Path to the target directory to apply patches.
-    #[cfg_attr(feature = "clipboard", arg(required_unless_present = "clipboard"))]
-    #[cfg_attr(not(feature = "clipboard"), arg(required = true))]
+    #[cfg_attr(
+        feature = "clipboard",
+        arg(required_unless_present_any = ["clipboard", "file"])
+    )]
+    #[cfg_attr(not(feature = "clipboard"), arg(required_unless_present = "file"))]
     target_dir: Option<PathBuf>,
 
+    /// Patch this single file in memory instead of a target directory, and
+    /// write the result to --output. Use `-` to read the file from stdin.
+    /// The paths in the patch are ignored, so --strip cannot be used with it.
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["target_dir", "output_dir", "backup_dir", "dry_run", "strip"],
+        help = "Patch a single FILE in memory ('-' for stdin) and print the result."
+    )]
+    file: Option<PathBuf>,
+    /// Where --file writes the patched content. `-` (the default) is stdout.
+    #[arg(
+        short = 'o',
+        long,
+        value_name = "FILE",
+        requires = "file",
+        help = "Write the result of --file to FILE instead of stdout ('-')."
+    )]
+    output: Option<PathBuf>,
+
     /// Input from clipboard instead of a file.
     #[cfg(feature = "clipboard")]
     #[arg(short = 'c', long, help = "Input from clipboard instead of a file.")]
@@ -349,6 +1158,68 @@ struct Args {
     /// Reverse the patch before applying (swaps additions and deletions).
     #[arg(short = 'R', long, help = "Reverse the patch before applying.")]
     reverse: bool,
+    /// Strip this many leading components from the patch paths, like `patch -p`.
+    /// `auto` picks the level that names an existing file.
+    #[arg(
+        short = 'p',
+        long = "strip",
+        value_name = "N",
+        default_value = "0",
+        value_parser = parse_path_strip,
+        help = "Strip N leading components from patch paths (like patch -pN), or 'auto'."
+    )]
+    strip: PathStrip,
+    /// Write the patched files under this directory, mirroring their relative paths,
+    /// instead of modifying the target directory.
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with = "backup_dir",
+        help = "Write patched files under DIR instead of modifying the target directory."
+    )]
+    output_dir: Option<PathBuf>,
+    /// Copy each file to this directory, mirroring its relative path, before patching it in place.
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Copy the original of each patched file under DIR before modifying it."
+    )]
+    backup_dir: Option<PathBuf>,
+    /// Save the hunks that fail to apply under this directory, as `.rej` files
+    /// mirroring the patched files' relative paths.
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with = "file",
+        help = "Save failed hunks as .rej files under DIR, mirroring the patched files' paths."
+    )]
+    reject_dir: Option<PathBuf>,
+    /// How to report the outcome. `json` prints a machine-readable summary to
+    /// stdout instead of the dry-run diffs.
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        value_enum,
+        default_value_t = ReportFormat::Text,
+        conflicts_with_all = ["file", "describe"],
+        help = "Report format: 'text' logs to stderr, 'json' also prints a summary to stdout."
+    )]
+    report_format: ReportFormat,
+    /// Print a plain-language description of the patches and exit without applying them.
+    #[arg(
+        long,
+        help = "Describe what the patches do in plain language, then exit without applying."
+    )]
+    describe: bool,
+    /// Ask before applying each hunk, like `git add -p`. The answers are read
+    /// from stdin, which must be a terminal unless `MPATCH_ASSUME_TTY` is set.
+    #[arg(
+        short = 'i',
+        long,
+        conflicts_with_all = ["file", "dry_run", "output_dir", "describe", "report_format"],
+        help = "Ask whether to apply each hunk before changing any file. Answers are read from the terminal; set MPATCH_ASSUME_TTY=1 to read them from piped stdin instead."
+    )]
+    interactive: bool,
     /// Increase logging verbosity. Can be used multiple times.
     /// -v for info, -vv for debug, -vvv for trace.
     /// -vvvv also generates a comprehensive debug report file.
@@ -356,6 +1227,15 @@ struct Args {
     verbose: u8,
 }
 
+/// The format of the report `mpatch` prints when it finishes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ReportFormat {
+    /// Log messages on stderr only.
+    Text,
+    /// A JSON summary on stdout, in addition to the log messages on stderr.
+    Json,
+}
+
 /// A "Tee" writer that sends output to both stderr and a shared file.
 /// This is used in debug report mode (`-vvvv`) to show logs on the console
 /// while also writing them to the report file.
@@ -391,7 +1271,8 @@ fn setup_logging_and_reporting(
     patches: &[Patch],
 ) -> Result<Option<ReportData>> {
     let mut builder = Builder::new();
-    let report_data = if args.verbose >= 4 {
+    // A debug report describes a target directory, which --file mode has none of.
+    let report_data = if args.verbose >= 4 && args.file.is_none() {
         let anonymizer = Anonymizer::new(args);
         // --- Create and Write Report Header ---
         let (file_arc, original_contents) =
@@ -629,11 +1510,11 @@ fn write_report_footer(
                 // Scope 3: Write result
                 {
                     let mut file = file_arc.lock().unwrap();
-                    if compare_patches(original_patch, &recreated_patch) {
+                    let original_norm = original_patch.change_signature();
+                    let recreated_norm = recreated_patch.change_signature();
+                    if original_norm == recreated_norm {
                         let _ = writeln!(file, "\n- **Result:** <span style='color:green;'>SUCCESS</span>\n- **Details:** The regenerated patch is identical to the input patch (ignoring context lines).");
                     } else {
-                        let original_norm = format_normalized_patch(original_patch);
-                        let recreated_norm = format_normalized_patch(&recreated_patch);
                         let diff_text = similar::udiff::unified_diff(
                             similar::Algorithm::default(),
                             &original_norm,
@@ -675,46 +1556,6 @@ fn write_report_footer(
     }
 }
 
-/// Formats a [`Patch`] struct into a normalized string for robust discrepancy checking.
-/// It excludes context lines, ignores interleaving of +/- lines, removes self-replacements,
-/// and sorts additions/deletions globally to ignore hunk ordering differences.
-fn format_normalized_patch(patch: &Patch) -> String {
-    let mut all_removed = Vec::new();
-    let mut all_added = Vec::new();
-
-    for hunk in &patch.hunks {
-        all_removed.extend(hunk.removed_lines().into_iter().map(|s| s.to_string()));
-        all_added.extend(hunk.added_lines().into_iter().map(|s| s.to_string()));
-    }
-
-    // Remove identical lines (self-replacements) using multiset subtraction
-    let mut i = 0;
-    while i < all_removed.len() {
-        if let Some(j) = all_added.iter().position(|a| a == &all_removed[i]) {
-            all_removed.remove(i);
-            all_added.remove(j);
-        } else {
-            i += 1;
-        }
-    }
-
-    // Sort to ignore ordering differences across hunks
-    all_removed.sort();
-    all_added.sort();
-
-    let mut output = String::new();
-    for line in all_removed {
-        let _ = writeln!(output, "-{}", line);
-    }
-    for line in all_added {
-        let _ = writeln!(output, "+{}", line);
-    }
-    if !patch.ends_with_newline {
-        let _ = write!(output, "\\ No newline at end of file");
-    }
-    output
-}
-
 /// Replaces sensitive paths in command line arguments with placeholders.
 /// This helps protect user privacy when sharing debug reports.
 fn anonymize_command_args(args: &Args) -> String {
@@ -756,10 +1597,3 @@ fn anonymize_command_args(args: &Args) -> String {
     }
     anonymized_args.join(" ")
 }
-
-/// Compares two patches for semantic equivalence, focusing on the actual line changes.
-/// It ignores context lines, hunk headers, interleaving, self-replacements, and hunk order
-/// by comparing their normalized string representations.
-fn compare_patches(original: &Patch, recreated: &Patch) -> bool {
-    format_normalized_patch(original) == format_normalized_patch(recreated)
-}
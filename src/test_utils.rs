@@ -0,0 +1,593 @@
+//! Golden-corpus helpers for pinning `mpatch`'s behavior on real diffs.
+//!
+//! This module is only available with the `test-utils` feature enabled. A
+//! corpus is a set of [`CorpusCase`]s, each pairing an input diff (usually the
+//! raw Markdown an LLM produced) with the original file and the outcome you
+//! expect. Replaying the corpus with [`run_case()`] after upgrading `mpatch` or
+//! changing [`ApplyOptions`] shows exactly which cases changed behavior.
+//!
+//! On disk, [`load_corpus()`] expects one directory per case:
+//!
+//! ```text
+//! corpus/
+//!   fuzzy-indent/
+//!     case.md                 the input diff (required)
+//!     original.txt            the file before patching (absent for creations)
+//!     expected.txt            the exact content after patching
+//!   stale-context/
+//!     case.md
+//!     original.txt
+//!     expected_failures.txt   1-based indices of the hunks expected to fail
+//! ```
+//!
+//! Every case needs exactly one of `expected.txt` and `expected_failures.txt`.
+//! Files are read byte for byte, so line endings are part of the expectation.
+
+use crate::{apply_patch_to_content, parse_auto, ApplyOptions};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// One recorded patch scenario and its expected outcome.
+///
+/// # Examples
+///
+/// ```
+/// use mpatch::test_utils::{CorpusCase, Expected};
+///
+/// let case = CorpusCase {
+///     name: "rename-variable".to_string(),
+///     input_diff: "```diff\n--- a/f\n+++ b/f\n@@ -1 +1 @@\n-x\n+y\n```\n".to_string(),
+///     original: Some("x\n".to_string()),
+///     expected: Expected::Content("y\n".to_string()),
+/// };
+/// assert_eq!(case.name, "rename-variable");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorpusCase {
+    /// A name identifying the case in reports. [`load_corpus()`] uses the
+    /// name of the case's directory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mpatch::test_utils::{CorpusCase, Expected};
+    /// # let case = CorpusCase { name: "crlf".to_string(), input_diff: String::new(), original: None, expected: Expected::Failure(vec![]) };
+    /// println!("Running case {}", case.name);
+    /// ```
+    pub name: String,
+    /// The input containing the patch, in any format [`parse_auto()`] accepts.
+    /// It must describe changes to a single file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mpatch::test_utils::{CorpusCase, Expected};
+    /// # let case = CorpusCase { name: "c".to_string(), input_diff: "--- a/f\n+++ b/f\n".to_string(), original: None, expected: Expected::Failure(vec![]) };
+    /// assert!(case.input_diff.starts_with("--- a/f"));
+    /// ```
+    pub input_diff: String,
+    /// The content of the file before patching, or `None` if the patch
+    /// creates the file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mpatch::test_utils::{CorpusCase, Expected};
+    /// # let case = CorpusCase { name: "c".to_string(), input_diff: String::new(), original: None, expected: Expected::Failure(vec![]) };
+    /// if case.original.is_none() {
+    ///     println!("This case creates a new file.");
+    /// }
+    /// ```
+    pub original: Option<String>,
+    /// The outcome the case is expected to have.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mpatch::test_utils::{CorpusCase, Expected};
+    /// # let case = CorpusCase { name: "c".to_string(), input_diff: String::new(), original: None, expected: Expected::Failure(vec![2]) };
+    /// assert_eq!(case.expected, Expected::Failure(vec![2]));
+    /// ```
+    pub expected: Expected,
+}
+
+/// The outcome a [`CorpusCase`] is expected to have.
+///
+/// # Examples
+///
+/// ```
+/// use mpatch::test_utils::Expected;
+///
+/// let applies = Expected::Content("new content\n".to_string());
+/// let second_hunk_fails = Expected::Failure(vec![2]);
+/// assert_ne!(applies, second_hunk_fails);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expected {
+    /// Every hunk applies and the patched file has exactly this content.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mpatch::test_utils::Expected;
+    /// let expected = Expected::Content("fn main() {}\n".to_string());
+    /// ```
+    Content(String),
+    /// Exactly these hunks fail to apply, given as 1-based indices like
+    /// [`HunkFailure::hunk_index`](crate::HunkFailure::hunk_index).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mpatch::test_utils::Expected;
+    /// let expected = Expected::Failure(vec![1, 3]);
+    /// ```
+    Failure(Vec<usize>),
+}
+
+/// The result of replaying a [`CorpusCase`] with [`run_case()`].
+///
+/// Anything other than [`CaseOutcome::Passed`] means `mpatch` behaved
+/// differently from what the case recorded. The [`Display`](fmt::Display)
+/// implementation describes the difference.
+///
+/// # Examples
+///
+/// ```
+/// use mpatch::test_utils::CaseOutcome;
+///
+/// let outcome = CaseOutcome::UnexpectedFailures { expected: vec![], actual: vec![2] };
+/// assert!(!outcome.is_passed());
+/// assert_eq!(outcome.to_string(), "expected failed hunks [] but got [2]");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CaseOutcome {
+    /// The case behaved as expected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mpatch::test_utils::CaseOutcome;
+    /// assert!(CaseOutcome::Passed.is_passed());
+    /// ```
+    Passed,
+    /// The input diff could not be parsed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mpatch::test_utils::CaseOutcome;
+    /// let outcome = CaseOutcome::ParseFailed("No patches found".to_string());
+    /// ```
+    ParseFailed(String),
+    /// The input diff did not describe exactly one file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mpatch::test_utils::CaseOutcome;
+    /// let outcome = CaseOutcome::WrongPatchCount(2);
+    /// assert_eq!(outcome.to_string(), "expected a patch for one file but found 2");
+    /// ```
+    WrongPatchCount(usize),
+    /// The failed hunks differ from the expectation. When
+    /// [`Expected::Content`] was expected, `expected` is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mpatch::test_utils::CaseOutcome;
+    /// let outcome = CaseOutcome::UnexpectedFailures { expected: vec![1], actual: vec![] };
+    /// ```
+    UnexpectedFailures {
+        /// The 1-based indices of the hunks that were expected to fail.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use mpatch::test_utils::CaseOutcome;
+        /// let outcome = CaseOutcome::UnexpectedFailures { expected: vec![1], actual: vec![] };
+        /// if let CaseOutcome::UnexpectedFailures { expected, .. } = outcome {
+        ///     assert_eq!(expected, vec![1]);
+        /// }
+        /// ```
+        expected: Vec<usize>,
+        /// The 1-based indices of the hunks that actually failed.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use mpatch::test_utils::CaseOutcome;
+        /// let outcome = CaseOutcome::UnexpectedFailures { expected: vec![1], actual: vec![] };
+        /// if let CaseOutcome::UnexpectedFailures { actual, .. } = outcome {
+        ///     assert!(actual.is_empty());
+        /// }
+        /// ```
+        actual: Vec<usize>,
+    },
+    /// Every hunk applied, but the patched content differs from
+    /// [`Expected::Content`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mpatch::test_utils::CaseOutcome;
+    /// let outcome = CaseOutcome::ContentMismatch { expected: "a\n".to_string(), actual: "b\n".to_string() };
+    /// ```
+    ContentMismatch {
+        /// The content the case expected.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use mpatch::test_utils::CaseOutcome;
+        /// let outcome = CaseOutcome::ContentMismatch { expected: "a\n".to_string(), actual: "b\n".to_string() };
+        /// if let CaseOutcome::ContentMismatch { expected, .. } = outcome {
+        ///     assert_eq!(expected, "a\n");
+        /// }
+        /// ```
+        expected: String,
+        /// The content `mpatch` produced.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use mpatch::test_utils::CaseOutcome;
+        /// let outcome = CaseOutcome::ContentMismatch { expected: "a\n".to_string(), actual: "b\n".to_string() };
+        /// if let CaseOutcome::ContentMismatch { actual, .. } = outcome {
+        ///     assert_eq!(actual, "b\n");
+        /// }
+        /// ```
+        actual: String,
+    },
+}
+
+impl CaseOutcome {
+    /// Returns `true` if the case behaved as expected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mpatch::test_utils::CaseOutcome;
+    /// assert!(CaseOutcome::Passed.is_passed());
+    /// assert!(!CaseOutcome::WrongPatchCount(0).is_passed());
+    /// ```
+    pub fn is_passed(&self) -> bool {
+        matches!(self, CaseOutcome::Passed)
+    }
+}
+
+impl fmt::Display for CaseOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaseOutcome::Passed => write!(f, "passed"),
+            CaseOutcome::ParseFailed(message) => write!(f, "failed to parse: {}", message),
+            CaseOutcome::WrongPatchCount(count) => {
+                write!(f, "expected a patch for one file but found {}", count)
+            }
+            CaseOutcome::UnexpectedFailures { expected, actual } => {
+                write!(
+                    f,
+                    "expected failed hunks {:?} but got {:?}",
+                    expected, actual
+                )
+            }
+            CaseOutcome::ContentMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "content mismatch\n--- expected\n{}\n--- actual\n{}",
+                    expected, actual
+                )
+            }
+        }
+    }
+}
+
+/// Represents errors that can occur while loading a corpus from disk.
+///
+/// # Examples
+///
+/// ```
+/// use mpatch::test_utils::{load_corpus, CorpusError};
+///
+/// let err = load_corpus("does/not/exist".as_ref()).unwrap_err();
+/// assert!(matches!(err, CorpusError::Io { .. }));
+/// ```
+#[derive(Error, Debug)]
+pub enum CorpusError {
+    /// A file or directory of the corpus could not be read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mpatch::test_utils::CorpusError;
+    /// use std::io;
+    /// use std::path::PathBuf;
+    /// let err = CorpusError::Io {
+    ///     path: PathBuf::from("corpus/case/case.md"),
+    ///     source: io::Error::new(io::ErrorKind::NotFound, "not found"),
+    /// };
+    /// ```
+    #[error("Failed to read '{path}': {source}")]
+    Io {
+        /// The path that could not be read.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// # use mpatch::test_utils::CorpusError;
+        /// # use std::io;
+        /// # use std::path::PathBuf;
+        /// # let err = CorpusError::Io { path: PathBuf::from("a"), source: io::Error::new(io::ErrorKind::NotFound, "x") };
+        /// if let CorpusError::Io { path, .. } = err {
+        ///     assert_eq!(path, PathBuf::from("a"));
+        /// }
+        /// ```
+        path: PathBuf,
+        /// The underlying I/O error.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// # use mpatch::test_utils::CorpusError;
+        /// # use std::io;
+        /// # use std::path::PathBuf;
+        /// # let err = CorpusError::Io { path: PathBuf::from("a"), source: io::Error::new(io::ErrorKind::NotFound, "x") };
+        /// if let CorpusError::Io { source, .. } = err {
+        ///     assert_eq!(source.kind(), io::ErrorKind::NotFound);
+        /// }
+        /// ```
+        #[source]
+        source: std::io::Error,
+    },
+    /// A case directory has neither or both of `expected.txt` and
+    /// `expected_failures.txt`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mpatch::test_utils::CorpusError;
+    /// use std::path::PathBuf;
+    /// let err = CorpusError::AmbiguousExpectation(PathBuf::from("corpus/case"));
+    /// assert_eq!(
+    ///     err.to_string(),
+    ///     "Case 'corpus/case' needs exactly one of expected.txt and expected_failures.txt"
+    /// );
+    /// ```
+    #[error("Case '{}' needs exactly one of expected.txt and expected_failures.txt", .0.display())]
+    AmbiguousExpectation(PathBuf),
+    /// `expected_failures.txt` contains something other than hunk indices.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mpatch::test_utils::CorpusError;
+    /// use std::path::PathBuf;
+    /// let err = CorpusError::InvalidHunkIndex {
+    ///     path: PathBuf::from("expected_failures.txt"),
+    ///     value: "two".to_string(),
+    /// };
+    /// assert_eq!(err.to_string(), "Invalid hunk index 'two' in 'expected_failures.txt'");
+    /// ```
+    #[error("Invalid hunk index '{value}' in '{}'", path.display())]
+    InvalidHunkIndex {
+        /// The `expected_failures.txt` file.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// # use mpatch::test_utils::CorpusError;
+        /// # use std::path::PathBuf;
+        /// # let err = CorpusError::InvalidHunkIndex { path: PathBuf::from("f.txt"), value: "x".to_string() };
+        /// if let CorpusError::InvalidHunkIndex { path, .. } = err {
+        ///     assert_eq!(path, PathBuf::from("f.txt"));
+        /// }
+        /// ```
+        path: PathBuf,
+        /// The entry that is not a positive integer.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// # use mpatch::test_utils::CorpusError;
+        /// # use std::path::PathBuf;
+        /// # let err = CorpusError::InvalidHunkIndex { path: PathBuf::from("f.txt"), value: "x".to_string() };
+        /// if let CorpusError::InvalidHunkIndex { value, .. } = err {
+        ///     assert_eq!(value, "x");
+        /// }
+        /// ```
+        value: String,
+    },
+}
+
+/// Replays a corpus case and compares the result with its expectation.
+///
+/// The input diff is parsed with [`parse_auto()`] and applied in memory with
+/// [`apply_patch_to_content()`], so no files are touched.
+///
+/// # Arguments
+///
+/// * `case` - The case to replay.
+/// * `options` - The options to apply the patch with.
+///
+/// # Returns
+///
+/// [`CaseOutcome::Passed`] if `mpatch` behaved as the case expects, or a
+/// description of the difference otherwise.
+///
+/// # Examples
+///
+/// ```
+/// use mpatch::test_utils::{run_case, CaseOutcome, CorpusCase, Expected};
+/// use mpatch::ApplyOptions;
+///
+/// let case = CorpusCase {
+///     name: "simple".to_string(),
+///     input_diff: "```diff\n--- a/f\n+++ b/f\n@@ -1 +1 @@\n-x\n+y\n```\n".to_string(),
+///     original: Some("x\n".to_string()),
+///     expected: Expected::Content("y\n".to_string()),
+/// };
+///
+/// assert_eq!(run_case(&case, &ApplyOptions::new()), CaseOutcome::Passed);
+/// ```
+pub fn run_case(case: &CorpusCase, options: &ApplyOptions) -> CaseOutcome {
+    let patches = match parse_auto(&case.input_diff) {
+        Ok(patches) => patches,
+        Err(e) => return CaseOutcome::ParseFailed(e.to_string()),
+    };
+    let [patch] = patches.as_slice() else {
+        return CaseOutcome::WrongPatchCount(patches.len());
+    };
+
+    let result = apply_patch_to_content(patch, case.original.as_deref(), options);
+    let failed: Vec<usize> = result
+        .report
+        .failures()
+        .iter()
+        .map(|failure| failure.hunk_index)
+        .collect();
+
+    match &case.expected {
+        Expected::Content(expected) => {
+            if !failed.is_empty() {
+                CaseOutcome::UnexpectedFailures {
+                    expected: Vec::new(),
+                    actual: failed,
+                }
+            } else if result.new_content != *expected {
+                CaseOutcome::ContentMismatch {
+                    expected: expected.clone(),
+                    actual: result.new_content,
+                }
+            } else {
+                CaseOutcome::Passed
+            }
+        }
+        Expected::Failure(expected) => {
+            if failed == *expected {
+                CaseOutcome::Passed
+            } else {
+                CaseOutcome::UnexpectedFailures {
+                    expected: expected.clone(),
+                    actual: failed,
+                }
+            }
+        }
+    }
+}
+
+/// Loads every case from a corpus directory.
+///
+/// Each subdirectory of `dir` is one case, laid out as described in the
+/// [module documentation](self). Cases are returned sorted by name, and
+/// entries that are not directories are ignored.
+///
+/// # Arguments
+///
+/// * `dir` - The corpus directory.
+///
+/// # Returns
+///
+/// The cases found in `dir`.
+///
+/// # Errors
+///
+/// Returns a [`CorpusError`] if a directory or required file cannot be read,
+/// if a case does not have exactly one expectation file, or if
+/// `expected_failures.txt` contains anything other than whitespace- or
+/// comma-separated hunk indices.
+///
+/// # Examples
+///
+/// ```
+/// use mpatch::test_utils::{load_corpus, Expected};
+/// use std::fs;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let dir = tempfile::tempdir()?;
+/// let case_dir = dir.path().join("simple");
+/// fs::create_dir(&case_dir)?;
+/// fs::write(case_dir.join("case.md"), "--- a/f\n+++ b/f\n@@ -1 +1 @@\n-x\n+y\n")?;
+/// fs::write(case_dir.join("original.txt"), "x\n")?;
+/// fs::write(case_dir.join("expected.txt"), "y\n")?;
+///
+/// let cases = load_corpus(dir.path())?;
+///
+/// assert_eq!(cases.len(), 1);
+/// assert_eq!(cases[0].name, "simple");
+/// assert_eq!(cases[0].expected, Expected::Content("y\n".to_string()));
+/// # Ok(())
+/// # }
+/// ```
+pub fn load_corpus(dir: &Path) -> Result<Vec<CorpusCase>, CorpusError> {
+    let io_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source| CorpusError::Io { path, source }
+    };
+
+    let mut case_dirs = Vec::new();
+    for entry in fs::read_dir(dir).map_err(io_error(dir))? {
+        let path = entry.map_err(io_error(dir))?.path();
+        if path.is_dir() {
+            case_dirs.push(path);
+        }
+    }
+    case_dirs.sort();
+
+    case_dirs
+        .iter()
+        .map(|case_dir| load_case(case_dir))
+        .collect()
+}
+
+/// Loads a single case directory. See [`load_corpus()`].
+fn load_case(case_dir: &Path) -> Result<CorpusCase, CorpusError> {
+    let read = |name: &str| -> Result<Option<String>, CorpusError> {
+        let path = case_dir.join(name);
+        match fs::read_to_string(&path) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(source) => Err(CorpusError::Io { path, source }),
+        }
+    };
+
+    let case_path = case_dir.join("case.md");
+    let input_diff = read("case.md")?.ok_or_else(|| CorpusError::Io {
+        path: case_path,
+        source: std::io::ErrorKind::NotFound.into(),
+    })?;
+    let original = read("original.txt")?;
+
+    let expected = match (read("expected.txt")?, read("expected_failures.txt")?) {
+        (Some(content), None) => Expected::Content(content),
+        (None, Some(list)) => {
+            let path = case_dir.join("expected_failures.txt");
+            let indices = list
+                .split(|c: char| c.is_whitespace() || c == ',')
+                .filter(|value| !value.is_empty())
+                .map(|value| match value.parse::<usize>() {
+                    Ok(index) if index > 0 => Ok(index),
+                    _ => Err(CorpusError::InvalidHunkIndex {
+                        path: path.clone(),
+                        value: value.to_string(),
+                    }),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Expected::Failure(indices)
+        }
+        _ => return Err(CorpusError::AmbiguousExpectation(case_dir.to_path_buf())),
+    };
+
+    let name = case_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    Ok(CorpusCase {
+        name,
+        input_diff,
+        original,
+        expected,
+    })
+}
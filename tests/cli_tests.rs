@@ -0,0 +1,424 @@
+use assert_cmd::cargo::cargo_bin_cmd;
+use indoc::indoc;
+use serde_json::Value;
+use std::fs;
+use tempfile::tempdir;
+
+const DIFF: &str = indoc! {"
+    --- a/greet.txt
+    +++ b/greet.txt
+    @@ -1,3 +1,3 @@
+     hello
+    -world
+    +mpatch
+     bye
+"};
+
+#[test]
+fn test_cli_reads_patch_from_stdin() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("greet.txt"), "hello\nworld\nbye\n").unwrap();
+
+    cargo_bin_cmd!("mpatch")
+        .arg("-")
+        .arg(dir.path())
+        .write_stdin(DIFF)
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(dir.path().join("greet.txt")).unwrap(),
+        "hello\nmpatch\nbye\n"
+    );
+}
+
+#[test]
+fn test_cli_single_file_writes_result_to_stdout() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("greet.txt");
+    fs::write(&file, "hello\nworld\nbye\n").unwrap();
+
+    // The patch comes from stdin and the file from --file.
+    cargo_bin_cmd!("mpatch")
+        .arg("-")
+        .arg("--file")
+        .arg(&file)
+        .write_stdin(DIFF)
+        .assert()
+        .success()
+        .stdout("hello\nmpatch\nbye\n");
+    assert_eq!(fs::read_to_string(&file).unwrap(), "hello\nworld\nbye\n");
+
+    // The patch comes from a file and the content from stdin.
+    let patch_file = dir.path().join("fix.diff");
+    fs::write(&patch_file, DIFF).unwrap();
+    cargo_bin_cmd!("mpatch")
+        .arg(&patch_file)
+        .args(["--file", "-", "--output", "-"])
+        .write_stdin("hello\nworld\nbye\n")
+        .assert()
+        .success()
+        .stdout("hello\nmpatch\nbye\n");
+
+    // --output writes to a file instead.
+    let output = dir.path().join("out.txt");
+    cargo_bin_cmd!("mpatch")
+        .arg(&patch_file)
+        .arg("--file")
+        .arg(&file)
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success()
+        .stdout("");
+    assert_eq!(fs::read_to_string(&output).unwrap(), "hello\nmpatch\nbye\n");
+}
+
+#[test]
+fn test_cli_single_file_fails_on_partial_apply() {
+    let dir = tempdir().unwrap();
+    let patch_file = dir.path().join("fix.diff");
+    fs::write(&patch_file, DIFF).unwrap();
+
+    cargo_bin_cmd!("mpatch")
+        .arg(&patch_file)
+        .args(["--file", "-", "--fuzz-factor", "0"])
+        .write_stdin("something\nelse\nentirely\n")
+        .assert()
+        .code(2)
+        .stdout("");
+
+    // Both inputs cannot come from stdin.
+    cargo_bin_cmd!("mpatch")
+        .args(["-", "--file", "-"])
+        .write_stdin(DIFF)
+        .assert()
+        .code(1);
+
+    // The patch's paths are not used, so stripping them is a usage error
+    // rather than silently ignored.
+    let assert = cargo_bin_cmd!("mpatch")
+        .arg(&patch_file)
+        .args(["--file", "-", "-p", "1"])
+        .write_stdin("hello\nworld\nbye\n")
+        .assert()
+        .failure()
+        .stdout("");
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("--strip"), "{stderr}");
+}
+
+#[test]
+fn test_cli_very_verbose_shows_closest_candidate_for_failed_hunk() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("greet.txt"), "hello\nword\nbye\n").unwrap();
+
+    let assert = cargo_bin_cmd!("mpatch")
+        .arg("-")
+        .arg(dir.path())
+        .args(["--fuzz-factor", "0.99", "-vv"])
+        .write_stdin(DIFF)
+        .assert();
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr).into_owned();
+
+    assert!(stderr.contains("Hunk 1 came closest to matching at line 1"));
+    assert!(stderr.contains("-world"));
+    assert!(stderr.contains("+word"));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("greet.txt")).unwrap(),
+        "hello\nword\nbye\n"
+    );
+}
+
+/// A batch where `greet.txt` applies, one hunk of `list.txt` fails, and
+/// `missing.txt` does not exist.
+const MIXED_DIFF: &str = indoc! {"
+    --- a/greet.txt
+    +++ b/greet.txt
+    @@ -1,3 +1,3 @@
+     hello
+    -world
+    +mpatch
+     bye
+    --- a/src/list.txt
+    +++ b/src/list.txt
+    @@ -1,3 +1,3 @@
+     one
+    -two
+    +TWO
+     three
+    @@ -10,3 +10,3 @@
+     nine
+    -ten
+    +TEN
+     eleven
+    --- a/missing.txt
+    +++ b/missing.txt
+    @@ -1,1 +1,1 @@
+    -gone
+    +here
+"};
+
+#[test]
+fn test_cli_json_report_and_reject_dir_for_mixed_batch() {
+    let dir = tempdir().unwrap();
+    let rejects = tempdir().unwrap();
+    fs::write(dir.path().join("greet.txt"), "hello\nworld\nbye\n").unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(dir.path().join("src/list.txt"), "one\ntwo\nthree\n").unwrap();
+
+    let assert = cargo_bin_cmd!("mpatch")
+        .arg("-")
+        .arg(dir.path())
+        .args([
+            "--fuzz-factor",
+            "0",
+            "--report-format",
+            "json",
+            "--reject-dir",
+        ])
+        .arg(rejects.path())
+        .write_stdin(MIXED_DIFF)
+        .assert()
+        .code(3);
+    let report: Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+
+    assert_eq!(report["status"], "error");
+    assert_eq!(report["exit_code"], 3);
+    let files = report["files"].as_array().unwrap();
+    assert_eq!(files.len(), 3);
+
+    assert_eq!(files[0]["path"], "greet.txt");
+    assert_eq!(files[0]["status"], "applied");
+    assert_eq!(files[0]["written"], true);
+    let hunks = files[0]["report"]["hunk_results"].as_array().unwrap();
+    assert_eq!(hunks[0]["Applied"]["match_type"], "Exact");
+    assert!(files[0]["error"].is_null());
+
+    assert_eq!(files[1]["status"], "partial");
+    let hunks = files[1]["report"]["hunk_results"].as_array().unwrap();
+    assert!(hunks[0]["Applied"].is_object());
+    assert_eq!(hunks[1]["Failed"], "ContextNotFound");
+    let reject_file = rejects.path().join("src/list.txt.rej");
+    assert_eq!(
+        files[1]["reject_file"],
+        reject_file.to_string_lossy().as_ref()
+    );
+    let rejected = fs::read_to_string(&reject_file).unwrap();
+    assert!(rejected.contains("+TEN"));
+    assert!(!rejected.contains("+TWO"));
+
+    assert_eq!(files[2]["status"], "error");
+    assert!(files[2]["error"].as_str().unwrap().contains("missing.txt"));
+    // A file that could not be patched at all has no per-hunk report.
+    assert!(files[2]["report"].is_null());
+    assert!(files[2]["reject_file"].is_null());
+
+    // The patches that could be applied still were.
+    assert_eq!(
+        fs::read_to_string(dir.path().join("greet.txt")).unwrap(),
+        "hello\nmpatch\nbye\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/list.txt")).unwrap(),
+        "one\nTWO\nthree\n"
+    );
+}
+
+#[test]
+fn test_cli_exit_codes_distinguish_failure_classes() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("greet.txt"), "hello\nworld\nbye\n").unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(dir.path().join("src/list.txt"), "one\ntwo\nthree\n").unwrap();
+
+    // Everything applies: 0, and the JSON report says so.
+    let assert = cargo_bin_cmd!("mpatch")
+        .arg("-")
+        .arg(dir.path())
+        .args(["--dry-run", "--report-format", "json"])
+        .write_stdin(DIFF)
+        .assert()
+        .code(0);
+    let report: Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert_eq!(report["status"], "clean");
+    assert_eq!(report["dry_run"], true);
+
+    // Some hunks fail: 2.
+    let partial = MIXED_DIFF.split("--- a/missing.txt").next().unwrap();
+    cargo_bin_cmd!("mpatch")
+        .arg("-")
+        .arg(dir.path())
+        .args(["--fuzz-factor", "0", "--dry-run"])
+        .write_stdin(partial)
+        .assert()
+        .code(2);
+
+    // A target file is missing: 3.
+    cargo_bin_cmd!("mpatch")
+        .arg("-")
+        .arg(dir.path())
+        .args(["--dry-run"])
+        .write_stdin(MIXED_DIFF)
+        .assert()
+        .code(3);
+
+    // Usage errors: 1.
+    cargo_bin_cmd!("mpatch")
+        .arg("-")
+        .arg(dir.path())
+        .arg("--no-such-flag")
+        .write_stdin(DIFF)
+        .assert()
+        .code(1);
+    cargo_bin_cmd!("mpatch")
+        .arg("-")
+        .arg(dir.path().join("not-a-dir"))
+        .write_stdin(DIFF)
+        .assert()
+        .code(1);
+}
+
+#[test]
+fn test_cli_backup_dir_keeps_originals_of_changed_files() {
+    let dir = tempdir().unwrap();
+    let target = dir.path().join("project");
+    fs::create_dir_all(target.join("src")).unwrap();
+    fs::write(target.join("src/greet.txt"), "hello\nworld\nbye\n").unwrap();
+    let backup = dir.path().join("backup");
+    let diff = DIFF
+        .replace("a/greet.txt", "a/src/greet.txt")
+        .replace("b/greet.txt", "b/src/greet.txt");
+
+    cargo_bin_cmd!("mpatch")
+        .arg("--backup-dir")
+        .arg(&backup)
+        .arg("-")
+        .arg(&target)
+        .write_stdin(diff.clone())
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(target.join("src/greet.txt")).unwrap(),
+        "hello\nmpatch\nbye\n"
+    );
+    assert_eq!(
+        fs::read_to_string(backup.join("src/greet.txt")).unwrap(),
+        "hello\nworld\nbye\n"
+    );
+
+    // Applying it again changes nothing, so the backup is left alone.
+    cargo_bin_cmd!("mpatch")
+        .arg("--backup-dir")
+        .arg(&backup)
+        .arg("-")
+        .arg(&target)
+        .write_stdin(diff)
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(backup.join("src/greet.txt")).unwrap(),
+        "hello\nworld\nbye\n"
+    );
+}
+
+#[test]
+fn test_cli_interactive_applies_only_accepted_hunks() {
+    let dir = tempdir().unwrap();
+    let target = dir.path().join("list.txt");
+    fs::write(&target, "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\n").unwrap();
+    let patch_file = dir.path().join("fix.diff");
+    fs::write(
+        &patch_file,
+        indoc! {"
+            --- a/list.txt
+            +++ b/list.txt
+            @@ -1,3 +1,3 @@
+             one
+            -two
+            +TWO
+             three
+            @@ -6,3 +6,3 @@
+             six
+            -seven
+            +SEVEN
+             eight
+        "},
+    )
+    .unwrap();
+
+    let assert = cargo_bin_cmd!("mpatch")
+        .arg("--interactive")
+        .arg(&patch_file)
+        .arg(dir.path())
+        .env("MPATCH_ASSUME_TTY", "1")
+        .write_stdin("y\nn\n")
+        .assert()
+        .success();
+    // The review goes to stderr, so that stdout only holds the report.
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("# hunk 1/2: would apply with exact match"));
+    assert!(stderr.contains("[y]es / [n]o / [a]ll for file / [q]uit"));
+    assert!(!stdout.contains("[y]es"));
+    assert!(!stdout.contains("# hunk"));
+    assert_eq!(
+        fs::read_to_string(&target).unwrap(),
+        "one\nTWO\nthree\nfour\nfive\nsix\nseven\neight\n"
+    );
+
+    // Quitting leaves the file being reviewed untouched, even the hunks
+    // already accepted.
+    fs::write(&target, "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\n").unwrap();
+    cargo_bin_cmd!("mpatch")
+        .arg("--interactive")
+        .arg(&patch_file)
+        .arg(dir.path())
+        .env("MPATCH_ASSUME_TTY", "1")
+        .write_stdin("y\nq\n")
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&target).unwrap(),
+        "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\n"
+    );
+
+    // Without a terminal to ask on, it refuses to start.
+    let assert = cargo_bin_cmd!("mpatch")
+        .arg("--interactive")
+        .arg(&patch_file)
+        .arg(dir.path())
+        .env_remove("MPATCH_ASSUME_TTY")
+        .write_stdin("y\ny\n")
+        .assert()
+        .code(1);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("needs a terminal"));
+    assert!(stderr.contains("MPATCH_ASSUME_TTY"));
+    assert_eq!(
+        fs::read_to_string(&target).unwrap(),
+        "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\n"
+    );
+
+    // A byte-order mark and CRLF endings are handled as when applying, so
+    // the first hunk is still shown as an exact match.
+    fs::write(
+        &target,
+        "\u{feff}one\r\ntwo\r\nthree\r\nfour\r\nfive\r\nsix\r\nseven\r\neight\r\n",
+    )
+    .unwrap();
+    let assert = cargo_bin_cmd!("mpatch")
+        .arg("--interactive")
+        .arg(&patch_file)
+        .arg(dir.path())
+        .env("MPATCH_ASSUME_TTY", "1")
+        .write_stdin("y\ny\n")
+        .assert()
+        .success();
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("# hunk 1/2: would apply with exact match"));
+    assert_eq!(
+        fs::read_to_string(&target).unwrap(),
+        "\u{feff}one\r\nTWO\r\nthree\r\nfour\r\nfive\r\nsix\r\nSEVEN\r\neight\r\n"
+    );
+}
@@ -1,13 +1,36 @@
 use indoc::indoc;
 use mpatch::{
-    apply_hunk_to_lines, apply_patch_to_file, apply_patch_to_lines, apply_patches_to_dir,
-    detect_patch, find_hunk_location, find_hunk_location_in_lines, invert_patches, parse_auto,
-    parse_diffs, parse_patches, parse_patches_from_lines, patch_content_str,
-    try_apply_patch_to_content, try_apply_patch_to_file, try_apply_patch_to_lines, ApplyOptions,
-    DefaultHunkFinder, Hunk, HunkApplyError, HunkApplyStatus, HunkFinder, HunkLocation, MatchType,
-    ParseError, Patch, PatchError, PatchFormat, StrictApplyError,
+    apply_file_replacements_to_dir, apply_hunk_at, apply_hunk_to_lines, apply_hunk_to_lines_with,
+    apply_patch_in_place, apply_patch_to_content, apply_patch_to_content_in_region,
+    apply_patch_to_content_with, apply_patch_to_file, apply_patch_to_lines,
+    apply_patch_to_lines_with, apply_patch_to_output_dir, apply_patch_to_path,
+    apply_patch_to_source, apply_patches_to_dir, apply_patches_to_dir_with,
+    apply_patches_to_dir_with_overrides, apply_patches_to_output_dir, coalesce_patches,
+    describe_patches, detect_patch, ensure_path_is_safe, explain_hunk_failure,
+    find_hunk_candidates, find_hunk_location, find_hunk_location_in_lines, invert_patches,
+    parse_auto, parse_auto_from_reader, parse_auto_validated, parse_auto_with,
+    parse_auto_with_report, parse_conflict_markers, parse_conflict_markers_for_file,
+    parse_conflict_markers_with_context, parse_context_diff, parse_diffs, parse_diffs_with,
+    parse_diffs_with_context, parse_file_blocks, parse_openai_patch, parse_patches,
+    parse_patches_from_lines, parse_patches_from_reader, parse_patches_strict, parse_patches_with,
+    parse_search_replace, parse_single_patch, patch_content_str, patches_from_dirs,
+    patches_from_dirs_with_report, patches_to_string, preview_patch_to_content, rebase_patch,
+    try_apply_patch_to_content, try_apply_patch_to_file, try_apply_patch_to_lines,
+    try_apply_patch_to_path, validate_relative_path, write_patches, ApplyOptions, BackupMode,
+    BatchOptions, BinaryFilePolicy, BinaryHunkKind, ConcurrentModificationPolicy,
+    DefaultHunkFinder, DiffParseOptions, DiffStyle, DirDiffOptions, EncodingPolicy, FailureAction,
+    FenceInfo, FileChange, FileChangeKind, FileReplacement, FuzzFactor, HeaderStyle, Hunk,
+    HunkApplier, HunkApplyError, HunkApplyStatus, HunkChangeKind, HunkFinder, HunkLocation,
+    InvalidOptionsError, LineEndingPolicy, LineSink, LineSource, LintSeverity,
+    MarkdownParseOptions, MarkdownPatchScanner, MatchType, MergeError, ParseError, ParseWarning,
+    Patch, PatchError, PatchFormat, PatchLint, PatchLintKind, PatchStyle, PathInferenceOptions,
+    PathResolution, PathStrip, PathValidationError, PreimagePolicy, ReaderParseError, ScoringMode,
+    SearchBudget, SearchStrategy, StrictApplyError, UndoError, VirtualWorkspace, WhitespaceMode,
 };
+use std::collections::HashMap;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tempfile::tempdir;
 
 #[test]
@@ -129,6 +152,121 @@ fn test_parse_accepts_all_code_blocks() {
     }
 }
 
+#[test]
+fn test_fence_info_parse() {
+    let info = FenceInfo::parse("rust, diff title=\"src/main.rs\" {lines='1-5'}");
+    assert_eq!(info.language.as_deref(), Some("rust"));
+    assert!(info.is_diff_labeled);
+    assert_eq!(
+        info.attributes,
+        vec![
+            ("title".to_string(), "src/main.rs".to_string()),
+            ("lines".to_string(), "1-5".to_string()),
+        ]
+    );
+
+    assert_eq!(FenceInfo::parse(""), FenceInfo::default());
+    assert_eq!(
+        FenceInfo::parse("{.diff}").language.as_deref(),
+        Some("diff")
+    );
+    for labeled in [
+        "diff",
+        "patch",
+        " Diff ",
+        "diff,rust",
+        "a,b,PATCH",
+        "{.patch}",
+    ] {
+        assert!(FenceInfo::parse(labeled).is_diff_labeled, "{}", labeled);
+    }
+    for unlabeled in [
+        "rust",
+        "dif",
+        "patch-work",
+        "mydiff",
+        "different",
+        "patchwork",
+        "diff=1",
+    ] {
+        assert!(
+            !FenceInfo::parse(unlabeled).is_diff_labeled,
+            "{}",
+            unlabeled
+        );
+    }
+}
+
+#[test]
+fn test_detect_markdown_agrees_with_parse_auto() {
+    let full = "--- a/file.txt\n+++ b/file.txt\n@@ -1 +1 @@\n-a\n+b\n";
+    let conflict = "<<<<<<< HEAD\na\n=======\nb\n>>>>>>> feature\n";
+    let headerless = "@@ -1 +1 @@\n-a\n+b\n";
+    let prose = "Title\n=====\n\n--- \nnot a diff\n";
+    let mut corpus = Vec::new();
+    for fence in [
+        "```diff",
+        "```patch",
+        "```rust",
+        "```",
+        "```patchwork",
+        "```mydiff",
+        "````text",
+    ] {
+        let close = &fence[..fence.chars().take_while(|&c| c == '`').count()];
+        for body in [full, conflict, headerless, prose] {
+            corpus.push(format!(
+                "Intro text.\n{}\n{}{}\nOutro.\n",
+                fence, body, close
+            ));
+        }
+    }
+    // A patch nested inside an example block does not count.
+    corpus.push(format!("````markdown\n```diff\n{}```\n````\n", full));
+
+    for doc in &corpus {
+        let detected = detect_patch(doc);
+        let parsed = parse_diffs(doc).unwrap_or_default();
+        if detected == PatchFormat::Markdown {
+            let patches = parse_auto(doc).unwrap();
+            assert!(
+                !patches.is_empty(),
+                "detected Markdown but found nothing:\n{}",
+                doc
+            );
+        }
+        if !parsed.is_empty() {
+            assert_eq!(
+                detected,
+                PatchFormat::Markdown,
+                "parse_diffs found a patch in:\n{}",
+                doc
+            );
+        }
+    }
+
+    // Labels alone no longer decide: an empty `diff` block is not a patch,
+    // and a `patchwork` block with a complete header is.
+    assert_eq!(
+        detect_patch("```diff\nsome notes\n```"),
+        PatchFormat::Unknown
+    );
+    let (patches, report) = parse_auto_with_report("```diff\n@@ -1 +1 @@\n-a\n+b\n```").unwrap();
+    assert!(patches.is_empty());
+    assert_eq!(
+        report.warnings,
+        vec![ParseWarning::DiffBlockWithoutHeader { line: 1 }]
+    );
+    assert_eq!(
+        detect_patch(&format!("```patchwork\n{}```", full)),
+        PatchFormat::Markdown
+    );
+    // Prose in an unlabeled block is skipped rather than parsed.
+    let (patches, report) = parse_auto_with_report(&format!("```text\n{}```", prose)).unwrap();
+    assert!(patches.is_empty());
+    assert!(report.warnings.is_empty());
+}
+
 #[test]
 fn test_parse_multiple_diff_blocks() {
     let diff = indoc! {r#"
@@ -997,8 +1135,17 @@ fn test_file_creation_empty_content_does_not_create_file() {
             lines: vec![], // No lines = empty match, empty replace
             old_start_line: Some(0),
             new_start_line: Some(0),
+            raw_header: None,
+            old_count: None,
+            new_count: None,
         }],
         ends_with_newline: false,
+        index_lines: vec![],
+        binary: None,
+        new_file_path: None,
+        deletes_file: false,
+        path_inferred: false,
+        source_block: None,
     };
 
     let options = ApplyOptions::exact();
@@ -1053,6 +1200,7 @@ fn test_fuzzy_deletion_removes_file() {
     let options = ApplyOptions {
         dry_run: false,
         fuzz_factor: 0.3,
+        ..Default::default()
     };
     let result = apply_patch_to_file(patch, dir.path(), options).unwrap();
 
@@ -1063,6 +1211,42 @@ fn test_fuzzy_deletion_removes_file() {
     );
 }
 
+#[test]
+fn test_dev_null_deletion_removes_file_and_prunes_dirs() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("docs/old")).unwrap();
+    fs::write(dir.path().join("docs/keep.txt"), "stays\n").unwrap();
+    let file_path = dir.path().join("docs/old/x.txt");
+    fs::write(&file_path, "one\ntwo\n").unwrap();
+
+    let diff = indoc! {"
+        --- a/docs/old/x.txt
+        +++ /dev/null
+        @@ -1,2 +0,0 @@
+        -one
+        -two
+    "};
+    let patch = parse_single_patch(diff).unwrap();
+    assert!(patch.deletes_file);
+    assert!(patch.to_string().contains("+++ /dev/null\n"));
+
+    // A dry run shows the deletion without touching the file.
+    let preview = apply_patch_to_file(&patch, dir.path(), ApplyOptions::dry_run()).unwrap();
+    assert!(preview.diff.unwrap().contains("+++ /dev/null"));
+    assert!(!preview.removed);
+    assert!(file_path.exists());
+
+    let options = ApplyOptions::new().with_prune_empty_dirs(true);
+    let result = apply_patch_to_file(&patch, dir.path(), options).unwrap();
+    assert!(result.report.all_applied_cleanly());
+    assert!(result.removed);
+    assert!(!file_path.exists());
+    // The emptied directory is pruned, but not its non-empty parent.
+    assert!(!dir.path().join("docs/old").exists());
+    assert!(dir.path().join("docs/keep.txt").exists());
+}
+
 #[test]
 fn test_creation_of_empty_file_is_skipped() {
     // If we try to create a file with empty content, and it doesn't exist,
@@ -1185,6 +1369,7 @@ fn test_fuzzy_match_succeeds() {
     let options = ApplyOptions {
         dry_run: false,
         fuzz_factor: 0.5,
+        ..Default::default()
     };
     let result = apply_patch_to_file(patch, dir.path(), options).unwrap();
 
@@ -1343,6 +1528,7 @@ fn test_ambiguous_fuzzy_match_fails() {
     let options = ApplyOptions {
         dry_run: false,
         fuzz_factor: 0.5,
+        ..Default::default()
     };
     let result = apply_patch_to_file(patch, dir.path(), options).unwrap();
 
@@ -1352,7 +1538,7 @@ fn test_ambiguous_fuzzy_match_fails() {
     );
     assert!(matches!(
         result.report.hunk_results[0],
-        HunkApplyStatus::Failed(HunkApplyError::AmbiguousFuzzyMatch(_))
+        HunkApplyStatus::Failed(HunkApplyError::AmbiguousFuzzyMatch { .. })
     ));
     // Ensure file is unchanged
     let content = fs::read_to_string(file_path).unwrap();
@@ -1676,6 +1862,148 @@ fn test_apply_patch_to_lines() {
     assert!(result.report.all_applied_cleanly());
 }
 
+#[test]
+fn test_apply_patch_in_place_matches_content_application() {
+    let original = "fn main() {\n    let x = 1;\n    println!(\"{}\", x);\n}\n\nfn other() {}\n";
+    let diff = indoc! {r#"
+        ```diff
+        --- a/main.rs
+        +++ b/main.rs
+        @@ -1,4 +1,5 @@
+         fn main() {
+        -    let x = 1;
+        +    let x = 2;
+        +    let y = x * 2;
+             println!("{}", x);
+         }
+        @@ -6 +7 @@
+        -fn missing() {}
+        +fn replaced() {}
+        ```
+    "#};
+    let patch = parse_single_patch(diff).unwrap();
+    let options = ApplyOptions::exact();
+
+    let mut lines: Vec<String> = original.lines().map(String::from).collect();
+    let report = apply_patch_in_place(&patch, &mut lines, &options);
+    let expected = apply_patch_to_content(&patch, Some(original), &options);
+
+    assert_eq!(report, expected.report);
+    assert!(report.has_failures());
+    assert_eq!(lines.join("\n") + "\n", expected.new_content);
+    // The failed second hunk leaves its region untouched.
+    assert_eq!(lines.last().unwrap(), "fn other() {}");
+}
+
+#[test]
+fn test_apply_patch_to_content_in_region_picks_scoped_table() {
+    let original = indoc! {r#"
+        [package]
+        name = "demo"
+
+        [dependencies.alpha]
+        version = "1.0"
+        default-features = false
+
+        [dependencies.beta]
+        version = "1.0"
+        default-features = false
+
+        [dependencies.gamma]
+        version = "1.0"
+        default-features = false
+    "#};
+    let diff = indoc! {r#"
+        ```diff
+        --- a/Cargo.toml
+        +++ b/Cargo.toml
+        @@ -1,2 +1,3 @@
+         version = "1.0"
+         default-features = false
+        +features = ["serde"]
+        ```
+    "#};
+    let patch = parse_single_patch(diff).unwrap();
+    let options = ApplyOptions::exact();
+
+    // Searching the whole file cannot tell the three tables apart, so the line
+    // hint settles on the first one.
+    let whole = apply_patch_to_content(&patch, Some(original), &options);
+    match &whole.report.hunk_results[0] {
+        HunkApplyStatus::Applied { location, .. } => assert_eq!(location.start_index, 4),
+        other => panic!("unexpected status: {:?}", other),
+    }
+
+    // A caller-side section search finds the `beta` table.
+    let lines: Vec<&str> = original.lines().collect();
+    let start = lines
+        .iter()
+        .position(|l| *l == "[dependencies.beta]")
+        .unwrap();
+    let end = lines[start + 1..]
+        .iter()
+        .position(|l| l.starts_with('['))
+        .map_or(lines.len(), |i| start + 1 + i);
+
+    let result = apply_patch_to_content_in_region(&patch, original, start..end, &options);
+    assert!(result.report.all_applied_cleanly());
+    match &result.report.hunk_results[0] {
+        HunkApplyStatus::Applied { location, .. } => {
+            assert_eq!(location.start_index, 8);
+            assert_eq!(location.length, 2);
+        }
+        other => panic!("unexpected status: {:?}", other),
+    }
+    let expected = original.replace(
+        "[dependencies.beta]\nversion = \"1.0\"\ndefault-features = false\n",
+        "[dependencies.beta]\nversion = \"1.0\"\ndefault-features = false\nfeatures = [\"serde\"]\n",
+    );
+    assert_eq!(result.new_content, expected);
+    assert_eq!(result.new_content.matches("features = [").count(), 1);
+}
+
+#[test]
+fn test_apply_patch_to_content_in_region_rejects_hunks_outside_region() {
+    let original = "a\nb\nc\nd\ne\n";
+    let diff = indoc! {r#"
+        ```diff
+        --- a/f.txt
+        +++ b/f.txt
+        @@ -2,3 +2,3 @@
+         b
+        -c
+        +C
+         d
+        ```
+    "#};
+    let patch = parse_single_patch(diff).unwrap();
+
+    // The hunk needs three lines but the region only has two.
+    let result = apply_patch_to_content_in_region(&patch, original, 1..3, &ApplyOptions::new());
+    assert_eq!(
+        result.report.hunk_results,
+        vec![HunkApplyStatus::Failed(HunkApplyError::OutsideRegion {
+            region: 1..3
+        })]
+    );
+    assert_eq!(result.new_content, original);
+
+    // An override pointing outside the region is rejected as well.
+    let mut options = ApplyOptions::new();
+    options.hunk_location_overrides.insert(1, 0);
+    let result = apply_patch_to_content_in_region(&patch, original, 1..5, &options);
+    assert!(matches!(
+        result.report.hunk_results[0],
+        HunkApplyStatus::Failed(HunkApplyError::OutsideRegion { .. })
+    ));
+    assert_eq!(result.new_content, original);
+
+    // An override inside the region is given in whole-content coordinates.
+    options.hunk_location_overrides.insert(1, 1);
+    let result = apply_patch_to_content_in_region(&patch, original, 1..5, &options);
+    assert_eq!(result.new_content, "a\nb\nC\nd\ne\n");
+}
+
 #[test]
 fn test_apply_hunk_to_lines_in_place() {
     let mut original_lines = vec![
@@ -1798,6 +2126,7 @@ fn test_fuzzy_match_below_threshold_fails() {
     let options = ApplyOptions {
         dry_run: false,
         fuzz_factor: 0.9,
+        ..Default::default()
     };
     let result = apply_patch_to_file(patch, dir.path(), options).unwrap();
 
@@ -2220,7 +2549,10 @@ fn test_path_traversal_with_absolute_path_is_blocked() {
     let options = ApplyOptions::exact();
     let result = apply_patch_to_file(patch, dir.path(), options);
 
-    assert!(matches!(result, Err(PatchError::PathTraversal(_))));
+    assert!(matches!(
+        result,
+        Err(PatchError::AbsolutePathRejected { .. })
+    ));
 }
 
 #[test]
@@ -2981,6 +3313,9 @@ fn test_hunk_semantic_helpers() {
         ],
         old_start_line: Some(1),
         new_start_line: Some(1),
+        raw_header: None,
+        old_count: None,
+        new_count: None,
     };
 
     assert_eq!(hunk.context_lines(), vec!["context 1", "context 2"]);
@@ -3070,6 +3405,54 @@ fn test_apply_options_builder() {
     assert_eq!(default_options.fuzz_factor, 0.7);
 }
 
+#[test]
+fn test_fuzz_factor_is_validated_or_clamped() {
+    // The builder's `try_build` rejects out-of-range values and NaN.
+    for bad in [1.5, -0.3, f32::NAN] {
+        let result = ApplyOptions::builder().fuzz_factor(bad).try_build();
+        assert!(matches!(
+            result,
+            Err(InvalidOptionsError::FuzzFactorOutOfRange(_))
+        ));
+        assert!(FuzzFactor::new(bad).is_err());
+    }
+    let options = ApplyOptions::builder()
+        .fuzz_factor(0.5)
+        .try_build()
+        .unwrap();
+    assert_eq!(options.fuzz_factor, 0.5);
+
+    // `build`, the fluent setter and `From<f32>` clamp instead.
+    assert_eq!(
+        ApplyOptions::builder().fuzz_factor(1.5).build().fuzz_factor,
+        1.0
+    );
+    assert_eq!(ApplyOptions::new().with_fuzz_factor(-0.3).fuzz_factor, 0.0);
+    assert_eq!(
+        ApplyOptions::new().with_fuzz_factor(f32::NAN).fuzz_factor,
+        0.0
+    );
+    let clamped: FuzzFactor = 2.0.into();
+    assert_eq!(f32::from(clamped), 1.0);
+
+    // Plain `f32` struct literals still compile, and the finder clamps them,
+    // so a negative factor disables fuzzy matching instead of accepting anything.
+    let options = ApplyOptions {
+        fuzz_factor: -0.3,
+        ..Default::default()
+    };
+    let hunk = Hunk {
+        lines: vec!["-something else entirely".to_string(), "+x".to_string()],
+        old_start_line: None,
+        new_start_line: None,
+        raw_header: None,
+        old_count: None,
+        new_count: None,
+    };
+    let result = DefaultHunkFinder::new(&options).find_location(&hunk, &["alpha", "beta"]);
+    assert_eq!(result.unwrap_err(), HunkApplyError::ContextNotFound);
+}
+
 #[test]
 fn test_apply_options_convenience_constructors() {
     // Test ApplyOptions::new()
@@ -3091,7 +3474,7 @@ fn test_apply_options_fluent_methods() {
     assert_eq!(options.fuzz_factor, 0.9);
 
     // Test that it returns a modified copy
-    let options2 = options.with_dry_run(false);
+    let options2 = options.clone().with_dry_run(false);
     assert!(options.dry_run, "Original options should be unchanged");
     assert!(
         !options2.dry_run,
@@ -3102,7 +3485,7 @@ fn test_apply_options_fluent_methods() {
         "Other fields should be preserved"
     );
 
-    let options3 = options2.with_fuzz_factor(0.1);
+    let options3 = options2.clone().with_fuzz_factor(0.1);
     assert_eq!(
         options2.fuzz_factor, 0.9,
         "Original options should be unchanged"
@@ -3135,6 +3518,45 @@ fn test_patch_from_texts_no_change() {
     assert!(patch.hunks.is_empty());
 }
 
+#[test]
+fn test_patch_with_context_applies_like_original() {
+    let old_text: String = (1..=30).map(|i| format!("line {i}\n")).collect();
+    let new_text = old_text
+        .replace("line 4\n", "line four\n")
+        .replace("line 7\n", "line 7\nline 7b\n")
+        .replace("line 20\n", "")
+        .replace("line 29\n", "line twenty-nine\n");
+    let patch = Patch::from_texts("file.txt", &old_text, &new_text, 5).unwrap();
+    assert_eq!(patch.hunks.len(), 2);
+
+    for max_context in 0..=6 {
+        let trimmed = patch.with_context(max_context);
+        for (hunk, original) in trimmed.hunks.iter().zip(&patch.hunks) {
+            let leading = hunk.lines.iter().take_while(|l| l.starts_with(' ')).count();
+            let trailing = hunk
+                .lines
+                .iter()
+                .rev()
+                .take_while(|l| l.starts_with(' '))
+                .count();
+            assert!(leading <= max_context && trailing <= max_context);
+            // The context between the two changes of the first hunk is kept.
+            assert_eq!(hunk.removed_lines(), original.removed_lines());
+            assert_eq!(hunk.added_lines(), original.added_lines());
+        }
+        assert!(trimmed.hunks[0].lines.iter().any(|l| l == " line 5"));
+
+        let result = apply_patch_to_content(&trimmed, Some(&old_text), &ApplyOptions::exact());
+        assert!(result.report.all_applied_cleanly(), "context {max_context}");
+        assert_eq!(result.new_content, new_text, "context {max_context}");
+    }
+
+    let trimmed = patch.with_context(2);
+    assert_eq!(trimmed.hunks[0].old_start_line, Some(2));
+    assert_eq!(trimmed.hunks[0].new_start_line, Some(2));
+    assert_eq!(trimmed.hunks[1].old_start_line, Some(18));
+}
+
 #[test]
 fn test_patch_inversion() {
     let old_text = "line 1\nline 2\n";
@@ -3198,6 +3620,260 @@ fn test_apply_patches_to_dir() {
     assert_eq!(content2, "qux\n");
 }
 
+#[test]
+fn test_parse_and_apply_thousands_of_sections_for_one_file() {
+    let sections = 5000;
+    let content: String = (0..sections * 4).map(|i| format!("line {}\n", i)).collect();
+    let diff: String = (0..sections)
+        .map(|i| {
+            let first = i * 4;
+            format!(
+                "--- a/generated.txt\n+++ b/generated.txt\n@@ -{},3 +{},3 @@\n line {}\n-line {}\n+LINE {}\n line {}\n",
+                first + 1,
+                first + 1,
+                first,
+                first + 1,
+                first + 1,
+                first + 2
+            )
+        })
+        .collect();
+
+    let patches = parse_patches(&diff).unwrap();
+    assert_eq!(patches.len(), 1);
+    assert_eq!(patches[0].hunks.len(), sections);
+
+    let result = apply_patch_to_content(&patches[0], Some(&content), &ApplyOptions::exact());
+    assert!(result.report.all_applied_cleanly());
+    let expected: String = (0..sections * 4)
+        .map(|i| {
+            if i % 4 == 1 {
+                format!("LINE {}\n", i)
+            } else {
+                format!("line {}\n", i)
+            }
+        })
+        .collect();
+    assert_eq!(result.new_content, expected);
+}
+
+#[test]
+fn test_merge_keeps_first_seen_file_order() {
+    let diff = indoc! {"
+        --- a/b.txt
+        +++ b/b.txt
+        @@ -1 +1 @@
+        -b1
+        +B1
+        --- a/a.txt
+        +++ b/a.txt
+        @@ -1 +1 @@
+        -a1
+        +A1
+        --- a/b.txt
+        +++ b/b.txt
+        @@ -2 +2 @@
+        -b2
+        +B2
+    "};
+    let patches = parse_patches(diff).unwrap();
+    let paths: Vec<_> = patches
+        .iter()
+        .map(|p| p.file_path.to_str().unwrap())
+        .collect();
+    assert_eq!(paths, vec!["b.txt", "a.txt"]);
+    assert_eq!(patches[0].hunks.len(), 2);
+    assert_eq!(patches[0].hunks[1].removed_lines(), vec!["b2"]);
+}
+
+#[test]
+fn test_line_canonicalizer_ignores_comment_drift_in_context() {
+    let original = indoc! {"
+        // Copyright 2026 Example Corp.
+        fn main() {
+            let x = 1; // TODO(alice): rename
+            run(x);
+        }
+    "};
+    let diff = indoc! {r#"
+        ```diff
+        --- a/main.rs
+        +++ b/main.rs
+        @@ -1,5 +1,5 @@
+         // Copyright 2024 Example Corp.
+         fn main() {
+             let x = 1; // TODO(bob): rename
+        -    run(x);
+        +    run(x + 1);
+         }
+        ```
+    "#};
+    let patch = parse_single_patch(diff).unwrap();
+    let strip_comments = |line: &str| {
+        let code = line.split("//").next().unwrap_or("");
+        code.split_whitespace().collect::<Vec<_>>().join(" ")
+    };
+
+    // Without a canonicalizer, exact matching fails.
+    let exact = apply_patch_to_content(&patch, Some(original), &ApplyOptions::exact());
+    assert!(exact.report.has_failures());
+
+    let options = ApplyOptions::exact().with_line_canonicalizer(strip_comments);
+    let result = apply_patch_to_content(&patch, Some(original), &options);
+
+    assert!(result.report.all_applied_cleanly());
+    assert!(matches!(
+        result.report.hunk_results[0],
+        HunkApplyStatus::Applied {
+            match_type: MatchType::ExactCanonicalized,
+            ..
+        }
+    ));
+    // The file's own comments survive; only the changed line comes from the patch.
+    assert_eq!(
+        result.new_content,
+        indoc! {"
+            // Copyright 2026 Example Corp.
+            fn main() {
+                let x = 1; // TODO(alice): rename
+                run(x + 1);
+            }
+        "}
+    );
+}
+
+#[test]
+fn test_batch_result_order_and_lookup_with_duplicate_paths() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "one\n").unwrap();
+
+    let diff = |path: &str, old: &str, new: &str| {
+        let text = format!("--- a/{path}\n+++ b/{path}\n@@ -1 +1 @@\n-{old}\n+{new}\n");
+        parse_patches(&text).unwrap().remove(0)
+    };
+    let patches = vec![
+        diff("a.txt", "one", "two"),
+        diff("missing.txt", "x", "y"),
+        diff("a.txt", "WRONG", "three"),
+        diff("a.txt", "two", "four"),
+    ];
+
+    let batch = apply_patches_to_dir(&patches, dir.path(), ApplyOptions::exact());
+
+    // `results[i]` always corresponds to `patches[i]`.
+    assert_eq!(batch.results.len(), patches.len());
+    for (i, entry) in batch.iter().enumerate() {
+        assert_eq!(entry.patch_index, i);
+        assert_eq!(entry.path, patches[i].file_path);
+        assert_eq!(entry.path, batch.results[i].0);
+    }
+
+    // `get` returns the first match, `get_all` every match in order.
+    let first = batch.get(Path::new("a.txt")).unwrap().as_ref().unwrap();
+    assert!(first.report.all_applied_cleanly());
+    let all = batch.get_all(Path::new("a.txt"));
+    assert_eq!(all.len(), 3);
+    assert!(all[1].as_ref().unwrap().report.has_failures());
+    assert!(all[2].as_ref().unwrap().report.all_applied_cleanly());
+    assert!(matches!(
+        batch.get(Path::new("missing.txt")),
+        Some(Err(PatchError::TargetNotFound(_)))
+    ));
+    assert!(batch.get(Path::new("other.txt")).is_none());
+    assert!(batch.get_all(Path::new("other.txt")).is_empty());
+
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.txt")).unwrap(),
+        "four\n"
+    );
+}
+
+#[test]
+fn test_batch_stops_on_first_hard_error() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "one\n").unwrap();
+    fs::write(dir.path().join("c.txt"), "three\n").unwrap();
+
+    let diff = |path: &str, old: &str, new: &str| {
+        let text = format!("--- a/{path}\n+++ b/{path}\n@@ -1 +1 @@\n-{old}\n+{new}\n");
+        parse_patches(&text).unwrap().remove(0)
+    };
+    let patches = vec![
+        diff("a.txt", "one", "uno"),
+        diff("missing.txt", "x", "y"),
+        diff("c.txt", "three", "tres"),
+    ];
+
+    let batch_options = BatchOptions {
+        stop_on_hard_error: true,
+        ..Default::default()
+    };
+    let batch = apply_patches_to_dir_with(&patches, dir.path(), ApplyOptions::new(), batch_options);
+
+    assert_eq!(batch.results.len(), 3);
+    assert!(batch.results[0].1.is_ok());
+    assert!(matches!(
+        batch.results[1].1,
+        Err(PatchError::TargetNotFound(_))
+    ));
+    assert!(matches!(
+        batch.results[2].1,
+        Err(PatchError::NotAttempted { .. })
+    ));
+    assert_eq!(batch.hard_failures().len(), 1);
+    assert_eq!(batch.skipped(), vec![&PathBuf::from("c.txt")]);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.txt")).unwrap(),
+        "uno\n"
+    );
+    // The third patch was never attempted.
+    assert_eq!(
+        fs::read_to_string(dir.path().join("c.txt")).unwrap(),
+        "three\n"
+    );
+
+    // The default options keep going, exactly like `apply_patches_to_dir`.
+    fs::write(dir.path().join("a.txt"), "one\n").unwrap();
+    let batch = apply_patches_to_dir_with(
+        &patches,
+        dir.path(),
+        ApplyOptions::new(),
+        BatchOptions::default(),
+    );
+    assert!(batch.skipped().is_empty());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("c.txt")).unwrap(),
+        "tres\n"
+    );
+}
+
+#[test]
+fn test_batch_stops_on_partial_apply() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "one\n").unwrap();
+    fs::write(dir.path().join("b.txt"), "two\n").unwrap();
+
+    let diff = |path: &str, old: &str, new: &str| {
+        let text = format!("--- a/{path}\n+++ b/{path}\n@@ -1 +1 @@\n-{old}\n+{new}\n");
+        parse_patches(&text).unwrap().remove(0)
+    };
+    let patches = vec![diff("a.txt", "WRONG", "uno"), diff("b.txt", "two", "dos")];
+
+    let batch_options = BatchOptions {
+        stop_on_partial: true,
+        ..Default::default()
+    };
+    let batch =
+        apply_patches_to_dir_with(&patches, dir.path(), ApplyOptions::exact(), batch_options);
+
+    assert!(batch.results[0].1.as_ref().unwrap().report.has_failures());
+    assert_eq!(batch.skipped(), vec![&PathBuf::from("b.txt")]);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("b.txt")).unwrap(),
+        "two\n"
+    );
+}
+
 mod ensure_path_is_safe_tests {
     use mpatch::{ensure_path_is_safe, PatchError};
     use std::fs;
@@ -3505,6 +4181,9 @@ mod fuzzy_finder_diagnostics {
             lines: hunk_match_block.iter().map(|s| format!(" {}", s)).collect(), // Assume all context lines for simplicity
             old_start_line: Some(1),
             new_start_line: Some(1),
+            raw_header: None,
+            old_count: None,
+            new_count: None,
         };
 
         let result = finder.find_location(&hunk, &target_lines.iter().collect::<Vec<_>>());
@@ -3559,6 +4238,9 @@ mod fuzzy_finder_diagnostics {
             lines: vec![" line A".to_string(), " line B".to_string()],
             old_start_line: Some(1),
             new_start_line: Some(1),
+            raw_header: None,
+            old_count: None,
+            new_count: None,
         };
 
         let target_lines = vec!["extra line", "line A", "line B"];
@@ -3779,7 +4461,7 @@ fn test_strict_apply_variants() {
         try_apply_patch_to_content(failing_patch, Some(original_content), &failing_options);
 
     assert!(failure_result_content.is_err());
-    if let Err(StrictApplyError::PartialApply { report }) = failure_result_content {
+    if let Err(StrictApplyError::PartialApply { report, .. }) = failure_result_content {
         assert!(!report.all_applied_cleanly());
         assert_eq!(report.failures().len(), 1);
         assert_eq!(report.failures()[0].hunk_index, 2);
@@ -3913,7 +4595,7 @@ mod patch_content_str_tests {
         let options = ApplyOptions::exact();
         let result = patch_content_str(diff, Some(ORIGINAL), &options);
         assert!(matches!(result, Err(OneShotError::Apply(_))));
-        if let Err(OneShotError::Apply(StrictApplyError::PartialApply { report })) = result {
+        if let Err(OneShotError::Apply(StrictApplyError::PartialApply { report, .. })) = result {
             assert!(!report.all_applied_cleanly());
         } else {
             panic!("Expected a PartialApply error");
@@ -3936,6 +4618,9 @@ fn test_patch_and_hunk_display_format() {
                 ],
                 old_start_line: Some(1),
                 new_start_line: Some(1),
+                raw_header: None,
+                old_count: None,
+                new_count: None,
             },
             Hunk {
                 lines: vec![
@@ -3945,9 +4630,18 @@ fn test_patch_and_hunk_display_format() {
                 ],
                 old_start_line: Some(10),
                 new_start_line: Some(10),
+                raw_header: None,
+                old_count: None,
+                new_count: None,
             },
         ],
         ends_with_newline: true,
+        index_lines: vec![],
+        binary: None,
+        new_file_path: None,
+        deletes_file: false,
+        path_inferred: false,
+        source_block: None,
     };
 
     let expected_output = concat!(
@@ -3989,6 +4683,12 @@ fn test_patch_and_hunk_display_format() {
         file_path: "empty.txt".into(),
         hunks: vec![],
         ends_with_newline: true,
+        index_lines: vec![],
+        binary: None,
+        new_file_path: None,
+        deletes_file: false,
+        path_inferred: false,
+        source_block: None,
     };
     let expected_empty = "--- a/empty.txt\n+++ b/empty.txt\n";
     assert_eq!(
@@ -4002,6 +4702,12 @@ fn test_patch_and_hunk_display_format() {
         file_path: "empty.txt".into(),
         hunks: vec![],
         ends_with_newline: false,
+        index_lines: vec![],
+        binary: None,
+        new_file_path: None,
+        deletes_file: false,
+        path_inferred: false,
+        source_block: None,
     };
     // The "No newline" marker should only appear if there are hunks.
     assert_eq!(
@@ -4017,8 +4723,17 @@ fn test_patch_and_hunk_display_format() {
             lines: vec!["+line 1".to_string(), "+line 2".to_string()],
             old_start_line: Some(0),
             new_start_line: Some(1),
+            raw_header: None,
+            old_count: None,
+            new_count: None,
         }],
         ends_with_newline: true,
+        index_lines: vec![],
+        binary: None,
+        new_file_path: None,
+        deletes_file: false,
+        path_inferred: false,
+        source_block: None,
     };
     let expected_creation = concat!(
         "--- a/new_file.txt\n",
@@ -4042,6 +4757,9 @@ fn test_patch_and_hunk_display_format() {
         ],
         old_start_line: Some(5),
         new_start_line: Some(5),
+        raw_header: None,
+        old_count: None,
+        new_count: None,
     };
     let expected_hunk_str = "@@ -5,2 +5,2 @@\n context\n-deleted\n+added\n";
     assert_eq!(
@@ -4065,9 +4783,13 @@ fn test_apply_result_helpers() {
                 },
                 match_type: MatchType::Exact,
                 replaced_lines: vec![],
+                interference: None,
+                byte_range: None,
             },
             HunkApplyStatus::SkippedNoChanges,
         ],
+        stats: None,
+        line_map: None,
     };
     assert!(all_success.all_applied_cleanly());
     assert!(!all_success.has_failures());
@@ -4084,11 +4806,15 @@ fn test_apply_result_helpers() {
                 },
                 match_type: MatchType::Exact,
                 replaced_lines: vec![],
+                interference: None,
+                byte_range: None,
             },
             HunkApplyStatus::Failed(HunkApplyError::ContextNotFound),
             HunkApplyStatus::SkippedNoChanges,
             HunkApplyStatus::Failed(HunkApplyError::AmbiguousExactMatch(vec![])),
         ],
+        stats: None,
+        line_map: None,
     };
     assert!(!mixed_result.all_applied_cleanly());
     assert!(mixed_result.has_failures());
@@ -4101,6 +4827,8 @@ fn test_apply_result_helpers() {
             HunkApplyStatus::Failed(HunkApplyError::ContextNotFound),
             HunkApplyStatus::Failed(HunkApplyError::ContextNotFound),
         ],
+        stats: None,
+        line_map: None,
     };
     assert!(!all_failures.all_applied_cleanly());
     assert!(all_failures.has_failures());
@@ -4110,6 +4838,8 @@ fn test_apply_result_helpers() {
     // Case 4: Empty result
     let empty_result = ApplyResult {
         hunk_results: vec![],
+        stats: None,
+        line_map: None,
     };
     assert!(empty_result.all_applied_cleanly());
     assert!(!empty_result.has_failures());
@@ -4167,7 +4897,7 @@ fn test_conflict_markers_git_style_labels() {
 
 #[test]
 fn test_conflict_markers_multiple_blocks_in_one_file() {
-    // Conflict markers are parsed as a single large hunk containing context and changes
+    // Conflict blocks this close together share a single hunk with their context
     let diff = indoc! {r#"
         ```diff
         Context Start
@@ -5707,8 +6437,8 @@ mod extended_stress_tests {
 
         assert!(result.report.all_applied_cleanly());
         let content = fs::read_to_string(&file_path).unwrap();
-        // Output is normalized to LF by mpatch
-        assert_eq!(content, "line1\nline two\nline3\n");
+        // The file keeps its CRLF line endings
+        assert_eq!(content, "line1\r\nline two\r\nline3\r\n");
     }
 
     #[test]
@@ -6525,7 +7255,7 @@ fn test_complex_apply_and_reverse_cycle() {
     let options = ApplyOptions::exact();
 
     // 3. Apply Original Patch (Forward)
-    let result = apply_patch_to_file(&patches[0], dir.path(), options).unwrap();
+    let result = apply_patch_to_file(&patches[0], dir.path(), options.clone()).unwrap();
     assert!(result.report.all_applied_cleanly(), "Forward patch failed");
 
     // 4. Invert Patch
@@ -6547,25 +7277,157 @@ fn test_complex_apply_and_reverse_cycle() {
 }
 
 #[test]
-fn test_newline_only_file_preservation() {
-    // Tests the fix where a file intended to be exactly one newline
-    // was being truncated to 0 bytes.
-    let original = "content\n";
+fn test_reverse_option_round_trips_content_byte_for_byte() {
+    // No trailing newline: `Patch::invert()` would add one back.
+    let original = "fn main() {\n    let x = 1;\n    println!(\"{}\", x);\n}";
     let diff = indoc! {r#"
-        ```diff
-        --- a/file.txt
-        +++ b/file.txt
-        @@ -1 +1 @@
-        -content
-        +
-        ```
+        --- a/main.rs
+        +++ b/main.rs
+        @@ -1,4 +1,5 @@
+         fn main() {
+        -    let x = 1;
+        +    let x = 2;
+        +    let y = 3;
+             println!("{}", x);
+         }
+        \ No newline at end of file
     "#};
+    let patch = parse_single_patch(diff).unwrap();
+
+    let forward = apply_patch_to_content(&patch, Some(original), &ApplyOptions::exact());
+    assert!(forward.report.all_applied_cleanly());
+
+    let options = ApplyOptions::exact().with_reverse(true);
+    let backward = apply_patch_to_content(&patch, Some(&forward.new_content), &options);
+    assert!(backward.report.all_applied_cleanly());
+    assert_eq!(backward.new_content, original);
+
+    match &backward.report.hunk_results[0] {
+        HunkApplyStatus::Applied { replaced_lines, .. } => assert_eq!(
+            replaced_lines,
+            &vec![
+                "fn main() {".to_string(),
+                "    let x = 2;".to_string(),
+                "    let y = 3;".to_string(),
+                "    println!(\"{}\", x);".to_string(),
+                "}".to_string(),
+            ]
+        ),
+        other => panic!("unexpected status: {:?}", other),
+    }
 
-    let options = ApplyOptions::new();
-    let result = patch_content_str(diff, Some(original), &options).unwrap();
+    let lines: Vec<&str> = forward.new_content.lines().collect();
+    let from_lines = apply_patch_to_lines(&patch, Some(&lines), &options);
+    assert_eq!(from_lines.new_content, format!("{}\n", original));
+}
 
-    // Result should be exactly one newline, not an empty string.
-    assert_eq!(result, "\n");
+#[test]
+fn test_preview_patch_to_content_matches_dry_run_and_round_trips() {
+    let original = "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\nnine\nlast";
+    let diff = indoc! {"
+        --- a/notes.txt
+        +++ b/notes.txt
+        @@ -1,3 +1,3 @@
+         one
+        -two
+        +TWO
+         three
+        @@ -8,3 +8,3 @@
+         eight
+         nine
+        -last
+        \\ No newline at end of file
+        +LAST
+    "};
+    let patch = parse_single_patch(diff).unwrap();
+    let preview = preview_patch_to_content(&patch, Some(original), &ApplyOptions::new());
+    assert!(preview.report.all_applied_cleanly());
+    assert!(preview
+        .diff
+        .starts_with("--- a/notes.txt\n+++ b/notes.txt\n"));
+    assert!(preview.diff.contains("\\ No newline at end of file"));
+
+    // It is the same diff a dry run on disk reports.
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("notes.txt"), original).unwrap();
+    let dry_run = apply_patch_to_file(&patch, dir.path(), ApplyOptions::dry_run()).unwrap();
+    assert_eq!(dry_run.diff.as_deref(), Some(preview.diff.as_str()));
+
+    // Re-applying the preview reproduces the new content.
+    let regenerated = parse_patches(&preview.diff).unwrap();
+    assert_eq!(regenerated.len(), 1);
+    let result = apply_patch_to_content(&regenerated[0], Some(original), &ApplyOptions::exact());
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(result.new_content, preview.new_content);
+}
+
+#[test]
+fn test_reverse_option_on_files_with_dry_run_and_deletion() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("kept.txt"), "a\nB\nc\n").unwrap();
+    let diff = indoc! {r#"
+        --- a/kept.txt
+        +++ b/kept.txt
+        @@ -1,3 +1,3 @@
+         a
+        -b
+        +B
+         c
+        --- a/gone.txt
+        +++ /dev/null
+        @@ -1,2 +0,0 @@
+        -first
+        -second
+    "#};
+    let patches = parse_auto(diff).unwrap();
+
+    // A dry run previews the un-apply without touching anything.
+    let preview = apply_patch_to_file(
+        &patches[0],
+        dir.path(),
+        ApplyOptions::dry_run().with_reverse(true),
+    )
+    .unwrap();
+    assert!(preview.diff.unwrap().contains("-B\n+b\n"));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("kept.txt")).unwrap(),
+        "a\nB\nc\n"
+    );
+
+    let options = ApplyOptions::builder().reverse(true).build();
+    let batch = apply_patches_to_dir(&patches, dir.path(), options);
+    assert!(batch.all_succeeded());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("kept.txt")).unwrap(),
+        "a\nb\nc\n"
+    );
+    // Reversing a deletion brings the file back.
+    assert_eq!(
+        fs::read_to_string(dir.path().join("gone.txt")).unwrap(),
+        "first\nsecond\n"
+    );
+}
+
+#[test]
+fn test_newline_only_file_preservation() {
+    // Tests the fix where a file intended to be exactly one newline
+    // was being truncated to 0 bytes.
+    let original = "content\n";
+    let diff = indoc! {r#"
+        ```diff
+        --- a/file.txt
+        +++ b/file.txt
+        @@ -1 +1 @@
+        -content
+        +
+        ```
+    "#};
+
+    let options = ApplyOptions::new();
+    let result = patch_content_str(diff, Some(original), &options).unwrap();
+
+    // Result should be exactly one newline, not an empty string.
+    assert_eq!(result, "\n");
     assert_eq!(result.len(), 1);
 }
 
@@ -6790,3 +7652,7083 @@ fn test_smart_indentation_outdented_added_line_fallback() {
     "#};
     assert_eq!(content, expected);
 }
+
+#[test]
+fn test_fuzzy_window_does_not_consume_next_hunk_context() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    // Hunk 1 wants to remove two lines that are no longer there. The same text
+    // appears a couple of lines further down, inside the context of hunk 2, so a
+    // flexible fuzzy window for hunk 1 would swallow (and delete) it.
+    let original = indoc! {r#"
+        fn run() {
+            let config = load_config();
+            let db = connect(&config);
+            let cache = Cache::new();
+            let pool = Pool::new(&db);
+            init_logging(&config);
+            // shutdown
+            log("done");
+            cleanup();
+            flush();
+        }
+    "#};
+    let diff = indoc! {r#"
+        --- a/run.rs
+        +++ b/run.rs
+        @@ -1,8 +1,6 @@
+         fn run() {
+             let config = load_config();
+             let db = connect(&config);
+             let cache = Cache::new();
+             let pool = Pool::new(&db);
+             init_logging(&config);
+        -    log("done");
+        -    cleanup();
+        @@ -10,5 +8,5 @@
+             log("done");
+             cleanup();
+        -    flush();
+        +    flush_all();
+         }
+    "#};
+    let patch = parse_patches(diff).unwrap().remove(0);
+    let options = ApplyOptions::new();
+
+    // Applying the hunks independently shows the naive cascade: hunk 1 eats the
+    // context of hunk 2, which then fails.
+    let mut naive_lines: Vec<String> = original.lines().map(String::from).collect();
+    let naive: Vec<_> = patch
+        .hunks
+        .iter()
+        .map(|hunk| apply_hunk_to_lines(hunk, &mut naive_lines, &options))
+        .collect();
+    assert!(matches!(naive[1], HunkApplyStatus::Failed(_)));
+
+    // The applier keeps hunk 1 out of hunk 2's territory so both apply.
+    let result = apply_patch_to_content(&patch, Some(original), &options);
+    assert!(result.report.all_applied_cleanly());
+    assert!(result.report.hunk_results.iter().all(|s| matches!(
+        s,
+        HunkApplyStatus::Applied {
+            interference: None,
+            ..
+        }
+    )));
+    let expected = indoc! {r#"
+        fn run() {
+            let config = load_config();
+            let db = connect(&config);
+            let cache = Cache::new();
+            let pool = Pool::new(&db);
+            init_logging(&config);
+            // shutdown
+            log("done");
+            cleanup();
+            flush_all();
+        }
+    "#};
+    assert_eq!(result.new_content, expected);
+}
+
+#[test]
+fn test_unavoidable_hunk_interference_is_reported_on_both_hunks() {
+    let original = indoc! {r#"
+        fn run() {
+            let config = load_config();
+            let db = connect(&config);
+            init_logging(&config);
+
+            log("done");
+            cleanup();
+            flush();
+        }
+    "#};
+    // Hunk 1 has too little context to be placed anywhere but on top of hunk 2.
+    let diff = indoc! {r#"
+        --- a/run.rs
+        +++ b/run.rs
+        @@ -4,3 +4,1 @@
+             init_logging(&config);
+        -    log("done");
+        -    cleanup();
+        @@ -8,5 +6,5 @@
+             log("done");
+             cleanup();
+        -    flush();
+        +    flush_all();
+         }
+    "#};
+    let patch = parse_patches(diff).unwrap().remove(0);
+    let result = apply_patch_to_content(&patch, Some(original), &ApplyOptions::new());
+
+    assert!(matches!(
+        result.report.hunk_results[0],
+        HunkApplyStatus::Applied {
+            interference: Some(2),
+            ..
+        }
+    ));
+    let failures = result.report.failures();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].hunk_index, 2);
+    assert_eq!(failures[0].interference, Some(1));
+
+    // The region of a later hunk is still found after an earlier fuzzy hunk
+    // has shifted the lines.
+    let with_header = format!(
+        "// Runs the job.\n// Safe to call twice.\n// See docs/run.md.\n{}",
+        original
+    );
+    let diff = indoc! {r#"
+        --- a/run.rs
+        +++ b/run.rs
+        @@ -1,3 +1,5 @@
+         // Runs the job!
+        +// Added line one.
+        +// Added line two.
+         // Safe to call twice.
+         // See docs/run.md.
+        @@ -7,3 +9,1 @@
+             init_logging(&config);
+        -    log("done");
+        -    cleanup();
+        @@ -11,5 +11,5 @@
+             log("done");
+             cleanup();
+        -    flush();
+        +    flush_all();
+         }
+    "#};
+    let patch = parse_patches(diff).unwrap().remove(0);
+    let result = apply_patch_to_content(&patch, Some(&with_header), &ApplyOptions::new());
+    assert!(matches!(
+        result.report.hunk_results[0],
+        HunkApplyStatus::Applied {
+            match_type: MatchType::Fuzzy { .. },
+            ..
+        }
+    ));
+    assert!(matches!(
+        result.report.hunk_results[1],
+        HunkApplyStatus::Applied {
+            interference: Some(3),
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_parse_auto_with_report_counts_blocks_and_hunks() {
+    let content = indoc! {r#"
+        Some prose.
+        ```rust
+        fn helper() {}
+        ```
+        ```diff
+        --- a/src/lib.rs
+        +++ b/src/lib.rs
+        @@ -1,2 +1,2 @@
+         fn a() {}
+        -fn b() {}
+        +fn c() {}
+        @@ -10,1 +10,1 @@
+        -old
+        +new
+        ```
+        ```python
+        print("not a patch")
+        ```
+    "#};
+    let (patches, report) = parse_auto_with_report(content).unwrap();
+    assert_eq!(patches.len(), 1);
+    assert_eq!(report.detected_format, PatchFormat::Markdown);
+    assert_eq!(report.blocks_found, 3);
+    assert_eq!(report.blocks_skipped, 2);
+    assert_eq!(report.patches, 1);
+    assert_eq!(report.hunks, 2);
+    assert!(report.warnings.is_empty());
+    // The plain function returns the same patches.
+    assert_eq!(parse_auto(content).unwrap(), patches);
+}
+
+#[test]
+fn test_parse_report_warnings_use_input_line_numbers() {
+    let content = indoc! {r#"
+        Intro line.
+
+        ```diff
+        --- a/file.txt
+        +++ b/file.txt
+        -stray removal before any hunk
+        @@ -1,2 +1,2 @@
+         keep
+        unprefixed context
+        -old
+        +new
+        ```
+        ```diff
+        --- a/other.txt
+        +++ b/other.txt
+        @@ -1 +1 @@
+        -x
+        +y
+    "#};
+    let (patches, report) = parse_auto_with_report(content).unwrap();
+    assert_eq!(patches.len(), 2);
+    assert_eq!(
+        report.warnings,
+        vec![
+            ParseWarning::MissingHunkHeader { line: 6 },
+            ParseWarning::UnprefixedContextLine { line: 9 },
+            ParseWarning::UnterminatedCodeBlock { line: 13 },
+        ]
+    );
+}
+
+#[test]
+fn test_parse_report_for_raw_and_conflict_input() {
+    let raw = "--- a/f.txt\n+++ b/f.txt\n@@ -1 +1 @@\n-a\n+b\n@@ -5 +5 @@\n";
+    let (patches, report) = parse_auto_with_report(raw).unwrap();
+    assert_eq!(report.detected_format, PatchFormat::Unified);
+    assert_eq!(report.blocks_found, 0);
+    assert_eq!(report.hunks, patches[0].hunks.len());
+    assert_eq!(report.warnings, vec![ParseWarning::EmptyHunk { line: 6 }]);
+
+    let conflict = "<<<<\nold\n====\nnew\n>>>>\n";
+    let (patches, report) = parse_auto_with_report(conflict).unwrap();
+    assert_eq!(report.detected_format, PatchFormat::Conflict);
+    assert_eq!(report.patches, 1);
+    assert_eq!(report.hunks, 1);
+    assert_eq!(patches.len(), 1);
+    assert!(report.warnings.is_empty());
+}
+
+#[test]
+fn test_tie_breaking_follows_drift_observed_from_earlier_hunks() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let block = ["    if retries > 0 {", "        retry();", "    }"];
+
+    // The file as the diff author saw it: a unique function at the top and a
+    // duplicated block at line 51.
+    let mut original = vec!["fn unique_setup() {".to_string(), "    init();".to_string()];
+    original.push("}".to_string());
+    while original.len() < 50 {
+        original.push(format!("// filler {}", original.len()));
+    }
+    original.extend(block.iter().map(|s| s.to_string()));
+    original.push("// end".to_string());
+
+    // Since then, 200 lines (including a copy of the block near line 51) were
+    // inserted at the top of the file.
+    let mut current: Vec<String> = (0..200).map(|i| format!("// new header {}", i)).collect();
+    for (offset, line) in block.iter().enumerate() {
+        current[48 + offset] = line.to_string();
+    }
+    current.extend(original.iter().cloned());
+
+    let diff = indoc! {r#"
+        --- a/lib.rs
+        +++ b/lib.rs
+        @@ -1,3 +1,3 @@
+         fn unique_setup() {
+        -    init();
+        +    init_all();
+         }
+        @@ -51,3 +51,3 @@
+             if retries > 0 {
+        -        retry();
+        +        retry_with_backoff();
+             }
+    "#};
+    let patch = parse_patches(diff).unwrap().remove(0);
+
+    // Without drift correction, the raw hint picks the copy in the new header.
+    let options = ApplyOptions::exact();
+    let (naive, _) = find_hunk_location_in_lines(&patch.hunks[1], &current, &options).unwrap();
+    assert_eq!(naive.start_index, 48);
+
+    let result = apply_patch_to_lines(&patch, Some(&current), &options);
+    assert!(result.report.all_applied_cleanly());
+    match &result.report.hunk_results[1] {
+        HunkApplyStatus::Applied { location, .. } => assert_eq!(location.start_index, 250),
+        other => panic!("unexpected status: {:?}", other),
+    }
+    let new_lines: Vec<&str> = result.new_content.lines().collect();
+    assert_eq!(new_lines[49], "        retry();");
+    assert_eq!(new_lines[251], "        retry_with_backoff();");
+    assert_eq!(new_lines[201], "    init_all();");
+}
+
+#[test]
+fn test_line_deltas_only_shift_hunks_below_them() {
+    // A block that appears twice, eight lines apart, at lines 12 and 20.
+    let mut original: Vec<String> = (1..=40).map(|i| format!("line {}", i)).collect();
+    for start in [11, 19] {
+        original[start] = "    match value {".to_string();
+        original[start + 1] = "        _ => todo!(),".to_string();
+    }
+    let ambiguous_hunk = indoc! {"
+        @@ -20,2 +20,2 @@
+             match value {
+        -        _ => todo!(),
+        +        _ => unreachable!(),
+    "};
+    let ten_new_lines: String = (0..10).map(|i| format!("+added {}\n", i)).collect();
+    let insertion_hunk =
+        |after: usize| format!("@@ -{0},1 +{0},11 @@\n line {0}\n{1}", after, ten_new_lines);
+
+    // The first hunk adds 10 lines above the block. The second hunk's hint
+    // then points between the two copies (now at lines 22 and 30), and only
+    // the offset picks the copy that was at line 20.
+    let diff = format!(
+        "--- a/f.rs\n+++ b/f.rs\n{}{}",
+        insertion_hunk(2),
+        ambiguous_hunk
+    );
+    let patch = parse_patches(&diff).unwrap().remove(0);
+    let options = ApplyOptions::exact();
+    let mut applier = HunkApplier::new(&patch, Some(&original), &options);
+    assert!(matches!(
+        applier.next(),
+        Some(HunkApplyStatus::Applied { .. })
+    ));
+    assert_eq!(applier.remaining_hunks(), &patch.hunks[1..]);
+    let (naive, _) =
+        find_hunk_location_in_lines(&patch.hunks[1], applier.current_lines(), &options).unwrap();
+    assert_eq!(naive.start_index, 21);
+    match applier.next() {
+        Some(HunkApplyStatus::Applied { location, .. }) => assert_eq!(location.start_index, 29),
+        other => panic!("unexpected status: {:?}", other),
+    }
+    assert!(applier.remaining_hunks().is_empty());
+
+    // Out of file order, a hunk that adds lines below the block must not shift it.
+    let diff = format!(
+        "--- a/f.rs\n+++ b/f.rs\n{}{}",
+        insertion_hunk(35),
+        ambiguous_hunk.replace("-20,2 +20,2", "-12,2 +12,2")
+    );
+    let patch = parse_patches(&diff).unwrap().remove(0);
+    let result = apply_patch_to_lines(&patch, Some(&original), &options);
+    assert!(result.report.all_applied_cleanly());
+    match &result.report.hunk_results[1] {
+        HunkApplyStatus::Applied { location, .. } => assert_eq!(location.start_index, 11),
+        other => panic!("unexpected status: {:?}", other),
+    }
+}
+
+#[test]
+fn test_min_context_for_fuzzy_rejects_bare_hunks() {
+    let original = indoc! {"
+        fn report(a: u32, b: u32) {
+            let total = compute_sum(first_value, second_valu);
+            println!(\"{}\", total);
+            let totals = compute_sums(a, b);
+        }
+    "};
+    // A one-line deletion with no context, and no exact match in the file.
+    let diff = indoc! {"
+        --- a/report.rs
+        +++ b/report.rs
+        @@ -2 +1,0 @@
+        -    let total = compute_sum(first_value, second_value);
+    "};
+    let patch = parse_single_patch(diff).unwrap();
+    assert!(patch.hunks[0].context_lines().is_empty());
+
+    // With a lenient fuzz factor, the similar-looking line is removed.
+    let lenient = ApplyOptions::new().with_fuzz_factor(0.6);
+    let result = apply_patch_to_content(&patch, Some(original), &lenient);
+    assert!(result.report.all_applied_cleanly());
+    assert!(matches!(
+        result.report.hunk_results[0],
+        HunkApplyStatus::Applied {
+            match_type: MatchType::Fuzzy { .. },
+            ..
+        }
+    ));
+
+    // With the knob set, the hunk fails fast instead of risking a wrong apply.
+    let options = lenient.with_min_context_for_fuzzy(2);
+    let result = apply_patch_to_content(&patch, Some(original), &options);
+    assert_eq!(result.new_content, original);
+    assert_eq!(
+        result.report.failures()[0].reason,
+        HunkApplyError::InsufficientContext {
+            required: 2,
+            found: 0
+        }
+    );
+
+    // Exact and whitespace-insensitive matches still apply.
+    let exact = original.replace("second_valu);", "second_value);  ");
+    let result = apply_patch_to_content(&patch, Some(&exact), &options);
+    assert!(result.report.all_applied_cleanly());
+    assert!(!result.new_content.contains("compute_sum("));
+}
+
+#[test]
+fn test_normalize_makes_equivalent_patches_equal() {
+    let base = "fn a() {}\nfn b() {}\nfn c() {}\nfn d() {}\nfn e() {}\nfn f() {}\n";
+
+    // One hunk with generous context and accurate hints.
+    let verbose = parse_patches(indoc! {r#"
+        --- a/lib.rs
+        +++ b/lib.rs
+        @@ -1,6 +1,6 @@
+         fn a() {}
+        -fn b() {}
+        +fn b2() {}
+         fn c() {}
+         fn d() {}
+        -fn e() {}
+        +fn e2() {}
+         fn f() {}
+    "#})
+    .unwrap()
+    .remove(0);
+
+    // Two terse hunks, in a different order, with stale hints.
+    let terse = parse_patches(indoc! {r#"
+        --- a/lib.rs
+        +++ b/lib.rs
+        @@ -40,2 +40,2 @@
+         fn d() {}
+        -fn e() {}
+        +fn e2() {}
+        @@ -90,2 +90,2 @@
+        -fn b() {}
+        +fn b2() {}
+         fn c() {}
+    "#})
+    .unwrap()
+    .remove(0);
+    assert_ne!(verbose, terse);
+
+    let options = ApplyOptions::new();
+    let a = verbose.normalize(base, &options).unwrap();
+    let b = terse.normalize(base, &options).unwrap();
+    assert_eq!(a, b);
+    assert!(verbose.semantic_eq(&terse, Some(base)));
+
+    // Normalizing is idempotent.
+    assert_eq!(a.normalize(base, &options).unwrap(), a);
+}
+
+#[test]
+fn test_normalize_reports_hunk_failure() {
+    let patch = Patch::from_texts("f.txt", "x\ny\n", "x\nz\n", 1).unwrap();
+    let err = patch
+        .normalize("something else\n", &ApplyOptions::exact())
+        .unwrap_err();
+    assert_eq!(err, HunkApplyError::ContextNotFound);
+}
+
+#[test]
+fn test_semantic_eq_distinguishes_locations_only_with_base() {
+    let base = "item\nother\nitem\n";
+    let first = Patch::from_texts("f.txt", base, "changed\nother\nitem\n", 1).unwrap();
+    let last = Patch::from_texts("f.txt", base, "item\nother\nchanged\n", 1).unwrap();
+
+    // Structurally, both patches replace one `item` with `changed`...
+    assert_eq!(first.change_signature(), last.change_signature());
+    assert!(first.semantic_eq(&last, None));
+    // ...but on the base they produce different results.
+    assert!(!first.semantic_eq(&last, Some(base)));
+
+    // Different files or newline handling are never equal.
+    let mut elsewhere = first.clone();
+    elsewhere.file_path = "g.txt".into();
+    assert!(!first.semantic_eq(&elsewhere, None));
+    let mut no_newline = first.clone();
+    no_newline.ends_with_newline = false;
+    assert!(!first.semantic_eq(&no_newline, None));
+}
+
+#[test]
+fn test_change_signature_cancels_self_replacements_across_hunks() {
+    let patch = Patch {
+        file_path: "f.txt".into(),
+        hunks: vec![
+            Hunk {
+                lines: vec!["-moved".to_string(), "-gone".to_string()],
+                old_start_line: Some(1),
+                new_start_line: Some(1),
+                raw_header: None,
+                old_count: None,
+                new_count: None,
+            },
+            Hunk {
+                lines: vec![" ctx".to_string(), "+moved".to_string(), "+new".to_string()],
+                old_start_line: Some(10),
+                new_start_line: Some(8),
+                raw_header: None,
+                old_count: None,
+                new_count: None,
+            },
+        ],
+        ends_with_newline: false,
+        index_lines: vec![],
+        binary: None,
+        new_file_path: None,
+        deletes_file: false,
+        path_inferred: false,
+        source_block: None,
+    };
+    assert_eq!(
+        patch.change_signature(),
+        "-gone\n+new\n\\ No newline at end of file"
+    );
+}
+
+/// Sets up a file and a patch for the concurrent-modification tests, and installs
+/// a hook that simulates another process saving the file the first `races` times
+/// mpatch is about to write it.
+fn setup_concurrent_edit(races: usize) -> (tempfile::TempDir, Patch) {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("shared.txt"), "alpha\nbeta\ngamma\n").unwrap();
+    let patch = parse_patches(indoc! {r#"
+        --- a/shared.txt
+        +++ b/shared.txt
+        @@ -1,3 +1,3 @@
+         alpha
+        -beta
+        +BETA
+         gamma
+    "#})
+    .unwrap()
+    .remove(0);
+
+    let mut remaining = races;
+    mpatch::test_hooks::set_before_write(Some(Box::new(move |path| {
+        if remaining > 0 {
+            remaining -= 1;
+            let current = fs::read_to_string(path).unwrap();
+            fs::write(path, format!("{}developer edit\n", current)).unwrap();
+        }
+    })));
+    (dir, patch)
+}
+
+#[test]
+fn test_concurrent_modification_overwrite_is_default() {
+    let (dir, patch) = setup_concurrent_edit(1);
+    let result = apply_patch_to_file(&patch, dir.path(), ApplyOptions::new());
+    mpatch::test_hooks::set_before_write(None);
+
+    assert!(result.unwrap().report.all_applied_cleanly());
+    // The developer's edit is clobbered, exactly as before the policy existed.
+    let content = fs::read_to_string(dir.path().join("shared.txt")).unwrap();
+    assert_eq!(content, "alpha\nBETA\ngamma\n");
+}
+
+#[test]
+fn test_concurrent_modification_fail_leaves_file_untouched() {
+    let (dir, patch) = setup_concurrent_edit(1);
+    let options =
+        ApplyOptions::new().with_concurrent_modification_policy(ConcurrentModificationPolicy::Fail);
+    let result = apply_patch_to_file(&patch, dir.path(), options);
+    mpatch::test_hooks::set_before_write(None);
+
+    assert!(matches!(
+        result,
+        Err(PatchError::TargetModifiedDuringApply { .. })
+    ));
+    let content = fs::read_to_string(dir.path().join("shared.txt")).unwrap();
+    assert_eq!(content, "alpha\nbeta\ngamma\ndeveloper edit\n");
+    // No staging files are left behind.
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn test_concurrent_modification_retry_applies_to_fresh_content() {
+    let (dir, patch) = setup_concurrent_edit(2);
+    let options = ApplyOptions::new()
+        .with_concurrent_modification_policy(ConcurrentModificationPolicy::Retry(2));
+    let result = apply_patch_to_file(&patch, dir.path(), options);
+    mpatch::test_hooks::set_before_write(None);
+
+    assert!(result.unwrap().report.all_applied_cleanly());
+    // Both concurrent edits survive, and the patch is applied on top of them.
+    let content = fs::read_to_string(dir.path().join("shared.txt")).unwrap();
+    assert_eq!(
+        content,
+        "alpha\nBETA\ngamma\ndeveloper edit\ndeveloper edit\n"
+    );
+}
+
+#[test]
+fn test_concurrent_modification_retry_gives_up_after_limit() {
+    let (dir, patch) = setup_concurrent_edit(3);
+    let options = ApplyOptions::new()
+        .with_concurrent_modification_policy(ConcurrentModificationPolicy::Retry(2));
+    let result = apply_patch_to_file(&patch, dir.path(), options);
+    mpatch::test_hooks::set_before_write(None);
+
+    assert!(matches!(
+        result,
+        Err(PatchError::TargetModifiedDuringApply { .. })
+    ));
+    let content = fs::read_to_string(dir.path().join("shared.txt")).unwrap();
+    assert!(content.contains("beta"));
+}
+
+#[test]
+fn test_concurrent_modification_guarded_write_without_race() {
+    let dir = tempdir().unwrap();
+    let options =
+        ApplyOptions::new().with_concurrent_modification_policy(ConcurrentModificationPolicy::Fail);
+
+    // Creation of a new file in a new directory.
+    let patch = Patch::from_texts("nested/new.txt", "", "hello\n", 3).unwrap();
+    let result = apply_patch_to_file(&patch, dir.path(), options.clone()).unwrap();
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("nested/new.txt")).unwrap(),
+        "hello\n"
+    );
+
+    // Deletion of the file that was just created.
+    let result = apply_patch_to_file(&patch.invert(), dir.path(), options).unwrap();
+    assert!(result.report.all_applied_cleanly());
+    assert!(!dir.path().join("nested/new.txt").exists());
+}
+
+#[test]
+fn test_search_replace_aider_transcript_end_to_end() {
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("src")).unwrap();
+    fs::write(
+        dir.path().join("src/greet.py"),
+        indoc! {r#"
+            def greet(name):
+                print("Hello " + name)
+
+
+            def farewell(name):
+                print("Bye " + name)
+        "#},
+    )
+    .unwrap();
+    fs::write(dir.path().join("README.md"), "# Demo\n").unwrap();
+
+    let transcript = indoc! {r#"
+        I'll switch both functions to f-strings and document the change.
+
+        src/greet.py
+        ```python
+        <<<<<<< SEARCH
+            print("Hello " + name)
+        =======
+            print(f"Hello {name}")
+        >>>>>>> REPLACE
+        ```
+
+        Now the second function:
+
+        src/greet.py
+        ```python
+        <<<<<<< SEARCH
+            print("Bye " + name)
+        =======
+            print(f"Bye {name}")
+        >>>>>>> REPLACE
+        ```
+
+        README.md
+        ```markdown
+        <<<<<<< SEARCH
+        =======
+
+        Uses f-strings.
+        >>>>>>> REPLACE
+        ```
+
+        And a new module:
+
+        ```python
+        src/util.py
+        <<<<<<< SEARCH
+        =======
+        def shout(text):
+            return text.upper()
+        >>>>>>> REPLACE
+        ```
+    "#};
+
+    assert_eq!(detect_patch(transcript), PatchFormat::SearchReplace);
+    let patches = parse_auto(transcript).unwrap();
+    assert_eq!(patches, parse_search_replace(transcript).unwrap());
+    let paths: Vec<_> = patches
+        .iter()
+        .map(|p| p.file_path.to_str().unwrap())
+        .collect();
+    assert_eq!(paths, vec!["src/greet.py", "README.md", "src/util.py"]);
+    assert_eq!(patches[0].hunks.len(), 2);
+    assert!(patches[2].is_creation());
+
+    let result = apply_patches_to_dir(&patches, dir.path(), ApplyOptions::exact());
+    assert!(result.all_succeeded());
+
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/greet.py")).unwrap(),
+        indoc! {r#"
+            def greet(name):
+                print(f"Hello {name}")
+
+
+            def farewell(name):
+                print(f"Bye {name}")
+        "#}
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("README.md")).unwrap(),
+        "# Demo\n\nUses f-strings.\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/util.py")).unwrap(),
+        "def shout(text):\n    return text.upper()\n"
+    );
+}
+
+#[test]
+fn test_search_replace_path_reuse_and_errors() {
+    // Consecutive blocks without a path line edit the previous block's file.
+    let content = indoc! {"
+        lib.rs
+        <<<<<<< SEARCH
+        a
+        =======
+        b
+        >>>>>>> REPLACE
+        <<<<<<< SEARCH
+        c
+        =======
+        d
+        >>>>>>> REPLACE
+    "};
+    let patches = parse_search_replace(content).unwrap();
+    assert_eq!(patches.len(), 1);
+    assert_eq!(patches[0].hunks.len(), 2);
+    assert_eq!(patches[0].hunks[1].removed_lines(), vec!["c"]);
+
+    // Prose is not mistaken for a path.
+    let no_path = indoc! {"
+        Here is the change you asked for:
+        ```
+        <<<<<<< SEARCH
+        a
+        =======
+        b
+        >>>>>>> REPLACE
+        ```
+    "};
+    assert!(matches!(
+        parse_search_replace(no_path),
+        Err(ParseError::MissingFileHeader { line: 3 })
+    ));
+    let bare_word = "Done.\n<<<<<<< SEARCH\na\n=======\nb\n>>>>>>> REPLACE\n";
+    assert!(matches!(
+        parse_auto(bare_word),
+        Err(ParseError::MissingFileHeader { line: 2 })
+    ));
+    for path_line in ["src/lib", "lib.rs", "`Makefile`", "**Makefile**"] {
+        let content = format!(
+            "{}\n<<<<<<< SEARCH\na\n=======\nb\n>>>>>>> REPLACE\n",
+            path_line
+        );
+        let patches = parse_search_replace(&content).unwrap();
+        assert_eq!(
+            patches[0].file_path,
+            PathBuf::from(path_line.trim_matches(|c| c == '`' || c == '*'))
+        );
+    }
+
+    // A unified diff that edits such markers is still a unified diff.
+    let unified = indoc! {"
+        --- a/docs/format.md
+        +++ b/docs/format.md
+        @@ -1,3 +1,3 @@
+         <<<<<<< SEARCH
+        -=======
+        +=======
+         >>>>>>> REPLACE
+    "};
+    assert_eq!(detect_patch(unified), PatchFormat::Unified);
+
+    // Only an empty SEARCH section appends; a bare insertion hunk from a
+    // unified diff still needs context to be placed.
+    let append =
+        parse_search_replace("f.txt\n<<<<<<< SEARCH\n=======\nadded\n>>>>>>> REPLACE\n").unwrap();
+    let result = apply_patch_to_content(&append[0], Some("a\nb\n"), &ApplyOptions::new());
+    assert_eq!(result.new_content, "a\nb\nadded\n");
+    let headerless = parse_single_patch("--- a/f.txt\n+++ b/f.txt\n+added\n").unwrap();
+    assert!(!headerless.hunks[0].appends_to_end());
+    let result = apply_patch_to_content(&headerless, Some("a\nb\nc\n"), &ApplyOptions::new());
+    assert_eq!(
+        result.report.hunk_results,
+        vec![HunkApplyStatus::Failed(HunkApplyError::ContextNotFound)]
+    );
+    assert_eq!(result.new_content, "a\nb\nc\n");
+
+    // An unterminated block is reported and ignored.
+    let unterminated =
+        "lib.rs\n<<<<<<< SEARCH\na\n=======\nb\n>>>>>>> REPLACE\nlib.rs\n<<<<<<< SEARCH\nc\n";
+    let (patches, report) = parse_auto_with_report(unterminated).unwrap();
+    assert_eq!(report.detected_format, PatchFormat::SearchReplace);
+    assert_eq!(patches[0].hunks.len(), 1);
+    assert_eq!(
+        report.warnings,
+        vec![ParseWarning::UnterminatedSearchReplaceBlock { line: 8 }]
+    );
+}
+
+#[test]
+fn test_openai_patch_end_to_end() {
+    let original_app = indoc! {r#"
+        import sys
+
+
+        class Greeter:
+            def greet(self, name):
+                print("Hi " + name)
+
+            def farewell(self, name):
+                print("Bye " + name)
+
+
+        def main():
+            Greeter().greet(sys.argv[1])
+    "#};
+
+    let response = indoc! {r#"
+        Here are the changes:
+
+        ```
+        *** Begin Patch
+        *** Update File: src/app.py
+        @@ class Greeter:
+             def greet(self, name):
+        -        print("Hi " + name)
+        +        print(f"Hello {name}")
+        @@     def farewell(self, name):
+        -        print("Bye " + name)
+        +        print(f"Goodbye {name}")
+
+        @@ def main():
+        -    Greeter().greet(sys.argv[1])
+        +    for name in sys.argv[1:]:
+        +        Greeter().greet(name)
+        *** End of File
+        *** Add File: src/__init__.py
+        +from .app import Greeter
+        *** Delete File: src/legacy.py
+        *** End Patch
+        ```
+    "#};
+
+    assert_eq!(detect_patch(response), PatchFormat::OpenAi);
+    let patches = parse_auto(response).unwrap();
+    assert_eq!(patches, parse_openai_patch(response).unwrap());
+    let paths: Vec<_> = patches
+        .iter()
+        .map(|p| p.file_path.to_str().unwrap())
+        .collect();
+    assert_eq!(
+        paths,
+        vec!["src/app.py", "src/__init__.py", "src/legacy.py"]
+    );
+
+    // Each `@@` anchor starts a new hunk and becomes its first context line.
+    let update = &patches[0];
+    assert_eq!(update.hunks.len(), 3);
+    assert_eq!(update.hunks[0].lines[0], " class Greeter:");
+    assert_eq!(
+        update.hunks[1].context_lines()[0],
+        "    def farewell(self, name):"
+    );
+    assert!(update.hunks.iter().all(|h| h.old_start_line.is_none()));
+
+    let result = apply_patch_to_content(update, Some(original_app), &ApplyOptions::exact());
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(
+        result.new_content,
+        indoc! {r#"
+            import sys
+
+
+            class Greeter:
+                def greet(self, name):
+                    print(f"Hello {name}")
+
+                def farewell(self, name):
+                    print(f"Goodbye {name}")
+
+
+            def main():
+                for name in sys.argv[1:]:
+                    Greeter().greet(name)
+        "#}
+    );
+
+    let added = &patches[1];
+    assert!(added.is_creation());
+    let result = apply_patch_to_content(added, None, &ApplyOptions::exact());
+    assert_eq!(result.new_content, "from .app import Greeter\n");
+
+    let deleted = &patches[2];
+    assert!(deleted.is_deletion());
+    assert!(!deleted.is_creation());
+    assert!(deleted.hunks.is_empty());
+}
+
+#[test]
+fn test_openai_delete_file_removes_target() {
+    let dir = tempdir().unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(dir.path().join("src/legacy.py"), "def old():\n    pass\n").unwrap();
+    let content = "*** Begin Patch\n*** Delete File: src/legacy.py\n*** End Patch\n";
+    let patches = parse_openai_patch(content).unwrap();
+    assert!(!patches[0].is_creation());
+    assert_eq!(describe_patches(&patches), "Deletes src/legacy.py.");
+    assert_eq!(
+        patches[0].to_string(),
+        "--- a/src/legacy.py\n+++ /dev/null\n"
+    );
+
+    // The format does not list the deleted lines, so a dry run previews them all.
+    let preview = apply_patch_to_file(&patches[0], dir.path(), ApplyOptions::dry_run()).unwrap();
+    assert!(preview.diff.unwrap().contains("+++ /dev/null"));
+    assert!(dir.path().join("src/legacy.py").exists());
+
+    let result = apply_patch_to_file(&patches[0], dir.path(), ApplyOptions::new()).unwrap();
+    assert!(result.report.all_applied_cleanly());
+    assert!(result.removed);
+    assert!(!dir.path().join("src/legacy.py").exists());
+
+    // Deleting a file that is not there is an error, not a creation.
+    assert!(matches!(
+        apply_patch_to_file(&patches[0], dir.path(), ApplyOptions::new()),
+        Err(PatchError::TargetNotFound(_))
+    ));
+
+    let mut workspace = VirtualWorkspace::new();
+    workspace.insert("src/legacy.py", "def old():\n    pass\n");
+    let batch = workspace.apply_patches(&patches, &ApplyOptions::new());
+    assert!(batch.all_applied_cleanly());
+    assert_eq!(workspace.get("src/legacy.py"), None);
+}
+
+#[test]
+fn test_openai_patch_move_and_errors() {
+    let content = indoc! {"
+        *** Begin Patch
+        *** Update File: old/name.txt
+        *** Move to: new/name.txt
+        @@
+         keep
+        -drop
+        *** End Patch
+        Trailing prose is ignored.
+        +not a change
+    "};
+    let patches = parse_openai_patch(content).unwrap();
+    assert_eq!(patches.len(), 1);
+    assert_eq!(patches[0].file_path, PathBuf::from("old/name.txt"));
+    assert_eq!(
+        patches[0].new_file_path,
+        Some(PathBuf::from("new/name.txt"))
+    );
+    assert_eq!(patches[0].hunks[0].lines, vec![" keep", "-drop"]);
+
+    // A missing `*** End Patch` is tolerated; the first foreign line ends the patch.
+    let unterminated = "*** Begin Patch\n*** Add File: a.txt\n+a\n```\n+b\n";
+    let patches = parse_openai_patch(unterminated).unwrap();
+    assert_eq!(patches[0].hunks[0].lines, vec!["+a"]);
+
+    let (_, report) = parse_auto_with_report(unterminated).unwrap();
+    assert_eq!(report.detected_format, PatchFormat::OpenAi);
+    assert_eq!(report.patches, 1);
+
+    // Change lines need a file header, and headers need a path.
+    assert!(matches!(
+        parse_openai_patch("*** Begin Patch\n-a\n*** End Patch\n"),
+        Err(ParseError::MissingFileHeader { line: 2 })
+    ));
+    assert!(matches!(
+        parse_openai_patch("*** Begin Patch\n*** Update File:\n-a\n*** End Patch\n"),
+        Err(ParseError::MissingFileHeader { line: 2 })
+    ));
+}
+
+#[test]
+fn test_validate_reports_malformed_hunks() {
+    let diff = indoc! {"
+        --- a/src/lib.rs
+        +++ b/src/lib.rs
+        @@ -1,6 +1,6 @@
+         fn a() {
+        -    1
+        +    2
+         }
+        @@ -10 +10 @@
+        -fn b() {}
+        +fn b() -> u8 { 0 }
+        @@ -20,3 +20,3 @@
+         unchanged
+        @@ -1,6 +1,6 @@
+         fn a() {
+        -    1
+        +    2
+         }
+    "};
+    let patches = parse_patches(diff).unwrap();
+    let lints = patches[0].validate();
+    assert_eq!(
+        lints,
+        vec![
+            PatchLint {
+                hunk: Some(1),
+                kind: PatchLintKind::HeaderCountMismatch {
+                    declared_old: 6,
+                    actual_old: 3,
+                    declared_new: 6,
+                    actual_new: 3,
+                },
+            },
+            PatchLint {
+                hunk: Some(2),
+                kind: PatchLintKind::NoContext,
+            },
+            PatchLint {
+                hunk: Some(3),
+                kind: PatchLintKind::EmptyHunk,
+            },
+            PatchLint {
+                hunk: Some(3),
+                kind: PatchLintKind::HeaderCountMismatch {
+                    declared_old: 3,
+                    actual_old: 1,
+                    declared_new: 3,
+                    actual_new: 1,
+                },
+            },
+            PatchLint {
+                hunk: Some(4),
+                kind: PatchLintKind::HeaderCountMismatch {
+                    declared_old: 6,
+                    actual_old: 3,
+                    declared_new: 6,
+                    actual_new: 3,
+                },
+            },
+            PatchLint {
+                hunk: Some(4),
+                kind: PatchLintKind::DuplicateHunk { first: 1 },
+            },
+        ]
+    );
+    let errors: Vec<_> = lints
+        .iter()
+        .filter(|l| l.severity() == LintSeverity::Error)
+        .collect();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].to_string(), "hunk 4: hunk repeats hunk 1");
+
+    // A hand-built hunk with an unprefixed line, and whole-file creations and
+    // deletions, which need no context.
+    let hunk = Hunk {
+        lines: vec![" a".to_string(), "b".to_string(), "+c".to_string()],
+        old_start_line: Some(1),
+        new_start_line: Some(1),
+        raw_header: None,
+        old_count: None,
+        new_count: None,
+    };
+    assert_eq!(
+        hunk.validate(),
+        vec![PatchLint {
+            hunk: None,
+            kind: PatchLintKind::UnprefixedLine { line: 2 },
+        }]
+    );
+    let creation = "--- /dev/null\n+++ b/new.txt\n@@ -0,0 +1,2 @@\n+a\n+b\n";
+    let deletion = "--- a/old.txt\n+++ b/old.txt\n@@ -1,2 +0,0 @@\n-a\n-b\n";
+    for diff in [creation, deletion] {
+        assert!(parse_patches(diff).unwrap()[0].validate().is_empty());
+    }
+}
+
+#[test]
+fn test_parse_auto_validated_pairs_lints_with_patches() {
+    let content = indoc! {"
+        ```diff
+        --- a/good.txt
+        +++ b/good.txt
+        @@ -1,3 +1,3 @@
+         one
+        -two
+        +TWO
+         three
+        ```
+
+        ```diff
+        --- a/bad.txt
+        +++ b/bad.txt
+        @@ -1,40 +1,40 @@
+        -entire old file
+        +entire new file
+        ```
+    "};
+    let (patches, lints) = parse_auto_validated(content).unwrap();
+    // Parsing itself is unchanged.
+    assert_eq!(patches, parse_auto(content).unwrap());
+    assert_eq!(
+        lints.iter().map(|(i, l)| (*i, l.kind)).collect::<Vec<_>>(),
+        vec![
+            (1, PatchLintKind::NoContext),
+            (
+                1,
+                PatchLintKind::HeaderCountMismatch {
+                    declared_old: 40,
+                    actual_old: 1,
+                    declared_new: 40,
+                    actual_new: 1,
+                }
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_conflict_markers_take_file_path_from_heading_or_hint() {
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("src")).unwrap();
+    fs::write(
+        dir.path().join("src/config.rs"),
+        "pub const PORT: u16 = 80;\npub const HOST: &str = \"localhost\";\n",
+    )
+    .unwrap();
+
+    let doc = indoc! {r#"
+        ## Configuration
+
+        Switch to the unprivileged port:
+
+        **src/config.rs**
+
+        ```rust
+        <<<<
+        pub const PORT: u16 = 80;
+        ====
+        pub const PORT: u16 = 8080;
+        >>>>
+        ```
+    "#};
+    let patches = parse_auto(doc).unwrap();
+    assert_eq!(patches.len(), 1);
+    assert_eq!(patches[0].file_path, PathBuf::from("src/config.rs"));
+
+    let result = apply_patches_to_dir(&patches, dir.path(), ApplyOptions::exact());
+    assert!(result.all_succeeded());
+    assert!(!dir.path().join("patch_target").exists());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/config.rs")).unwrap(),
+        "pub const PORT: u16 = 8080;\npub const HOST: &str = \"localhost\";\n"
+    );
+
+    // A leading comment names the file and is not kept as context.
+    for hint in ["// file: src/lib.rs", "# src/lib.rs", "`src/lib.rs`"] {
+        let content = format!("{}\n<<<<\nold\n====\nnew\n>>>>\n", hint);
+        let patches = parse_conflict_markers(&content);
+        assert_eq!(
+            patches[0].file_path,
+            PathBuf::from("src/lib.rs"),
+            "{}",
+            hint
+        );
+        assert_eq!(patches[0].hunks[0].lines, vec!["-old", "+new"]);
+    }
+
+    // Ordinary comments and headings are not paths.
+    for line in [
+        "# TODO: tidy up",
+        "#!/bin/sh",
+        "// v1.2",
+        "**Note:**",
+        "src/lib.rs",
+    ] {
+        let content = format!("{}\n<<<<\nold\n====\nnew\n>>>>\n", line);
+        let patches = parse_conflict_markers(&content);
+        assert_eq!(
+            patches[0].file_path,
+            PathBuf::from("patch_target"),
+            "{}",
+            line
+        );
+        assert_eq!(patches[0].hunks[0].lines[0], format!(" {}", line));
+    }
+
+    // An explicit path wins over the hint.
+    let patches =
+        parse_conflict_markers_for_file("// file: a.rs\n<<<<\nx\n====\ny\n>>>>\n", "b.rs");
+    assert_eq!(patches[0].file_path, PathBuf::from("b.rs"));
+}
+
+#[test]
+fn test_markdown_patch_scanner_matches_parse_diffs_in_small_chunks() {
+    let markdown = "\
+Intro text.\r
+\r
+````markdown\r
+Docs with a nested example that must not be parsed:\r
+```diff\r
+--- a/example.txt\r
++++ b/example.txt\r
+@@ -1 +1 @@\r
+-x\r
++y\r
+```\r
+````\r
+\r
+```diff\r
+--- a/src/main.rs\r
++++ b/src/main.rs\r
+@@ -1,3 +1,3 @@\r
+ fn main() {\r
+-    old();\r
++    new();\r
+ }\r
+```\r
+**src/config.rs**\r
+```\r
+<<<<<<< ORIGINAL\r
+debug = false\r
+=======\r
+debug = true\r
+>>>>>>> UPDATED\r
+```\r
+Done.";
+    let expected = parse_diffs(markdown).unwrap();
+    assert_eq!(expected.len(), 2);
+    assert_eq!(expected[1].file_path, PathBuf::from("src/config.rs"));
+
+    let mut scanner = MarkdownPatchScanner::new();
+    let mut streamed = Vec::new();
+    let mut closed_after = Vec::new();
+    for (i, chunk) in markdown.as_bytes().chunks(7).enumerate() {
+        scanner.push_str(std::str::from_utf8(chunk).unwrap());
+        let patches = scanner.poll_patches().unwrap();
+        if !patches.is_empty() {
+            closed_after.push(i);
+        }
+        streamed.extend(patches);
+    }
+    // Each patch is emitted as soon as its block closes, not at the end.
+    assert_eq!(closed_after.len(), 2);
+    assert!(scanner.finish().unwrap().is_empty());
+    assert_eq!(streamed, expected);
+}
+
+/// The same diff, used to check that every line-ending style parses identically.
+const LINE_ENDING_DIFF: &str =
+    "--- a/notes.txt\n+++ b/notes.txt\n@@ -1,3 +1,3 @@\n first\n-second\n+SECOND\n third\n";
+
+#[test]
+fn test_parse_handles_cr_crlf_and_mixed_line_endings() {
+    let expected = parse_patches(LINE_ENDING_DIFF).unwrap();
+    assert_eq!(expected[0].hunks[0].lines.len(), 4);
+
+    let cr_only = LINE_ENDING_DIFF.replace('\n', "\r");
+    let crlf = LINE_ENDING_DIFF.replace('\n', "\r\n");
+    // LF headers with a CRLF hunk body, and a stray CR-only line in the middle.
+    let mixed = "--- a/notes.txt\n+++ b/notes.txt\n@@ -1,3 +1,3 @@\r\n first\r\n-second\r+SECOND\r\n third\n";
+
+    for fixture in [&cr_only, &crlf, &mixed.to_string()] {
+        assert_eq!(detect_patch(fixture), PatchFormat::Unified);
+        assert_eq!(&parse_patches(fixture).unwrap(), &expected);
+        assert_eq!(&parse_auto(fixture).unwrap(), &expected);
+
+        let fenced = format!("```diff\r{}```\r", fixture);
+        assert_eq!(detect_patch(&fenced), PatchFormat::Markdown);
+        assert_eq!(&parse_diffs(&fenced).unwrap(), &expected);
+        assert_eq!(&parse_auto(&fenced).unwrap(), &expected);
+    }
+}
+
+#[test]
+fn test_cr_only_diff_applies_to_lf_file() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("notes.txt"), "first\nsecond\nthird\n").unwrap();
+
+    let cr_only = format!("```diff\r{}```\r", LINE_ENDING_DIFF.replace('\n', "\r"));
+    let patches = parse_auto(&cr_only).unwrap();
+    let result = apply_patches_to_dir(&patches, dir.path(), ApplyOptions::exact());
+
+    assert!(result.all_succeeded());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("notes.txt")).unwrap(),
+        "first\nSECOND\nthird\n"
+    );
+}
+
+#[test]
+fn test_dry_run_batch_sees_earlier_creations() {
+    let dir = tempdir().unwrap();
+    let create = Patch::from_texts("src/new.rs", "", "fn a() {}\nfn b() {}\n", 3).unwrap();
+    let modify = Patch::from_texts(
+        "src/new.rs",
+        "fn a() {}\nfn b() {}\n",
+        "fn a() {}\nfn c() {}\n",
+        1,
+    )
+    .unwrap();
+    let patches = vec![create, modify];
+
+    let result = apply_patches_to_dir(&patches, dir.path(), ApplyOptions::dry_run());
+    assert!(result.all_succeeded(), "{:?}", result.hard_failures());
+    assert!(!dir.path().join("src/new.rs").exists());
+
+    // The second preview is rendered against the first patch's output.
+    let second_diff = result.results[1].1.as_ref().unwrap().diff.clone().unwrap();
+    assert!(second_diff.contains("-fn b() {}"));
+    assert!(second_diff.contains("+fn c() {}"));
+    assert!(second_diff.contains(" fn a() {}"));
+
+    // A real run agrees with the dry run.
+    let real = apply_patches_to_dir(&patches, dir.path(), ApplyOptions::new());
+    assert!(real.all_succeeded());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/new.rs")).unwrap(),
+        "fn a() {}\nfn c() {}\n"
+    );
+}
+
+#[test]
+fn test_dry_run_preview_of_merged_creation_starts_from_dev_null() {
+    let dir = tempdir().unwrap();
+    // Both sections target the same path, so they are merged into one patch
+    // that starts from a file that does not exist yet.
+    let diff = indoc! {"
+        --- /dev/null
+        +++ b/new.txt
+        @@ -0,0 +1,2 @@
+        +one
+        +two
+        --- a/new.txt
+        +++ b/new.txt
+        @@ -1,2 +1,2 @@
+         one
+        -two
+        +TWO
+    "};
+    let patches = parse_patches(diff).unwrap();
+    assert_eq!(patches.len(), 1);
+
+    let result = apply_patch_to_file(&patches[0], dir.path(), ApplyOptions::dry_run()).unwrap();
+    let preview = result.diff.unwrap();
+    assert!(
+        preview.starts_with("--- /dev/null\n+++ b/new.txt\n"),
+        "{}",
+        preview
+    );
+
+    // The preview is itself a creation patch for the final content.
+    let regenerated = &parse_patches(&preview).unwrap()[0];
+    assert!(regenerated.is_creation());
+    let created = apply_patch_to_content(regenerated, None, &ApplyOptions::exact());
+    assert_eq!(created.new_content, "one\nTWO\n");
+}
+
+#[test]
+fn test_dry_run_batch_sees_earlier_modifications_and_deletions() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("old.txt"), "one\ntwo\n").unwrap();
+
+    let modify = Patch::from_texts("old.txt", "one\ntwo\n", "one\nTWO\n", 3).unwrap();
+    let delete = Patch::from_texts("old.txt", "one\nTWO\n", "", 3).unwrap();
+    let after_delete = Patch::from_texts("old.txt", "one\nTWO\n", "one\nthree\n", 3).unwrap();
+
+    // Modify, then delete: the deletion only matches the modified content.
+    let result = apply_patches_to_dir(
+        &[modify.clone(), delete.clone()],
+        dir.path(),
+        ApplyOptions::dry_run(),
+    );
+    assert!(result.all_succeeded());
+    let delete_diff = result.results[1].1.as_ref().unwrap().diff.clone().unwrap();
+    assert!(delete_diff.contains("-TWO"));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("old.txt")).unwrap(),
+        "one\ntwo\n"
+    );
+
+    // Once deleted, a later modification cannot find its target.
+    let result = apply_patches_to_dir(
+        &[modify, delete, after_delete],
+        dir.path(),
+        ApplyOptions::dry_run(),
+    );
+    assert!(matches!(
+        result.results[2].1,
+        Err(PatchError::TargetNotFound(_))
+    ));
+}
+
+#[test]
+fn test_apply_patches_to_output_dir_mirrors_tree_and_leaves_originals() {
+    let src = tempdir().unwrap();
+    let out = tempdir().unwrap();
+    let out_dir = out.path().join("review");
+    fs::create_dir_all(src.path().join("src/nested")).unwrap();
+    fs::write(src.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+    fs::write(src.path().join("src/nested/util.rs"), "fn util() {}\n").unwrap();
+    fs::write(src.path().join("obsolete.txt"), "gone\n").unwrap();
+    fs::write(src.path().join("untouched.txt"), "same\n").unwrap();
+
+    let patches = vec![
+        Patch::from_texts("src/main.rs", "fn main() {}\n", "fn main() { run(); }\n", 3).unwrap(),
+        Patch::from_texts("src/nested/util.rs", "fn util() {}\n", "fn util2() {}\n", 3).unwrap(),
+        Patch::from_texts("docs/new.md", "", "# New\n", 3).unwrap(),
+        // A second patch for the same file builds on the first one's output.
+        Patch::from_texts(
+            "src/main.rs",
+            "fn main() { run(); }\n",
+            "fn main() { run(); stop(); }\n",
+            3,
+        )
+        .unwrap(),
+        Patch::from_texts("obsolete.txt", "gone\n", "", 3).unwrap(),
+    ];
+
+    let batch = apply_patches_to_output_dir(&patches, src.path(), &out_dir, ApplyOptions::exact());
+    assert!(batch.all_succeeded(), "{:?}", batch.hard_failures());
+    assert!(batch
+        .iter()
+        .all(|e| e.outcome.as_ref().unwrap().report.all_applied_cleanly()));
+
+    // The output tree holds exactly the patched files.
+    let read = |path: &str| fs::read_to_string(out_dir.join(path)).unwrap();
+    assert_eq!(read("src/main.rs"), "fn main() { run(); stop(); }\n");
+    assert_eq!(read("src/nested/util.rs"), "fn util2() {}\n");
+    assert_eq!(read("docs/new.md"), "# New\n");
+    assert!(!out_dir.join("untouched.txt").exists());
+    // The deletion is recorded as written, with no file in the output.
+    let deletion = batch
+        .get(Path::new("obsolete.txt"))
+        .unwrap()
+        .as_ref()
+        .unwrap();
+    assert!(deletion.written);
+    assert!(!out_dir.join("obsolete.txt").exists());
+
+    // The originals are untouched.
+    assert_eq!(
+        fs::read_to_string(src.path().join("src/main.rs")).unwrap(),
+        "fn main() {}\n"
+    );
+    assert_eq!(
+        fs::read_to_string(src.path().join("src/nested/util.rs")).unwrap(),
+        "fn util() {}\n"
+    );
+    assert_eq!(
+        fs::read_to_string(src.path().join("obsolete.txt")).unwrap(),
+        "gone\n"
+    );
+    assert!(!src.path().join("docs").exists());
+
+    // A dry run writes to neither directory.
+    let preview_dir = out.path().join("preview");
+    let preview =
+        apply_patches_to_output_dir(&patches, src.path(), &preview_dir, ApplyOptions::dry_run());
+    assert!(preview.all_succeeded());
+    assert!(!preview_dir.exists());
+}
+
+#[test]
+fn test_apply_patch_to_output_dir_rejects_traversal() {
+    let src = tempdir().unwrap();
+    let out = tempdir().unwrap();
+    let patch = Patch::from_texts("../escape.txt", "", "x\n", 3).unwrap();
+    let result = apply_patch_to_output_dir(&patch, src.path(), out.path(), ApplyOptions::new());
+    assert!(matches!(result, Err(PatchError::PathTraversal(_))));
+}
+
+/// Content with two identical blocks, so that the hunk below is ambiguous.
+const DUPLICATE_BLOCKS: &str = "fn a() {\n    step();\n}\nfn b() {\n    step();\n}\n";
+
+fn ambiguous_step_patch() -> Patch {
+    Patch {
+        file_path: "f.rs".into(),
+        hunks: vec![Hunk {
+            lines: vec![
+                "-    step();".to_string(),
+                "+    step_twice();".to_string(),
+                " }".to_string(),
+            ],
+            old_start_line: None,
+            new_start_line: None,
+            raw_header: None,
+            old_count: None,
+            new_count: None,
+        }],
+        ends_with_newline: true,
+        index_lines: vec![],
+        binary: None,
+        new_file_path: None,
+        deletes_file: false,
+        path_inferred: false,
+        source_block: None,
+    }
+}
+
+#[test]
+fn test_hunk_location_override_resolves_ambiguity() {
+    let patch = ambiguous_step_patch();
+
+    // Without an override, the match is ambiguous.
+    let result = apply_patch_to_content(&patch, Some(DUPLICATE_BLOCKS), &ApplyOptions::exact());
+    let failures = result.report.failures();
+    let HunkApplyError::AmbiguousExactMatch(candidates) = &failures[0].reason else {
+        panic!("unexpected failure: {:?}", failures[0].reason);
+    };
+    assert_eq!(candidates, &vec![1, 4]);
+    assert_eq!(
+        failures[0].reason.to_string(),
+        "Ambiguous exact match found at lines: 2, 5"
+    );
+    let lines: Vec<&str> = DUPLICATE_BLOCKS.lines().collect();
+    let description = failures[0]
+        .reason
+        .describe_candidates(&patch.hunks[0], &lines, 1)
+        .unwrap();
+    assert!(description.contains("Candidate at index 4 (line 5):"));
+    assert!(description.contains("  >   5 |     step();"));
+
+    // A human picks the second candidate.
+    let options = ApplyOptions::exact().with_hunk_location_overrides(HashMap::from([(1, 4)]));
+    let result = apply_patch_to_content(&patch, Some(DUPLICATE_BLOCKS), &options);
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(
+        result.new_content,
+        "fn a() {\n    step();\n}\nfn b() {\n    step_twice();\n}\n"
+    );
+
+    // Whitespace differences at the chosen location are tolerated.
+    let reindented = DUPLICATE_BLOCKS.replace("    step();\n}\nfn b", "\tstep();  \n}\nfn b");
+    let result = apply_patch_to_content(
+        &patch,
+        Some(&reindented),
+        &ApplyOptions::exact().with_hunk_location_overrides(HashMap::from([(1, 1)])),
+    );
+    assert!(result.report.all_applied_cleanly());
+    assert!(matches!(
+        result.report.hunk_results[0],
+        HunkApplyStatus::Applied {
+            match_type: MatchType::ExactIgnoringWhitespace,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_hunk_location_override_rejected_when_content_differs() {
+    let patch = ambiguous_step_patch();
+    let options = ApplyOptions::new().with_hunk_location_overrides(HashMap::from([(1, 3)]));
+    let result = apply_patch_to_content(&patch, Some(DUPLICATE_BLOCKS), &options);
+
+    let failures = result.report.failures();
+    assert_eq!(
+        failures[0].reason,
+        HunkApplyError::LocationOverrideMismatch {
+            start_index: 3,
+            line: 4,
+            expected: "    step();".to_string(),
+            found: Some("fn b() {".to_string()),
+        }
+    );
+    assert_eq!(result.new_content, DUPLICATE_BLOCKS);
+
+    // An override past the end of the content is reported as such.
+    let options = ApplyOptions::new().with_hunk_location_overrides(HashMap::from([(1, 6)]));
+    let result = apply_patch_to_content(&patch, Some(DUPLICATE_BLOCKS), &options);
+    let reason = result.report.failures()[0].reason.to_string();
+    assert!(reason.contains("past the end of the file"), "{}", reason);
+}
+
+const CONTEXT_DIFF_ORIGINAL: &str = "fn main() {\n    let x = 1;\n    println!(\"{}\", x);\n}\n\nfn helper() {\n    // old helper\n    todo!();\n}\n\nfn unused() {}\n\nfn tail() {\n    println!(\"tail\");\n}\n";
+
+#[test]
+fn test_parse_auto_applies_real_context_diff() {
+    // Output of `diff -c a/src/main.rs b/src/main.rs`.
+    let diff = "\
+*** a/src/main.rs\tFri Oct 16 10:45:06 2026
+--- b/src/main.rs\tFri Oct 16 10:45:06 2026
+***************
+*** 1,5 ****
+  fn main() {
+!     let x = 1;
+      println!(\"{}\", x);
+  }
+  
+--- 1,5 ----
+  fn main() {
+!     let x = 2;
+      println!(\"{}\", x);
+  }
+  
+***************
+*** 8,15 ****
+      todo!();
+  }
+  
+- fn unused() {}
+- 
+  fn tail() {
+      println!(\"tail\");
+  }
+--- 8,14 ----
+      todo!();
+  }
+  
+  fn tail() {
+      println!(\"tail\");
++     println!(\"more\");
+  }
+";
+    assert_eq!(detect_patch(diff), PatchFormat::Context);
+    let patches = parse_auto(diff).unwrap();
+    assert_eq!(patches, parse_context_diff(diff).unwrap());
+    assert_eq!(patches.len(), 1);
+    assert_eq!(
+        patches[0].file_path,
+        std::path::PathBuf::from("src/main.rs")
+    );
+    assert_eq!(patches[0].hunks.len(), 2);
+    assert_eq!(patches[0].hunks[1].old_start_line, Some(8));
+    assert_eq!(patches[0].hunks[1].new_start_line, Some(8));
+    assert_eq!(
+        patches[0].hunks[1].removed_lines(),
+        vec!["fn unused() {}", ""]
+    );
+
+    let result = apply_patch_to_content(
+        &patches[0],
+        Some(CONTEXT_DIFF_ORIGINAL),
+        &ApplyOptions::exact(),
+    );
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(
+        result.new_content,
+        "fn main() {\n    let x = 2;\n    println!(\"{}\", x);\n}\n\nfn helper() {\n    // old helper\n    todo!();\n}\n\nfn tail() {\n    println!(\"tail\");\n    println!(\"more\");\n}\n"
+    );
+
+    // The same diff inside a Markdown fence is recognized too.
+    let markdown = format!("```diff\n{}```\n", diff);
+    assert_eq!(parse_auto(&markdown).unwrap(), patches);
+}
+
+#[test]
+fn test_detect_patch_needs_context_hunks_for_context_format() {
+    // A `*** ` line followed by a `--- ` line is just as likely to be prose.
+    let notes = "Notes:\n*** Important\n--- see below\n";
+    assert_eq!(detect_patch(notes), PatchFormat::Unknown);
+    assert!(parse_auto(notes).unwrap().is_empty());
+
+    let with_range = "*** Important\n--- see below\n*** 1,2 ****\n";
+    assert_eq!(detect_patch(with_range), PatchFormat::Context);
+    let with_separator = "*** Important\n--- see below\n***************\n";
+    assert_eq!(detect_patch(with_separator), PatchFormat::Context);
+}
+
+#[test]
+fn test_recursive_diff_summary_lines_do_not_corrupt_hunks() {
+    // `diff -ru a b` output, with the timestamps removed.
+    let diff = "\
+diff -ru a/notes.txt b/notes.txt
+--- a/notes.txt
++++ b/notes.txt
+@@ -1,2 +1,2 @@
+ one
+-two
++TWO
+Only in a: only_old
+Binary files a/logo.png and b/logo.png differ
+diff -ru a/src/main.rs b/src/main.rs
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -1,3 +1,3 @@
+ fn main() {
+-    let x = 1;
++    let x = 2;
+     println!(\"{}\", x);
+Only in b: assets
+";
+    let (patches, report) = parse_auto_with_report(diff).unwrap();
+    assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+    assert_eq!(patches.len(), 2);
+    assert_eq!(patches[0].file_path, std::path::PathBuf::from("notes.txt"));
+    assert_eq!(patches[0].hunks[0].lines, vec![" one", "-two", "+TWO"]);
+    assert_eq!(patches[1].hunks[0].lines.len(), 4);
+
+    let result = apply_patch_to_content(&patches[0], Some("one\ntwo\n"), &ApplyOptions::exact());
+    assert_eq!(result.new_content, "one\nTWO\n");
+}
+
+#[test]
+fn test_original_header_style_round_trips_git_diff() {
+    let diff = "\
+diff --git a/src/lib.rs b/src/lib.rs
+index 3b18e51..a5c1d2f 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1 +1 @@
+-//! Old crate docs.
++//! New crate docs.
+@@ -10,3 +10,4 @@ impl Parser {
+     fn parse(&self) {
+         self.step();
++        self.finish();
+     }
+diff --git a/README.md b/README.md
+old mode 100644
+new mode 100755
+index 83db48f..bf269f4
+--- a/README.md
++++ b/README.md
+@@  -2,2  +2,2  @@   ## Usage  
+ Run it:
+-mpatch old
++mpatch new
+";
+    let patches = parse_patches(diff).unwrap();
+    assert_eq!(patches.len(), 2);
+    assert_eq!(
+        patches[0].hunks[1].raw_header.as_deref(),
+        Some("@@ -10,3 +10,4 @@ impl Parser {")
+    );
+    assert_eq!(patches[1].index_lines.len(), 4);
+
+    let faithful = PatchStyle {
+        header: HeaderStyle::Original,
+        include_index_lines: true,
+    };
+    let round_trip: String = patches.iter().map(|p| p.to_string_with(faithful)).collect();
+    assert_eq!(round_trip, diff);
+
+    // `Display` keeps recomputing headers and leaves out the index lines.
+    assert_eq!(
+        patches[1].to_string(),
+        "--- a/README.md\n+++ b/README.md\n@@ -2,2 +2,2 @@\n Run it:\n-mpatch old\n+mpatch new\n"
+    );
+
+    // Derived hunks have no original header to reproduce.
+    let inverted = patches[0].invert();
+    assert!(inverted.hunks.iter().all(|h| h.raw_header.is_none()));
+    assert!(inverted
+        .to_string_with(faithful)
+        .contains("@@ -10,4 +10,3 @@\n"));
+}
+
+const TWO_HUNK_ORIGINAL: &str = "alpha\nbeta\ngamma\ndelta\nepsilon\nzeta\neta\ntheta\n";
+const TWO_HUNK_DIFF: &str = "\
+--- a/greek.txt
++++ b/greek.txt
+@@ -1,3 +1,3 @@
+ alpha
+-beta
++BETA
+ gamma
+@@ -6,3 +6,3 @@
+ zeta
+-missing
++MISSING
+ theta
+";
+
+#[test]
+fn test_all_or_nothing_leaves_file_untouched_on_partial_apply() {
+    let patch = parse_single_patch(TWO_HUNK_DIFF).unwrap();
+
+    // Default behavior: the first hunk is written even though the second failed.
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("greek.txt");
+    fs::write(&file_path, TWO_HUNK_ORIGINAL).unwrap();
+    let result = apply_patch_to_file(&patch, dir.path(), ApplyOptions::exact()).unwrap();
+    assert_eq!(result.report.failures().len(), 1);
+    assert!(result.written);
+    assert!(fs::read_to_string(&file_path).unwrap().contains("BETA"));
+
+    // With all_or_nothing, the file stays byte-identical.
+    fs::write(&file_path, TWO_HUNK_ORIGINAL).unwrap();
+    let options = ApplyOptions::exact().with_all_or_nothing(true);
+    let result = apply_patch_to_file(&patch, dir.path(), options.clone()).unwrap();
+    assert_eq!(result.report.failures().len(), 1);
+    assert!(!result.written);
+    assert_eq!(fs::read(&file_path).unwrap(), TWO_HUNK_ORIGINAL.as_bytes());
+
+    let err = try_apply_patch_to_file(&patch, dir.path(), options.clone()).unwrap_err();
+    assert!(matches!(
+        err,
+        StrictApplyError::PartialApply { written: false, .. }
+    ));
+    assert_eq!(fs::read(&file_path).unwrap(), TWO_HUNK_ORIGINAL.as_bytes());
+
+    // Batches respect the option for every file.
+    let batch = apply_patches_to_dir(&[patch], dir.path(), options);
+    assert!(batch.all_succeeded());
+    assert!(!batch.results[0].1.as_ref().unwrap().written);
+    assert_eq!(fs::read(&file_path).unwrap(), TWO_HUNK_ORIGINAL.as_bytes());
+}
+
+#[test]
+fn test_write_rejects_saves_failed_hunks() {
+    let patch = parse_single_patch(TWO_HUNK_DIFF).unwrap();
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("greek.txt");
+    let reject_path = dir.path().join("greek.txt.rej");
+    fs::write(&file_path, TWO_HUNK_ORIGINAL).unwrap();
+
+    // Dry runs never write a reject file.
+    let options = ApplyOptions::exact().with_write_rejects(true);
+    let result =
+        apply_patch_to_file(&patch, dir.path(), options.clone().with_dry_run(true)).unwrap();
+    assert_eq!(result.reject_file, None);
+    assert!(!reject_path.exists());
+
+    let result = apply_patch_to_file(&patch, dir.path(), options.clone()).unwrap();
+    assert_eq!(result.report.failures().len(), 1);
+    assert_eq!(result.reject_file.as_deref(), Some(reject_path.as_path()));
+
+    // The reject file is a patch holding only the failed hunk.
+    let rejected = parse_patches(&fs::read_to_string(&reject_path).unwrap()).unwrap();
+    assert_eq!(rejected.len(), 1);
+    assert_eq!(rejected[0].file_path, PathBuf::from("greek.txt"));
+    assert_eq!(rejected[0].hunks, vec![patch.hunks[1].clone()]);
+
+    // Once everything applies, the stale reject file is removed.
+    let content = fs::read_to_string(&file_path).unwrap();
+    fs::write(&file_path, content.replace("\neta\n", "\nmissing\n")).unwrap();
+    let result = apply_patch_to_file(&rejected[0], dir.path(), options).unwrap();
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(result.reject_file, None);
+    assert!(!reject_path.exists());
+}
+
+#[test]
+fn test_describe_rust_patch_uses_section_headings_and_context() {
+    let diff = "\
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -1,4 +1,4 @@
+ pub(crate) async fn main() {
+-    run(1);
++    run(2);
+     finish();
+ }
+@@ -18,3 +18,7 @@ impl<T: Clone> Display for Wrapper<T> {
+     fn fmt(&self) {
+         write();
++        a();
++        b();
++        c();
++        d();
+     }
+";
+    let patches = parse_patches(diff).unwrap();
+    let description = patches[0].describe();
+    assert_eq!(description.kind, FileChangeKind::Modify);
+
+    let first = &description.hunks[0];
+    assert_eq!(first.kind, HunkChangeKind::Replace);
+    assert_eq!((first.added, first.removed, first.line), (1, 1, Some(2)));
+    assert_eq!(first.anchor.as_deref(), Some("`main` function"));
+
+    // The `@@` section heading wins over the context lines.
+    let second = &description.hunks[1];
+    assert_eq!(second.kind, HunkChangeKind::Insert);
+    assert_eq!(second.line, Some(19));
+    assert_eq!(
+        second.anchor.as_deref(),
+        Some("`Display for Wrapper<T>` impl")
+    );
+
+    assert_eq!(
+        description.to_string(),
+        "Modifies src/main.rs: replaces 1 line in the `main` function region (near line 2), \
+         adds 4 lines in the `Display for Wrapper<T>` impl region (after line 19)."
+    );
+}
+
+#[test]
+fn test_describe_python_patches_and_file_creation() {
+    let old = "import os\n\nclass Loader:\n    def load(self, path):\n        data = open(path).read()\n        return data\n";
+    let new =
+        "import os\n\nclass Loader:\n    def load(self, path):\n        return open(path).read()\n";
+    let modified = Patch::from_texts("loader.py", old, new, 2).unwrap();
+    let hunk = &modified.describe().hunks[0];
+    assert_eq!(hunk.kind, HunkChangeKind::Replace);
+    assert_eq!((hunk.removed, hunk.added), (2, 1));
+    assert_eq!(hunk.anchor.as_deref(), Some("`load` function"));
+
+    let created = Patch::from_texts(
+        "tests/test_new.py",
+        "",
+        "def test_it():\n    assert True\n",
+        3,
+    )
+    .unwrap();
+    let untouched_context = Patch::from_texts("notes.txt", "a\nb\nc\n", "a\nb\nc\nd\n", 3).unwrap();
+
+    assert_eq!(
+        describe_patches(&[modified, created, untouched_context]),
+        "Modifies loader.py: replaces 2 lines with 1 line in the `load` function region (near line 5).\n\
+         Creates tests/test_new.py with 2 lines.\n\
+         Modifies notes.txt: adds 1 line after line 3."
+    );
+}
+
+#[test]
+fn test_absurd_line_hint_does_not_resolve_ambiguity() {
+    let mut patch = ambiguous_step_patch();
+    patch.hunks[0].old_start_line = Some(999_999);
+    let result = apply_patch_to_content(&patch, Some(DUPLICATE_BLOCKS), &ApplyOptions::exact());
+    let failures = result.report.failures();
+    assert_eq!(
+        failures[0].reason,
+        HunkApplyError::AmbiguousExactMatch(vec![1, 4])
+    );
+    assert_eq!(result.new_content, DUPLICATE_BLOCKS);
+
+    // A zero hint on a hunk with context is ignored the same way.
+    patch.hunks[0].old_start_line = Some(0);
+    let result = apply_patch_to_content(&patch, Some(DUPLICATE_BLOCKS), &ApplyOptions::exact());
+    assert!(matches!(
+        result.report.failures()[0].reason,
+        HunkApplyError::AmbiguousExactMatch(_)
+    ));
+
+    // A hint within the slack still breaks the tie.
+    patch.hunks[0].old_start_line = Some(999_999);
+    let options = ApplyOptions::exact().with_line_hint_slack(usize::MAX);
+    let result = apply_patch_to_content(&patch, Some(DUPLICATE_BLOCKS), &options);
+    assert!(result.report.all_applied_cleanly());
+    assert!(result.new_content.ends_with("    step_twice();\n}\n"));
+}
+
+#[cfg(feature = "git")]
+#[test]
+fn test_commit_batch_stages_only_patched_files() {
+    use mpatch::{commit_batch, CommitOptions, GitCommitError};
+
+    let dir = tempdir().unwrap();
+    let repo = git2::Repository::init(dir.path()).unwrap();
+    fs::write(dir.path().join("keep.txt"), "one\ntwo\n").unwrap();
+    fs::write(dir.path().join("gone.txt"), "bye\n").unwrap();
+    let mut index = repo.index().unwrap();
+    index.add_path(std::path::Path::new("keep.txt")).unwrap();
+    index.add_path(std::path::Path::new("gone.txt")).unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let sig = git2::Signature::now("Base", "base@example.com").unwrap();
+    repo.commit(Some("HEAD"), &sig, &sig, "base", &tree, &[])
+        .unwrap();
+
+    // An unrelated change in the working tree must not end up in the commit.
+    fs::write(dir.path().join("unrelated.txt"), "untouched\n").unwrap();
+
+    let diff = indoc! {"
+        --- a/keep.txt
+        +++ b/keep.txt
+        @@ -1,2 +1,2 @@
+         one
+        -two
+        +TWO
+        --- a/gone.txt
+        +++ b/gone.txt
+        @@ -1 +0,0 @@
+        -bye
+        --- /dev/null
+        +++ b/added.txt
+        @@ -0,0 +1 @@
+        +hello
+    "};
+    let patches = parse_patches(diff).unwrap();
+    let batch = apply_patches_to_dir(&patches, dir.path(), ApplyOptions::new());
+    assert!(batch.all_succeeded());
+
+    let options = CommitOptions {
+        author_name: Some("Patch Bot".to_string()),
+        author_email: Some("bot@example.com".to_string()),
+        ..Default::default()
+    };
+    let oid = commit_batch(dir.path(), &batch, &patches, "Apply patch", options).unwrap();
+
+    let commit = repo.find_commit(oid).unwrap();
+    assert_eq!(commit.message(), Some("Apply patch"));
+    assert_eq!(commit.author().name(), Some("Patch Bot"));
+    assert_eq!(commit.author().email(), Some("bot@example.com"));
+    assert_eq!(commit.parent_count(), 1);
+
+    let tree = commit.tree().unwrap();
+    let mut names: Vec<String> = tree.iter().map(|e| e.name().unwrap().to_string()).collect();
+    names.sort();
+    assert_eq!(names, vec!["added.txt", "keep.txt"]);
+    let blob = tree
+        .get_name("keep.txt")
+        .unwrap()
+        .to_object(&repo)
+        .unwrap()
+        .peel_to_blob()
+        .unwrap();
+    assert_eq!(blob.content(), b"one\nTWO\n");
+
+    // A batch with a failed hunk is refused unless partial commits are allowed,
+    // and a file held back by `all_or_nothing` is never staged.
+    let bad = parse_patches(indoc! {"
+        --- a/keep.txt
+        +++ b/keep.txt
+        @@ -1 +1 @@
+        -missing line
+        +whatever
+    "})
+    .unwrap();
+    let batch = apply_patches_to_dir(
+        &bad,
+        dir.path(),
+        ApplyOptions::exact().with_all_or_nothing(true),
+    );
+    let err = commit_batch(dir.path(), &batch, &bad, "Bad", CommitOptions::default());
+    assert!(matches!(err, Err(GitCommitError::PartialBatch { failed }) if failed.len() == 1));
+    let err = commit_batch(
+        dir.path(),
+        &batch,
+        &bad,
+        "Bad",
+        CommitOptions {
+            allow_partial: true,
+            ..Default::default()
+        },
+    );
+    assert!(matches!(err, Err(GitCommitError::NothingToCommit)));
+    assert_eq!(repo.head().unwrap().target(), Some(oid));
+}
+
+/// `git diff --cached --binary` output that modifies a text file, adds a small
+/// PNG, and modifies another text file.
+const GIT_BINARY_DIFF: &str = "diff --git a/a.txt b/a.txt
+index 814f4a4..879de50 100644
+--- a/a.txt
++++ b/a.txt
+@@ -1,2 +1,2 @@
+ one
+-two
++TWO
+diff --git a/icon.png b/icon.png
+new file mode 100644
+index 0000000000000000000000000000000000000000..6a68df846aec5ae5f86335a5e3cf91cd93781ff5
+GIT binary patch
+literal 73
+zcmeAS@N?(olHy`uVBq!ia0vp^Od!kwBL7~QRScvAJY5_^D&{2rIDde_g-3vkLH-@{
+U-|l#kD?m90Pgg&ebxsLQ050MZH2?qr
+
+literal 0
+HcmV?d00001
+
+diff --git a/z.txt b/z.txt
+index 587be6b..b77b4eb 100644
+--- a/z.txt
++++ b/z.txt
+@@ -1 +1,2 @@
+ x
++y
+";
+
+#[test]
+fn test_parse_git_binary_patch_literal() {
+    let patches = parse_patches(GIT_BINARY_DIFF).unwrap();
+    assert_eq!(patches.len(), 3);
+
+    // The base85 lines must not leak into the neighbouring text patches.
+    assert_eq!(patches[0].hunks.len(), 1);
+    assert_eq!(patches[0].hunks[0].lines, vec![" one", "-two", "+TWO"]);
+    assert_eq!(patches[2].file_path.to_str(), Some("z.txt"));
+    assert_eq!(patches[2].hunks[0].lines, vec![" x", "+y"]);
+
+    let icon = &patches[1];
+    assert_eq!(icon.file_path.to_str(), Some("icon.png"));
+    assert!(icon.hunks.is_empty());
+    let binary = icon.binary.as_ref().unwrap();
+    assert_eq!(binary.forward.kind, BinaryHunkKind::Literal);
+    assert_eq!(binary.forward.size, 73);
+    assert_eq!(binary.forward.data.len(), 2);
+    assert_eq!(binary.reverse.as_ref().unwrap().size, 0);
+
+    // Formatting with the original headers reproduces git's output exactly.
+    let faithful = PatchStyle {
+        header: HeaderStyle::Original,
+        include_index_lines: true,
+    };
+    let round_trip: String = patches.iter().map(|p| p.to_string_with(faithful)).collect();
+    assert_eq!(round_trip, GIT_BINARY_DIFF);
+
+    // Binary patches are not text, so the in-memory functions reject them.
+    let result = apply_patch_to_content(icon, None, &ApplyOptions::new());
+    assert_eq!(
+        result.report.hunk_results,
+        vec![HunkApplyStatus::Failed(HunkApplyError::BinaryPatch)]
+    );
+    assert!(matches!(
+        try_apply_patch_to_content(icon, None, &ApplyOptions::new()),
+        Err(StrictApplyError::PartialApply { .. })
+    ));
+
+    // A dry run reports the size instead of a text diff.
+    let dir = tempdir().unwrap();
+    let result = apply_patch_to_file(icon, dir.path(), ApplyOptions::dry_run()).unwrap();
+    assert_eq!(result.diff.as_deref(), Some("binary file, 73 bytes\n"));
+    assert!(!dir.path().join("icon.png").exists());
+}
+
+#[cfg(feature = "binary")]
+#[test]
+fn test_apply_git_binary_patch_writes_decoded_bytes() {
+    const RED_PNG: [u8; 73] = [
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x02, 0x08, 0x02, 0x00, 0x00, 0x00, 0xfd,
+        0xd4, 0x9a, 0x73, 0x00, 0x00, 0x00, 0x10, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0xf8,
+        0xcf, 0xc0, 0x00, 0x44, 0x0c, 0x10, 0x0a, 0x00, 0x1f, 0xee, 0x03, 0xfd, 0x8b, 0x5f, 0x14,
+        0xd4, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "one\ntwo\n").unwrap();
+    fs::write(dir.path().join("z.txt"), "x\n").unwrap();
+
+    let patches = parse_patches(GIT_BINARY_DIFF).unwrap();
+    let batch = apply_patches_to_dir(&patches, dir.path(), ApplyOptions::new());
+    assert!(batch.all_succeeded());
+    assert_eq!(fs::read(dir.path().join("icon.png")).unwrap(), RED_PNG);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.txt")).unwrap(),
+        "one\nTWO\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("z.txt")).unwrap(),
+        "x\ny\n"
+    );
+
+    // A literal modification replaces the whole file.
+    let replace = parse_patches(indoc! {"
+        diff --git a/icon.png b/icon.png
+        index 6a68df846aec5ae5f86335a5e3cf91cd93781ff5..87e52631ba45d5671b94c2c70e01028a95787899 100644
+        GIT binary patch
+        literal 74
+        xcmZQz=M$At)-trPck>O2PRcAOuWRj@JZs^K_1pFyIeqEogXizQ{$U_S5dd$jBy9iy
+
+        literal 73
+        zcmeAS@N?(olHy`uVBq!ia0vp^Od!kwBL7~QRScvAJY5_^D&{2rIDde_g-3vkLH-@{
+        U-|l#kD?m90Pgg&ebxsLQ050MZH2?qr
+
+    "})
+    .unwrap();
+    apply_patch_to_file(&replace[0], dir.path(), ApplyOptions::new()).unwrap();
+    let expected: Vec<u8> = (0..=255u8)
+        .step_by(7)
+        .chain((0..=255u8).step_by(7))
+        .collect();
+    assert_eq!(fs::read(dir.path().join("icon.png")).unwrap(), expected);
+
+    // Inverting uses the recorded reverse literal to restore the old bytes.
+    apply_patch_to_file(&replace[0].invert(), dir.path(), ApplyOptions::new()).unwrap();
+    assert_eq!(fs::read(dir.path().join("icon.png")).unwrap(), RED_PNG);
+
+    // Deltas need the old content and are rejected with a clear error.
+    let delta = parse_patches(indoc! {"
+        diff --git a/icon.png b/icon.png
+        index 6a68df846aec5ae5f86335a5e3cf91cd93781ff5..734699a63d73fbb9c58733b572914b5d5cbff47a 100644
+        GIT binary patch
+        delta 41
+        vcmebDn4qG<@9E+gQZXkvA>jwVTLKq@_&4Uib+64oGXR07tDnm{r-UW|At4SM
+
+        delta 42
+        wcmeb9oS>pA;OXKRQZXm_$N2*cE<6HU4D#=o|8~cVTwwqLPgg&ebxsLQ04!Y%)&Kwi
+
+    "})
+    .unwrap();
+    let err = apply_patch_to_file(&delta[0], dir.path(), ApplyOptions::new()).unwrap_err();
+    assert!(matches!(err, PatchError::BinaryPatch { .. }));
+    assert_eq!(fs::read(dir.path().join("icon.png")).unwrap(), RED_PNG);
+
+    // An empty literal deletes the file.
+    let delete = parse_patches(indoc! {"
+        diff --git a/icon.png b/icon.png
+        deleted file mode 100644
+        index 6a68df846aec5ae5f86335a5e3cf91cd93781ff5..0000000000000000000000000000000000000000
+        GIT binary patch
+        literal 0
+        HcmV?d00001
+
+    "})
+    .unwrap();
+    apply_patch_to_file(&delete[0], dir.path(), ApplyOptions::new()).unwrap();
+    assert!(!dir.path().join("icon.png").exists());
+}
+
+#[test]
+fn test_block_origin_points_at_failing_markdown_block() {
+    let doc = indoc! {"
+        First change:
+
+        ```diff
+        --- a/one.txt
+        +++ b/one.txt
+        @@ -1 +1 @@
+        -one
+        +ONE
+        ```
+
+        Second change, against content that is not there:
+
+        ````rust
+        --- a/two.txt
+        +++ b/two.txt
+        @@ -1 +1 @@
+        -missing
+        +TWO
+        ````
+
+        ```diff
+        --- a/three.txt
+        +++ b/three.txt
+        @@ -1 +1 @@
+        -three
+        +THREE
+        ```
+    "};
+    let dir = tempdir().unwrap();
+    for (name, content) in [
+        ("one.txt", "one\n"),
+        ("two.txt", "two\n"),
+        ("three.txt", "three\n"),
+    ] {
+        fs::write(dir.path().join(name), content).unwrap();
+    }
+
+    let patches = parse_auto(doc).unwrap();
+    let origins: Vec<_> = patches
+        .iter()
+        .map(|p| {
+            let o = p.source_block.as_ref().unwrap();
+            (o.start_line, o.end_line, o.fence_info.as_str())
+        })
+        .collect();
+    assert_eq!(
+        origins,
+        vec![(3, 9, "diff"), (13, 19, "rust"), (21, 27, "diff")]
+    );
+
+    let batch = apply_patches_to_dir(&patches, dir.path(), ApplyOptions::exact());
+    let failed: Vec<_> = batch
+        .results
+        .iter()
+        .zip(&batch.origins)
+        .filter(|((_, r), _)| !r.as_ref().unwrap().report.all_applied_cleanly())
+        .map(|((path, _), origin)| (path.clone(), origin.as_ref().unwrap().start_line))
+        .collect();
+    assert_eq!(failed, vec![(std::path::PathBuf::from("two.txt"), 13)]);
+}
+
+#[test]
+fn test_block_origin_for_raw_unified_diff_sections() {
+    let diff = indoc! {"
+        diff --git a/a.txt b/a.txt
+        index 814f4a4..879de50 100644
+        --- a/a.txt
+        +++ b/a.txt
+        @@ -1,2 +1,2 @@
+         one
+        -two
+        +TWO
+
+        --- a/b.txt
+        +++ b/b.txt
+        @@ -1 +1 @@
+        -x
+        +y
+    "};
+    let patches = parse_auto(diff).unwrap();
+    let spans: Vec<_> = patches
+        .iter()
+        .map(|p| {
+            let o = p.source_block.as_ref().unwrap();
+            (o.start_line, o.end_line, o.fence_info.is_empty())
+        })
+        .collect();
+    assert_eq!(spans, vec![(1, 8, true), (10, 14, true)]);
+}
+
+#[test]
+fn test_golden_corpus_replays_unchanged() {
+    use mpatch::test_utils::{load_corpus, run_case};
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let cases = load_corpus(&dir).unwrap();
+    assert!(
+        cases.len() >= 5,
+        "corpus at {} is missing cases",
+        dir.display()
+    );
+
+    let options = ApplyOptions::new();
+    let failures: Vec<String> = cases
+        .iter()
+        .filter_map(|case| {
+            let outcome = run_case(case, &options);
+            (!outcome.is_passed()).then(|| format!("{}: {}", case.name, outcome))
+        })
+        .collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
+
+#[test]
+fn test_run_case_reports_behavioral_drift() {
+    use mpatch::test_utils::{run_case, CaseOutcome, CorpusCase, Expected};
+
+    let mut case = CorpusCase {
+        name: "drift".to_string(),
+        input_diff: "--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n".to_string(),
+        original: Some("a\nb\n".to_string()),
+        expected: Expected::Content("a\nB\n".to_string()),
+    };
+    assert_eq!(
+        run_case(&case, &ApplyOptions::new()),
+        CaseOutcome::ContentMismatch {
+            expected: "a\nB\n".to_string(),
+            actual: "a\nc\n".to_string(),
+        }
+    );
+
+    case.expected = Expected::Failure(vec![1]);
+    assert_eq!(
+        run_case(&case, &ApplyOptions::new()),
+        CaseOutcome::UnexpectedFailures {
+            expected: vec![1],
+            actual: vec![],
+        }
+    );
+
+    case.input_diff = "no patch here".to_string();
+    assert_eq!(
+        run_case(&case, &ApplyOptions::new()),
+        CaseOutcome::WrongPatchCount(0)
+    );
+}
+
+#[test]
+fn test_git_rename_with_hunks_moves_and_edits_file() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("old.rs"), "fn a() {}\nfn b() {}\n").unwrap();
+    let diff = indoc! {"
+        diff --git a/old.rs b/src/new.rs
+        similarity index 80%
+        rename from old.rs
+        rename to src/new.rs
+        index 1111111..2222222 100644
+        --- a/old.rs
+        +++ b/src/new.rs
+        @@ -1,2 +1,2 @@
+         fn a() {}
+        -fn b() {}
+        +fn c() {}
+    "};
+    let patches = parse_patches(diff).unwrap();
+    assert_eq!(patches.len(), 1);
+    assert_eq!(patches[0].file_path, Path::new("old.rs"));
+    assert_eq!(
+        patches[0].new_file_path.as_deref(),
+        Some(Path::new("src/new.rs"))
+    );
+
+    let dry = apply_patches_to_dir(&patches, dir.path(), ApplyOptions::dry_run());
+    let diff_text = dry.results[0].1.as_ref().unwrap().diff.clone().unwrap();
+    assert!(diff_text.contains("rename from old.rs\nrename to src/new.rs\n"));
+    assert!(diff_text.contains("+fn c() {}"));
+    assert!(dir.path().join("old.rs").exists());
+    assert!(!dir.path().join("src/new.rs").exists());
+
+    let batch = apply_patches_to_dir(&patches, dir.path(), ApplyOptions::new());
+    assert!(batch.all_succeeded());
+    assert_eq!(batch.results[0].0, Path::new("src/new.rs"));
+    assert!(!dir.path().join("old.rs").exists());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/new.rs")).unwrap(),
+        "fn a() {}\nfn c() {}\n"
+    );
+
+    // The destination is now taken, so renaming onto it again is refused.
+    fs::write(dir.path().join("old.rs"), "fn a() {}\nfn b() {}\n").unwrap();
+    let err = apply_patch_to_file(&patches[0], dir.path(), ApplyOptions::new()).unwrap_err();
+    assert!(matches!(err, PatchError::RenameTargetExists { .. }));
+}
+
+#[test]
+fn test_git_copy_with_hunks_keeps_source() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.rs"), "fn a() {}\nfn b() {}\n").unwrap();
+    let diff = indoc! {"
+        diff --git a/a.rs b/src/b.rs
+        similarity index 80%
+        copy from a.rs
+        copy to src/b.rs
+        --- a/a.rs
+        +++ b/src/b.rs
+        @@ -1,2 +1,2 @@
+         fn a() {}
+        -fn b() {}
+        +fn c() {}
+    "};
+    let patches = parse_patches(diff).unwrap();
+    assert_eq!(patches.len(), 1);
+    assert!(patches[0].is_copy());
+    assert_eq!(patches[0].destination_path(), Path::new("src/b.rs"));
+
+    let dry = apply_patch_to_file(&patches[0], dir.path(), ApplyOptions::dry_run()).unwrap();
+    let diff_text = dry.diff.unwrap();
+    assert!(diff_text.contains("copy from a.rs\ncopy to src/b.rs\n"));
+    assert!(!diff_text.contains("rename"));
+    assert!(patches[0]
+        .to_string()
+        .contains("copy from a.rs\ncopy to src/b.rs\n"));
+
+    let batch = apply_patches_to_dir(&patches, dir.path(), ApplyOptions::new());
+    assert!(batch.all_succeeded());
+    assert_eq!(batch.results[0].0, Path::new("src/b.rs"));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.rs")).unwrap(),
+        "fn a() {}\nfn b() {}\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/b.rs")).unwrap(),
+        "fn a() {}\nfn c() {}\n"
+    );
+
+    // Reversing the copy removes it and leaves the source alone.
+    let options = ApplyOptions::builder().reverse(true).build();
+    let result = apply_patch_to_file(&patches[0], dir.path(), options).unwrap();
+    assert!(result.removed);
+    assert!(!dir.path().join("src/b.rs").exists());
+    assert!(dir.path().join("a.rs").exists());
+
+    // In memory, the source is kept as well.
+    let mut workspace = VirtualWorkspace::new();
+    workspace.insert("a.rs", "fn a() {}\nfn b() {}\n");
+    assert!(workspace
+        .apply_patches(&patches, &ApplyOptions::new())
+        .all_succeeded());
+    assert_eq!(workspace.get("a.rs"), Some("fn a() {}\nfn b() {}\n"));
+    assert_eq!(workspace.get("src/b.rs"), Some("fn a() {}\nfn c() {}\n"));
+}
+
+#[test]
+fn test_pure_rename_moves_file_without_hunks() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "same\n").unwrap();
+    let diff = indoc! {"
+        diff --git a/a.txt b/docs/b.txt
+        similarity index 100%
+        rename from a.txt
+        rename to docs/b.txt
+        diff --git a/c.txt b/c.txt
+        --- a/c.txt
+        +++ b/c.txt
+        @@ -1 +1 @@
+        -x
+        +y
+    "};
+    let patches = parse_patches(diff).unwrap();
+    assert_eq!(patches.len(), 2);
+    assert!(patches[0].hunks.is_empty());
+    assert_eq!(patches[0].destination_path(), Path::new("docs/b.txt"));
+    assert_eq!(patches[1].new_file_path, None);
+
+    let result = apply_patch_to_file(&patches[0], dir.path(), ApplyOptions::dry_run()).unwrap();
+    assert_eq!(
+        result.diff.as_deref(),
+        Some("diff --git a/a.txt b/docs/b.txt\nrename from a.txt\nrename to docs/b.txt\n")
+    );
+    assert!(dir.path().join("a.txt").exists());
+
+    let result = apply_patch_to_file(&patches[0], dir.path(), ApplyOptions::new()).unwrap();
+    assert!(result.written);
+    assert!(!dir.path().join("a.txt").exists());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("docs/b.txt")).unwrap(),
+        "same\n"
+    );
+
+    // Paths without `a/` and `b/` prefixes that differ are not a rename.
+    let plain = parse_patches("--- old/f.txt\n+++ new/f.txt\n@@ -1 +1 @@\n-x\n+y\n").unwrap();
+    assert_eq!(plain[0].new_file_path, None);
+}
+
+#[test]
+fn test_pure_copy_without_hunks_creates_destination() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "same\n").unwrap();
+    let diff = indoc! {"
+        diff --git a/a.txt b/docs/b.txt
+        similarity index 100%
+        copy from a.txt
+        copy to docs/b.txt
+    "};
+    let patches = parse_patches(diff).unwrap();
+    assert_eq!(patches.len(), 1);
+    assert!(patches[0].is_copy());
+    assert!(patches[0].hunks.is_empty());
+    assert_eq!(patches[0].destination_path(), Path::new("docs/b.txt"));
+
+    let result = apply_patch_to_file(&patches[0], dir.path(), ApplyOptions::dry_run()).unwrap();
+    assert_eq!(
+        result.diff.as_deref(),
+        Some("diff --git a/a.txt b/docs/b.txt\ncopy from a.txt\ncopy to docs/b.txt\n")
+    );
+    assert!(!dir.path().join("docs/b.txt").exists());
+
+    let result = apply_patch_to_file(&patches[0], dir.path(), ApplyOptions::new()).unwrap();
+    assert!(result.written);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.txt")).unwrap(),
+        "same\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("docs/b.txt")).unwrap(),
+        "same\n"
+    );
+}
+
+#[test]
+fn test_rename_rejects_path_traversal_in_either_path() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "same\n").unwrap();
+    for diff in [
+        "diff --git a/a.txt b/../escape.txt\nrename from a.txt\nrename to ../escape.txt\n",
+        "diff --git a/../a.txt b/a2.txt\nrename from ../a.txt\nrename to a2.txt\n",
+    ] {
+        let patches = parse_patches(diff).unwrap();
+        assert_eq!(patches.len(), 1);
+        let err = apply_patch_to_file(&patches[0], dir.path(), ApplyOptions::new()).unwrap_err();
+        assert!(matches!(err, PatchError::PathTraversal(_)), "{err:?}");
+    }
+    assert!(dir.path().join("a.txt").exists());
+}
+
+#[test]
+fn test_parse_from_reader_matches_string_parsers() {
+    let lf = indoc! {"
+        diff --git a/a.txt b/a.txt
+        --- a/a.txt
+        +++ b/a.txt
+        @@ -1,2 +1,2 @@
+         keep
+        -old
+        +new
+        \\ No newline at end of file
+        --- a/b.txt
+        +++ b/b.txt
+        @@ -1 +1 @@
+        -x
+        +y
+    "};
+    for content in [
+        lf.to_string(),
+        lf.replace('\n', "\r\n"),
+        lf.replace('\n', "\r"),
+        lf.trim_end().to_string(),
+    ] {
+        let expected = parse_patches(&content).unwrap();
+        // A tiny buffer forces chunks to be split mid-line.
+        let reader = std::io::BufReader::with_capacity(3, content.as_bytes());
+        assert_eq!(parse_patches_from_reader(reader).unwrap(), expected);
+        assert_eq!(
+            parse_auto_from_reader(content.as_bytes()).unwrap(),
+            parse_auto(&content).unwrap()
+        );
+    }
+
+    let err = parse_patches_from_reader("@@ -1 +1 @@\n-a\n+b\n".as_bytes()).unwrap_err();
+    assert!(matches!(
+        err,
+        ReaderParseError::Parse(ParseError::MissingFileHeader { line: 1 })
+    ));
+}
+
+#[test]
+fn test_parse_from_reader_surfaces_io_errors() {
+    struct FailingReader(Vec<u8>);
+    impl std::io::Read for FailingReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(std::io::Error::other("connection reset"));
+            }
+            let n = buf.len().min(self.0.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0.drain(..n);
+            Ok(n)
+        }
+    }
+
+    let partial = b"--- a/f.txt\n+++ b/f.txt\n@@ -1 +1 @@\n-a\n".to_vec();
+    let reader = std::io::BufReader::new(FailingReader(partial.clone()));
+    let err = parse_patches_from_reader(reader).unwrap_err();
+    assert!(matches!(err, ReaderParseError::Io(ref e) if e.to_string() == "connection reset"));
+
+    let reader = std::io::BufReader::new(FailingReader(partial));
+    assert!(matches!(
+        parse_auto_from_reader(reader),
+        Err(ReaderParseError::Io(_))
+    ));
+}
+
+#[test]
+fn test_atomic_option_replaces_file_only_when_clean() {
+    let patch = parse_single_patch(TWO_HUNK_DIFF).unwrap();
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("greek.txt");
+    fs::write(&file_path, TWO_HUNK_ORIGINAL).unwrap();
+
+    let options = ApplyOptions::exact().with_atomic(true);
+    let result = apply_patch_to_file(&patch, dir.path(), options.clone()).unwrap();
+    assert_eq!(result.report.failures().len(), 1);
+    assert!(!result.written);
+    assert_eq!(fs::read(&file_path).unwrap(), TWO_HUNK_ORIGINAL.as_bytes());
+
+    let batch = apply_patches_to_dir(std::slice::from_ref(&patch), dir.path(), options.clone());
+    assert!(!batch.results[0].1.as_ref().unwrap().written);
+    assert_eq!(fs::read(&file_path).unwrap(), TWO_HUNK_ORIGINAL.as_bytes());
+
+    // Once the second hunk's context is present, the file is replaced.
+    let fixed = TWO_HUNK_ORIGINAL.replace("zeta\neta\n", "zeta\nmissing\n");
+    fs::write(&file_path, &fixed).unwrap();
+    let result = apply_patch_to_file(&patch, dir.path(), options).unwrap();
+    assert!(result.report.all_applied_cleanly());
+    assert!(result.written);
+    let patched = fs::read_to_string(&file_path).unwrap();
+    assert!(patched.contains("BETA") && patched.contains("MISSING"));
+
+    // No temporary files are left next to the target.
+    let names: Vec<_> = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .collect();
+    assert_eq!(names, vec!["greek.txt"]);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_atomic_option_preserves_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("run.sh");
+    fs::write(&file_path, "echo old\n").unwrap();
+    fs::set_permissions(&file_path, fs::Permissions::from_mode(0o750)).unwrap();
+
+    let patch =
+        parse_single_patch("--- a/run.sh\n+++ b/run.sh\n@@ -1 +1 @@\n-echo old\n+echo new\n")
+            .unwrap();
+    let options = ApplyOptions::new().with_atomic(true);
+    let result = apply_patch_to_file(&patch, dir.path(), options).unwrap();
+    assert!(result.written);
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), "echo new\n");
+    let mode = fs::metadata(&file_path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o750);
+}
+
+const GIT_MODE_AND_BINARY_DIFF: &str = "\
+diff --git a/icon.bin b/icon.bin
+index 36b8a989add8f2e5760a47e1812edec388eef91e..8c92b899283f60ae99e9ffdd78b013e5f1443f5f 100644
+GIT binary patch
+literal 7
+OcmZQzWDf9iR|Eh8^#Kb2
+
+literal 6
+NcmZQzWD4+e2LJ))0O<e#
+
+diff --git a/run.sh b/run.sh
+old mode 100644
+new mode 100755
+index a62f486..d046978
+--- a/run.sh
++++ b/run.sh
+@@ -1 +1 @@
+-echo one
++echo two
+diff --git a/tool.sh b/tool.sh
+old mode 100644
+new mode 100755
+";
+
+#[test]
+fn test_parse_git_mode_changes_and_binary_sections() {
+    let patches = parse_patches(GIT_MODE_AND_BINARY_DIFF).unwrap();
+    let paths: Vec<_> = patches.iter().map(|p| p.file_path.clone()).collect();
+    assert_eq!(paths, ["icon.bin", "run.sh", "tool.sh"].map(PathBuf::from));
+
+    assert!(patches[0].binary.is_some());
+    assert!(patches[0].hunks.is_empty());
+
+    assert_eq!(patches[1].hunks.len(), 1);
+    assert_eq!(patches[1].hunks[0].lines, vec!["-echo one", "+echo two"]);
+    assert_eq!(patches[1].old_mode(), Some(0o100644));
+    assert_eq!(patches[1].new_mode(), Some(0o100755));
+
+    assert!(patches[2].hunks.is_empty());
+    assert_eq!(patches[2].new_mode(), Some(0o100755));
+    assert_eq!(
+        patches[2].to_string(),
+        "diff --git a/tool.sh b/tool.sh\nold mode 100644\nnew mode 100755\n"
+    );
+
+    // Without `--binary`, Git only says the files differ, which carries nothing to apply.
+    let plain = GIT_MODE_AND_BINARY_DIFF.replace(
+        "GIT binary patch\nliteral 7\nOcmZQzWDf9iR|Eh8^#Kb2\n\nliteral 6\nNcmZQzWD4+e2LJ))0O<e#\n\n",
+        "Binary files a/icon.bin and b/icon.bin differ\n",
+    );
+    let patches = parse_patches(&plain).unwrap();
+    assert_eq!(patches.len(), 2);
+    assert_eq!(patches[0].hunks[0].lines, vec!["-echo one", "+echo two"]);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_apply_git_mode_changes() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempdir().unwrap();
+    for name in ["run.sh", "tool.sh"] {
+        let path = dir.path().join(name);
+        fs::write(&path, "echo one\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+    }
+    let mode = |name: &str| {
+        fs::metadata(dir.path().join(name))
+            .unwrap()
+            .permissions()
+            .mode()
+            & 0o777
+    };
+    let patches: Vec<_> = parse_patches(GIT_MODE_AND_BINARY_DIFF)
+        .unwrap()
+        .into_iter()
+        .skip(1)
+        .collect();
+
+    let dry = apply_patches_to_dir(&patches, dir.path(), ApplyOptions::dry_run());
+    let tool_diff = dry.results[1].1.as_ref().unwrap().diff.clone().unwrap();
+    assert_eq!(
+        tool_diff,
+        "diff --git a/tool.sh b/tool.sh\nold mode 100644\nnew mode 100755\n"
+    );
+    assert_eq!(mode("tool.sh"), 0o640);
+
+    let batch = apply_patches_to_dir(&patches, dir.path(), ApplyOptions::new());
+    assert!(batch.all_succeeded());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("run.sh")).unwrap(),
+        "echo two\n"
+    );
+    assert_eq!(mode("run.sh"), 0o750);
+    assert_eq!(mode("tool.sh"), 0o750);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("tool.sh")).unwrap(),
+        "echo one\n"
+    );
+
+    // Un-applying the mode change clears the executable bits again.
+    let options = ApplyOptions::new().with_reverse(true);
+    apply_patch_to_file(&patches[1], dir.path(), options).unwrap();
+    assert_eq!(mode("tool.sh"), 0o640);
+}
+
+#[test]
+fn test_ignore_whitespace_modes() {
+    let original = "fn main() {\n\tif ready {\n\t\tgo();\n\t}\n}\n";
+    let diff = indoc! {"
+        --- a/main.rs
+        +++ b/main.rs
+        @@ -2,3 +2,4 @@
+             if ready {
+                 go();
+        +        log();
+             }
+    "};
+    let patch = parse_single_patch(diff).unwrap();
+
+    // By default, only trailing whitespace is ignored, so exact matching fails.
+    let result = apply_patch_to_content(&patch, Some(original), &ApplyOptions::exact());
+    assert!(!result.report.all_applied_cleanly());
+
+    let options = ApplyOptions::exact().with_ignore_whitespace(WhitespaceMode::IgnoreLeading);
+    let result = apply_patch_to_content(&patch, Some(original), &options);
+    assert!(result.report.all_applied_cleanly());
+    assert!(matches!(
+        result.report.hunk_results[0],
+        HunkApplyStatus::Applied {
+            match_type: MatchType::ExactIgnoringIndentation,
+            ..
+        }
+    ));
+    // The file's tabs are kept, and the new line is indented to match.
+    assert_eq!(
+        result.new_content,
+        "fn main() {\n\tif ready {\n\t\tgo();\n\t\tlog();\n\t}\n}\n"
+    );
+
+    // `IgnoreAll` also tolerates whitespace between words.
+    let spaced = original.replace("if ready", "if  ready");
+    let result = apply_patch_to_content(&patch, Some(&spaced), &options);
+    assert!(!result.report.all_applied_cleanly());
+    let options = options.with_ignore_whitespace(WhitespaceMode::IgnoreAll);
+    let result = apply_patch_to_content(&patch, Some(&spaced), &options);
+    assert!(result.report.all_applied_cleanly());
+    assert!(result.new_content.contains("\tif  ready {\n"));
+
+    // `Exact` turns off the trailing-whitespace pass too.
+    let trailing = "a \nb\n";
+    let patch = parse_single_patch("--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n").unwrap();
+    let result = apply_patch_to_content(&patch, Some(trailing), &ApplyOptions::exact());
+    assert!(result.report.all_applied_cleanly());
+    let options = ApplyOptions::exact().with_ignore_whitespace(WhitespaceMode::Exact);
+    let result = apply_patch_to_content(&patch, Some(trailing), &options);
+    assert!(!result.report.all_applied_cleanly());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_apply_result_snapshot() {
+    use mpatch::ApplyResult;
+
+    let report = ApplyResult {
+        hunk_results: vec![
+            HunkApplyStatus::Applied {
+                location: HunkLocation {
+                    start_index: 2,
+                    length: 3,
+                },
+                match_type: MatchType::Exact,
+                replaced_lines: vec!["old".to_string()],
+                interference: None,
+                byte_range: None,
+            },
+            HunkApplyStatus::Applied {
+                location: HunkLocation {
+                    start_index: 10,
+                    length: 1,
+                },
+                match_type: MatchType::Fuzzy {
+                    score: 0.75,
+                    mode: ScoringMode::Ratio,
+                },
+                replaced_lines: vec![],
+                interference: Some(3),
+                byte_range: None,
+            },
+            HunkApplyStatus::SkippedNoChanges,
+            HunkApplyStatus::Failed(HunkApplyError::ContextNotFound),
+            HunkApplyStatus::Failed(HunkApplyError::AmbiguousExactMatch(vec![1, 5])),
+        ],
+        stats: None,
+        line_map: None,
+    };
+
+    let json = serde_json::to_string(&report).unwrap();
+    assert_eq!(
+        json,
+        concat!(
+            r#"{"hunk_results":["#,
+            r#"{"Applied":{"location":{"start_index":2,"length":3},"match_type":"Exact","replaced_lines":["old"],"interference":null,"byte_range":null}},"#,
+            r#"{"Applied":{"location":{"start_index":10,"length":1},"match_type":{"Fuzzy":{"score":0.75,"mode":"Ratio"}},"replaced_lines":[],"interference":3,"byte_range":null}},"#,
+            r#""SkippedNoChanges","#,
+            r#"{"Failed":"ContextNotFound"},"#,
+            r#"{"Failed":{"AmbiguousExactMatch":[1,5]}}"#,
+            r#"]}"#
+        )
+    );
+    let back: ApplyResult = serde_json::from_str(&json).unwrap();
+    assert_eq!(back, report);
+
+    let failure = &report.failures()[0];
+    assert_eq!(
+        serde_json::to_string(failure).unwrap(),
+        r#"{"hunk_index":4,"reason":"ContextNotFound","interference":null}"#
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_patch_options_and_batch_summary() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "one\n").unwrap();
+    let diff = indoc! {"
+        --- a/a.txt
+        +++ b/a.txt
+        @@ -1 +1 @@
+        -one
+        +uno
+        --- a/missing.txt
+        +++ b/missing.txt
+        @@ -1 +1 @@
+        -x
+        +y
+    "};
+    let patches = parse_patches(diff).unwrap();
+
+    // Patches and options round-trip; the canonicalizer is not serialized.
+    let json = serde_json::to_string(&patches).unwrap();
+    let back: Vec<Patch> = serde_json::from_str(&json).unwrap();
+    assert_eq!(back, patches);
+    let options = ApplyOptions::exact().with_ignore_whitespace(WhitespaceMode::IgnoreAll);
+    let json = serde_json::to_string(&options).unwrap();
+    let back: ApplyOptions = serde_json::from_str(&json).unwrap();
+    assert_eq!(back, options);
+    let partial: ApplyOptions = serde_json::from_str(r#"{"dry_run":true}"#).unwrap();
+    assert!(partial.dry_run);
+    assert_eq!(partial.fuzz_factor, ApplyOptions::default().fuzz_factor);
+
+    let batch = apply_patches_to_dir(&patches, dir.path(), ApplyOptions::new());
+    let value = serde_json::to_value(batch.to_summary()).unwrap();
+    let entries = value["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["path"], "a.txt");
+    assert_eq!(entries[0]["written"], true);
+    assert_eq!(entries[0]["error"], serde_json::Value::Null);
+    assert_eq!(entries[1]["report"], serde_json::Value::Null);
+    assert!(entries[1]["error"]
+        .as_str()
+        .unwrap()
+        .starts_with("Target file not found for patching"));
+
+    // Hard errors serialize as their message.
+    let error = &batch.hard_failures()[0].1;
+    assert_eq!(
+        serde_json::to_value(error).unwrap(),
+        serde_json::Value::String(error.to_string())
+    );
+}
+
+#[test]
+fn test_split_hunks_applies_locatable_edit() {
+    let original = indoc! {"
+        fn a() {
+            one();
+            two();
+        }
+        x
+        y
+        z
+        w
+        fn b() {
+            three();
+        }
+    "};
+    let diff = indoc! {"
+        --- a/lib.rs
+        +++ b/lib.rs
+        @@ -1,11 +1,11 @@
+         fn a() {
+        -    one();
+        +    uno();
+             two();
+         }
+         x
+         y
+         z
+         w
+         fn b() {
+        -    THREE();
+        +    tres();
+         }
+    "};
+    let patch = parse_single_patch(diff).unwrap();
+
+    // Without splitting, the whole hunk fails.
+    let result = apply_patch_to_content(&patch, Some(original), &ApplyOptions::exact());
+    assert!(matches!(
+        result.report.hunk_results[0],
+        HunkApplyStatus::Failed(_)
+    ));
+    assert_eq!(result.new_content, original);
+
+    let options = ApplyOptions::exact().with_split_hunks(true);
+    let result = apply_patch_to_content(&patch, Some(original), &options);
+    assert_eq!(result.new_content, original.replace("one()", "uno()"));
+    let HunkApplyStatus::PartiallyApplied { subhunks } = &result.report.hunk_results[0] else {
+        panic!("expected a split hunk: {:?}", result.report.hunk_results[0]);
+    };
+    assert_eq!(subhunks.len(), 2);
+    assert!(matches!(
+        &subhunks[0],
+        HunkApplyStatus::Applied { location, .. } if location.start_index == 0
+    ));
+    assert!(matches!(subhunks[1], HunkApplyStatus::Failed(_)));
+    assert!(!result.report.all_applied_cleanly());
+    assert_eq!(result.report.failures().len(), 1);
+    assert_eq!(result.report.failures()[0].hunk_index, 1);
+
+    // When the drift is only in the context between the edits, every piece applies.
+    let drifted = original.replace("y\n", "why\n").replace("z\n", "zed\n");
+    let patch = parse_single_patch(&diff.replace("THREE", "three")).unwrap();
+    let result = apply_patch_to_content(&patch, Some(&drifted), &options);
+    assert_eq!(
+        result.new_content,
+        drifted
+            .replace("one()", "uno()")
+            .replace("three()", "tres()")
+    );
+    assert!(matches!(
+        result.report.hunk_results[0],
+        HunkApplyStatus::PartiallyApplied { .. }
+    ));
+    assert!(result.report.all_applied_cleanly());
+}
+
+#[test]
+#[cfg(unix)]
+fn test_patched_files_keep_their_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+    let dir = tempdir().unwrap();
+    let script = dir.path().join("build.sh");
+    fs::write(&script, "echo old\n").unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    let patch =
+        parse_single_patch("--- a/build.sh\n+++ b/build.sh\n@@ -1 +1 @@\n-echo old\n+echo new\n")
+            .unwrap();
+
+    // A dry run leaves the file alone.
+    apply_patch_to_file(&patch, dir.path(), ApplyOptions::dry_run()).unwrap();
+    assert_eq!(mode(&script), 0o755);
+
+    // Written out of place, the copy carries the original's mode.
+    let out = tempdir().unwrap();
+    apply_patch_to_output_dir(&patch, dir.path(), out.path(), ApplyOptions::new()).unwrap();
+    assert_eq!(mode(&out.path().join("build.sh")), 0o755);
+
+    // Rewritten in place, the script stays executable.
+    apply_patch_to_file(&patch, dir.path(), ApplyOptions::new()).unwrap();
+    assert_eq!(fs::read_to_string(&script).unwrap(), "echo new\n");
+    assert_eq!(mode(&script), 0o755);
+
+    // So does a renamed copy.
+    let rename = parse_patches(indoc! {"
+        diff --git a/build.sh b/scripts/build.sh
+        similarity index 50%
+        rename from build.sh
+        rename to scripts/build.sh
+        --- a/build.sh
+        +++ b/scripts/build.sh
+        @@ -1 +1 @@
+        -echo new
+        +echo newer
+    "})
+    .unwrap();
+    let batch = apply_patches_to_dir(&rename, dir.path(), ApplyOptions::new());
+    assert!(batch.all_succeeded());
+    assert!(!script.exists());
+    assert_eq!(mode(&dir.path().join("scripts/build.sh")), 0o755);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_new_file_mode_makes_created_file_executable() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempdir().unwrap();
+    let patches = parse_patches(indoc! {"
+        diff --git a/run.sh b/run.sh
+        new file mode 100755
+        index 0000000..d046978
+        --- /dev/null
+        +++ b/run.sh
+        @@ -0,0 +1 @@
+        +echo hi
+    "})
+    .unwrap();
+    assert_eq!(patches[0].new_mode(), Some(0o100755));
+
+    let batch = apply_patches_to_dir(&patches, dir.path(), ApplyOptions::dry_run());
+    assert!(batch.all_succeeded());
+    assert!(!dir.path().join("run.sh").exists());
+
+    let batch = apply_patches_to_dir(&patches, dir.path(), ApplyOptions::new());
+    assert!(batch.all_succeeded());
+    let path = dir.path().join("run.sh");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "echo hi\n");
+    let mode = fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o100, 0o100);
+}
+
+#[test]
+fn test_failed_hunk_inserted_as_conflict_block() {
+    let original: String = (1..=10).map(|i| format!("line {i}\n")).collect();
+    let diff = indoc! {"
+        --- a/file.txt
+        +++ b/file.txt
+        @@ -2,1 +2,1 @@
+        -line 2
+        +line two
+        @@ -6,3 +6,3 @@
+         nowhere a
+        -nowhere b
+        +replacement b
+         nowhere c
+    "};
+    let patch = parse_single_patch(diff).unwrap();
+
+    // By default the hunk is skipped.
+    let result = apply_patch_to_content(&patch, Some(&original), &ApplyOptions::exact());
+    assert!(matches!(
+        result.report.hunk_results[1],
+        HunkApplyStatus::Failed(_)
+    ));
+
+    let options = ApplyOptions::exact().with_on_failure(FailureAction::InsertConflictMarkers);
+    let result = apply_patch_to_content(&patch, Some(&original), &options);
+    let lines: Vec<&str> = result.new_content.lines().collect();
+    assert_eq!(
+        lines[4..11],
+        [
+            "line 5",
+            "<<<<<<< patch",
+            "replacement b",
+            "=======",
+            "nowhere b",
+            ">>>>>>> original",
+            "line 6",
+        ]
+    );
+    assert_eq!(lines[1], "line two");
+    assert!(matches!(
+        &result.report.hunk_results[1],
+        HunkApplyStatus::AppliedWithConflict { location, .. }
+            if location.start_index == 5 && location.length == 5
+    ));
+    assert!(!result.report.all_applied_cleanly());
+    assert_eq!(result.report.failures()[0].hunk_index, 2);
+
+    // Without a line hint, the block goes at the end.
+    let mut patch = patch;
+    patch.hunks[1].old_start_line = None;
+    let result = apply_patch_to_content(&patch, Some(&original), &options);
+    assert!(result.new_content.ends_with(
+        "line 10\n<<<<<<< patch\nreplacement b\n=======\nnowhere b\n>>>>>>> original\n"
+    ));
+
+    // A hint past the end is clamped.
+    patch.hunks[1].old_start_line = Some(500);
+    let result = apply_patch_to_content(&patch, Some(&original), &options);
+    assert!(result.new_content.ends_with(">>>>>>> original\n"));
+
+    // The closing marker ends its line even in content that had no lines, or
+    // no trailing newline, to begin with.
+    let hunk_only = Patch {
+        hunks: vec![patch.hunks[1].clone()],
+        ..patch.clone()
+    };
+    for original in ["", "line 1"] {
+        let result = apply_patch_to_content(&hunk_only, Some(original), &options);
+        assert!(
+            result
+                .new_content
+                .ends_with("=======\nnowhere b\n>>>>>>> original\n"),
+            "{:?}",
+            result.new_content
+        );
+    }
+}
+
+fn eof_test_hunk(lines: &[&str]) -> Hunk {
+    Hunk {
+        lines: lines.iter().map(|l| l.to_string()).collect(),
+        old_start_line: None,
+        new_start_line: None,
+        raw_header: None,
+        old_count: None,
+        new_count: None,
+    }
+}
+
+#[test]
+fn test_eof_anchor_ignores_missing_trailing_blank_lines() {
+    // An editor stripped the two blank lines the hunk still expects at the end.
+    let hunk = eof_test_hunk(&[" fn a() {}", "-fn b() {}", "+fn b() { todo!() }", " ", " "]);
+    let mut lines = vec![
+        "fn main() {}".to_string(),
+        "fn a() {}".to_string(),
+        "fn b() {}".to_string(),
+    ];
+
+    let status = apply_hunk_to_lines(&hunk, &mut lines, &ApplyOptions::exact());
+    assert!(matches!(
+        status,
+        HunkApplyStatus::Applied {
+            location: HunkLocation {
+                start_index: 1,
+                length: 2
+            },
+            match_type: MatchType::ExactAtEof,
+            ..
+        }
+    ));
+    assert_eq!(
+        lines,
+        vec!["fn main() {}", "fn a() {}", "fn b() { todo!() }"]
+    );
+}
+
+#[test]
+fn test_eof_anchor_ignores_missing_trailing_newline_only_context() {
+    let hunk = eof_test_hunk(&[" first", "-second", "+SECOND", " "]);
+    let mut lines = vec!["first".to_string(), "second".to_string()];
+
+    let status = apply_hunk_to_lines(&hunk, &mut lines, &ApplyOptions::exact());
+    assert!(matches!(
+        status,
+        HunkApplyStatus::Applied {
+            match_type: MatchType::ExactAtEof,
+            ..
+        }
+    ));
+    assert_eq!(lines, vec!["first", "SECOND"]);
+}
+
+#[test]
+fn test_eof_anchor_rejects_deletions_past_end_of_file() {
+    // The hunk deletes a line the file does not have, so it must not apply.
+    let hunk = eof_test_hunk(&[" first", " second", "-third", "+THIRD", " "]);
+    let mut lines = vec!["first".to_string(), "second".to_string()];
+
+    let status = apply_hunk_to_lines(&hunk, &mut lines, &ApplyOptions::exact());
+    assert!(matches!(
+        status,
+        HunkApplyStatus::Failed(HunkApplyError::ContextNotFound)
+    ));
+    assert_eq!(lines, vec!["first", "second"]);
+}
+
+#[test]
+fn test_patch_merge_sorts_hunks_and_keeps_unhinted_last() {
+    let base = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+    let late = Patch::from_texts("f.txt", base, "a\nb\nc\nd\ne\nf\ng\nh\ni\nJ\n", 1).unwrap();
+    let early = Patch::from_texts("f.txt", base, "A\nb\nc\nd\ne\nf\ng\nh\ni\nj\n", 1).unwrap();
+    let mut unhinted =
+        Patch::from_texts("f.txt", base, "a\nb\nc\nd\nE\nf\ng\nh\ni\nj\n", 1).unwrap();
+    unhinted.hunks[0].old_start_line = None;
+
+    let merged = late.merge(unhinted).unwrap().merge(early).unwrap();
+    let starts: Vec<_> = merged.hunks.iter().map(|h| h.old_start_line).collect();
+    assert_eq!(starts, vec![Some(1), Some(9), None]);
+
+    let result = apply_patch_to_content(&merged, Some(base), &ApplyOptions::exact());
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(result.new_content, "A\nb\nc\nd\nE\nf\ng\nh\ni\nJ\n");
+}
+
+#[test]
+fn test_patch_merge_rejects_overlaps_and_different_files() {
+    let base = "a\nb\nc\nd\n";
+    let first = Patch::from_texts("f.txt", base, "a\nB\nc\nd\n", 1).unwrap();
+    let second = Patch::from_texts("f.txt", base, "a\nb\nC\nd\n", 1).unwrap();
+    assert_eq!(
+        first.clone().merge(second),
+        Err(MergeError::OverlappingHunks {
+            a_index: 1,
+            b_index: 1
+        })
+    );
+
+    let other_file = Patch::from_texts("g.txt", base, "a\nB\nc\nd\n", 1).unwrap();
+    assert_eq!(
+        first.merge(other_file),
+        Err(MergeError::DifferentFiles {
+            left: PathBuf::from("f.txt"),
+            right: PathBuf::from("g.txt"),
+        })
+    );
+}
+
+#[test]
+fn test_coalesce_patches_groups_by_file_in_first_seen_order() {
+    let diff_one = indoc! {"
+        --- a/b.txt
+        +++ b/b.txt
+        @@ -1,2 +1,2 @@
+        -one
+        +ONE
+         two
+        --- a/a.txt
+        +++ b/a.txt
+        @@ -1 +1 @@
+        -x
+        +y
+    "};
+    let diff_two = indoc! {"
+        --- a/b.txt
+        +++ b/b.txt
+        @@ -9,2 +9,2 @@
+         nine
+        -ten
+        +TEN
+    "};
+    let mut patches = parse_auto(diff_one).unwrap();
+    patches.extend(parse_auto(diff_two).unwrap());
+    assert_eq!(patches.len(), 3);
+
+    let coalesced = coalesce_patches(patches).unwrap();
+    let paths: Vec<_> = coalesced.iter().map(|p| p.file_path.clone()).collect();
+    assert_eq!(paths, vec![PathBuf::from("b.txt"), PathBuf::from("a.txt")]);
+    assert_eq!(coalesced[0].hunks.len(), 2);
+    assert_eq!(coalesced[0].hunks[1].old_start_line, Some(9));
+
+    // Overlapping patches for the same file are rejected.
+    let mut overlapping = parse_auto(diff_two).unwrap();
+    overlapping.extend(parse_auto(diff_two).unwrap());
+    assert!(matches!(
+        coalesce_patches(overlapping),
+        Err(MergeError::OverlappingHunks { .. })
+    ));
+}
+
+/// Delegates to the default finder, counting calls and refusing matches
+/// outside `allowed`.
+struct RecordingFinder<'a> {
+    inner: DefaultHunkFinder<'a>,
+    allowed: std::ops::Range<usize>,
+    calls: std::cell::Cell<usize>,
+}
+
+impl HunkFinder for RecordingFinder<'_> {
+    fn find_location<T: AsRef<str> + Sync>(
+        &self,
+        hunk: &Hunk,
+        target_lines: &[T],
+    ) -> Result<(HunkLocation, MatchType), HunkApplyError> {
+        self.calls.set(self.calls.get() + 1);
+        let (location, match_type) = self.inner.find_location(hunk, target_lines)?;
+        if location.start_index < self.allowed.start
+            || location.start_index + location.length > self.allowed.end
+        {
+            return Err(HunkApplyError::ContextNotFound);
+        }
+        Ok((location, match_type))
+    }
+}
+
+#[test]
+fn test_custom_hunk_finder_is_called_for_every_hunk() {
+    let options = ApplyOptions::exact();
+    let finder = RecordingFinder {
+        inner: DefaultHunkFinder::new(&options),
+        allowed: 0..6,
+        calls: std::cell::Cell::new(0),
+    };
+    let original = "a\nb\nc\nd\ne\nf\ng\nh\n";
+    let diff = indoc! {"
+        --- a/f.txt
+        +++ b/f.txt
+        @@ -1,2 +1,2 @@
+        -a
+        +A
+         b
+        @@ -4,2 +4,2 @@
+         d
+        -e
+        +E
+        @@ -7,2 +7,2 @@
+         g
+        -h
+        +H
+    "};
+    let patch = parse_single_patch(diff).unwrap();
+
+    // The last hunk lies outside the allowed range and fails.
+    let result = apply_patch_to_content_with(&patch, Some(original), &options, &finder);
+    assert_eq!(finder.calls.get(), 3);
+    assert_eq!(result.new_content, "A\nb\nc\nd\nE\nf\ng\nh\n");
+    assert!(matches!(
+        result.report.hunk_results[2],
+        HunkApplyStatus::Failed(HunkApplyError::ContextNotFound)
+    ));
+
+    let lines: Vec<&str> = original.lines().collect();
+    let result = apply_patch_to_lines_with(&patch, Some(&lines), &options, &finder);
+    assert_eq!(finder.calls.get(), 6);
+    assert_eq!(result.report.failures().len(), 1);
+
+    let mut applier = HunkApplier::with_finder(&patch, Some(&lines), &options, &finder);
+    assert!(matches!(
+        applier.next(),
+        Some(HunkApplyStatus::Applied { .. })
+    ));
+    assert_eq!(finder.calls.get(), 7);
+    assert_eq!(applier.count(), 2);
+    assert_eq!(finder.calls.get(), 9);
+
+    let mut owned: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+    let status = apply_hunk_to_lines_with(&patch.hunks[2], &mut owned, &finder);
+    assert!(matches!(status, HunkApplyStatus::Failed(_)));
+    assert_eq!(finder.calls.get(), 10);
+}
+
+#[test]
+fn test_utf8_bom_is_stripped_for_matching_and_kept_on_write() {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("bom.txt");
+    fs::write(&file_path, "\u{feff}line 1\nline 2\n").unwrap();
+    let diff = "--- a/bom.txt\n+++ b/bom.txt\n@@ -1,2 +1,2 @@\n-line 1\n+line one\n line 2\n";
+    let patch = parse_single_patch(diff).unwrap();
+
+    let result = apply_patch_to_file(&patch, dir.path(), ApplyOptions::exact()).unwrap();
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(
+        fs::read(&file_path).unwrap(),
+        b"\xEF\xBB\xBFline one\nline 2\n".to_vec()
+    );
+}
+
+#[test]
+fn test_latin1_file_needs_lossy_policy_and_ascii_only_changes() {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("latin1.txt");
+    let original = b"caf\xe9\nline 2\nline 3\n".to_vec();
+    fs::write(&file_path, &original).unwrap();
+    let diff =
+        "--- a/latin1.txt\n+++ b/latin1.txt\n@@ -2,2 +2,2 @@\n line 2\n-line 3\n+line three\n";
+    let patch = parse_single_patch(diff).unwrap();
+
+    // Strict decoding refuses the file outright.
+    let result = apply_patch_to_file(&patch, dir.path(), ApplyOptions::new());
+    assert!(matches!(result, Err(PatchError::InvalidEncoding { .. })));
+    assert_eq!(fs::read(&file_path).unwrap(), original);
+
+    // Lossy decoding patches ASCII lines and keeps the Latin-1 bytes intact.
+    let lossy = ApplyOptions::new().with_encoding(EncodingPolicy::Utf8Lossy);
+    let result = apply_patch_to_file(&patch, dir.path(), lossy.clone()).unwrap();
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(
+        fs::read(&file_path).unwrap(),
+        b"caf\xe9\nline 2\nline three\n".to_vec()
+    );
+
+    // A non-ASCII change can be previewed, but is not written.
+    let before = fs::read(&file_path).unwrap();
+    let diff = "--- a/latin1.txt\n+++ b/latin1.txt\n@@ -2,1 +2,1 @@\n-line 2\n+na\u{ef}ve\n";
+    let patch = parse_single_patch(diff).unwrap();
+    let preview =
+        apply_patch_to_file(&patch, dir.path(), lossy.clone().with_dry_run(true)).unwrap();
+    assert!(preview.diff.unwrap().contains("+na\u{ef}ve"));
+    let result = apply_patch_to_file(&patch, dir.path(), lossy);
+    assert!(matches!(result, Err(PatchError::InvalidEncoding { .. })));
+    assert_eq!(fs::read(&file_path).unwrap(), before);
+}
+
+#[test]
+fn test_collect_stats_reports_fuzzy_search() {
+    let original = "fn main() {\n    let x = 1;\n    println!(\"{}\", x);\n}\n\nfn other() {}\n";
+    let diff = indoc! {r#"
+        --- a/main.rs
+        +++ b/main.rs
+        @@ -1,4 +1,4 @@
+         fn main() {
+        -    let x = 1 ;
+        +    let x = 2;
+             println!("{}", x );
+         }
+    "#};
+    let patch = parse_single_patch(diff).unwrap();
+
+    let result = apply_patch_to_content(&patch, Some(original), &ApplyOptions::new());
+    assert!(result.report.all_applied_cleanly());
+    assert!(result.report.stats.is_none());
+
+    let options = ApplyOptions::new().with_collect_stats(true);
+    let result = apply_patch_to_content(&patch, Some(original), &options);
+    assert!(result.report.all_applied_cleanly());
+    assert!(result.new_content.contains("let x = 2;"));
+    let stats = result.report.stats.unwrap();
+    assert_eq!(stats.hunks.len(), 1);
+    let hunk = &stats.hunks[0];
+    assert_eq!(hunk.strategy, Some(SearchStrategy::Fuzzy));
+    assert!(hunk.candidates_scored > 0);
+    assert!(hunk.best_score.unwrap() >= options.fuzz_factor as f64);
+    assert!(hunk.second_best_score.unwrap() < hunk.best_score.unwrap());
+    assert!(!hunk.search_ranges.is_empty());
+}
+
+#[test]
+fn test_ambiguous_exact_match_resolved_by_section_heading() {
+    let original = indoc! {"
+        fn alpha() -> u32 {
+            let value = compute();
+            value + 1
+        }
+
+        fn beta() -> u32 {
+            let value = compute();
+            value + 1
+        }
+
+        fn gamma() -> u32 {
+            let value = compute();
+            value + 1
+        }
+    "};
+    // The line hint is far off, but the heading names the enclosing function.
+    let diff = indoc! {"
+        --- a/getters.rs
+        +++ b/getters.rs
+        @@ -406,3 +406,3 @@ fn beta() -> u32 {
+             let value = compute();
+        -    value + 1
+        +    value + 2
+         }
+    "};
+    let patch = parse_single_patch(diff).unwrap();
+    let options = ApplyOptions::exact();
+
+    let result = apply_patch_to_content(&patch, Some(original), &options);
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(
+        result.new_content,
+        original.replacen(
+            "fn beta() -> u32 {\n    let value = compute();\n    value + 1",
+            "fn beta() -> u32 {\n    let value = compute();\n    value + 2",
+            1
+        )
+    );
+
+    // With identical headings everywhere and no line hints, the candidates
+    // still tie and the error reports how each one scored.
+    let repeated = original.replace("alpha", "beta").replace("gamma", "beta");
+    let mut unhinted = patch.clone();
+    unhinted.hunks[0].old_start_line = None;
+    unhinted.hunks[0].new_start_line = None;
+    let result = apply_patch_to_content(&unhinted, Some(&repeated), &options);
+    let failures = result.report.failures();
+    let HunkApplyError::AmbiguousSurroundings { candidates, scores } = &failures[0].reason else {
+        panic!("unexpected failure: {:?}", failures[0].reason);
+    };
+    assert_eq!(candidates, &vec![1, 6, 11]);
+    assert_eq!(scores, &vec![1.0, 1.0, 1.0]);
+    assert_eq!(result.new_content, repeated);
+}
+
+#[test]
+fn test_parse_header_with_timestamps() {
+    let diff = concat!(
+        "--- a/src/main.rs\t2024-01-02 10:00:00.000000000 +0000\n",
+        "+++ b/src/main.rs\t2024-01-02 10:05:00.000000000 +0000\n",
+        "@@ -1 +1 @@\n",
+        "-old\n",
+        "+new\n",
+    );
+    let patch = parse_single_patch(diff).unwrap();
+    assert_eq!(patch.file_path, PathBuf::from("src/main.rs"));
+    assert_eq!(patch.new_file_path, None);
+
+    // Tabs are often turned into spaces when a diff is copied.
+    let diff = concat!(
+        "--- a/my file.txt 2024-01-02 10:00:00.000000000 +0000\n",
+        "+++ b/my file.txt 2024-01-02 10:05:00 +0000\n",
+        "@@ -1 +1 @@\n",
+        "-old\n",
+        "+new\n",
+    );
+    let patch = parse_single_patch(diff).unwrap();
+    assert_eq!(patch.file_path, PathBuf::from("my file.txt"));
+    assert_eq!(patch.new_file_path, None);
+
+    let diff = concat!(
+        "--- f.txt Mon Jan  1 10:00:00 2024\n",
+        "+++ f.txt Mon Jan  1 10:05:00 2024\n",
+        "@@ -1 +1 @@\n",
+        "-old\n",
+        "+new\n",
+    );
+    let patch = parse_single_patch(diff).unwrap();
+    assert_eq!(patch.file_path, PathBuf::from("f.txt"));
+
+    // A name that only looks partly like a date is kept whole.
+    let diff = "--- a/notes 2024-01-02.txt\n+++ b/notes 2024-01-02.txt\n@@ -1 +1 @@\n-old\n+new\n";
+    let patch = parse_single_patch(diff).unwrap();
+    assert_eq!(patch.file_path, PathBuf::from("notes 2024-01-02.txt"));
+}
+
+#[test]
+fn test_parse_header_with_quoted_path_containing_spaces() {
+    let diff = concat!(
+        "--- \"a/my file.txt\"\n",
+        "+++ \"b/my file.txt\"\n",
+        "@@ -1 +1 @@\n",
+        "-old\n",
+        "+new\n",
+    );
+    let patch = parse_single_patch(diff).unwrap();
+    assert_eq!(patch.file_path, PathBuf::from("my file.txt"));
+
+    let diff = concat!(
+        "--- \"a/say \\\"hi\\\".txt\"\t2024-01-02 10:00:00 +0000\n",
+        "+++ \"b/say \\\"hi\\\".txt\"\t2024-01-02 10:05:00 +0000\n",
+        "@@ -1 +1 @@\n",
+        "-old\n",
+        "+new\n",
+    );
+    let patch = parse_single_patch(diff).unwrap();
+    assert_eq!(patch.file_path, PathBuf::from("say \"hi\".txt"));
+}
+
+#[test]
+fn test_parse_header_with_git_octal_escapes() {
+    let diff = concat!(
+        "diff --git \"a/\\346\\226\\207.txt\" \"b/\\346\\226\\207.txt\"\n",
+        "--- \"a/\\346\\226\\207.txt\"\n",
+        "+++ \"b/\\346\\226\\207.txt\"\n",
+        "@@ -1 +1 @@\n",
+        "-old\n",
+        "+new\n",
+    );
+    let patch = parse_single_patch(diff).unwrap();
+    assert_eq!(patch.file_path, PathBuf::from("\u{6587}.txt"));
+
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("\u{6587}.txt"), "old\n").unwrap();
+    let result = apply_patch_to_file(&patch, dir.path(), ApplyOptions::new()).unwrap();
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("\u{6587}.txt")).unwrap(),
+        "new\n"
+    );
+}
+
+#[test]
+fn test_parse_header_normalizes_windows_separators() {
+    let diff = concat!(
+        "--- a\\src\\lib.rs\n",
+        "+++ b\\src\\lib.rs\n",
+        "@@ -1 +1 @@\n",
+        "-old\n",
+        "+new\n",
+    );
+    let patch = parse_single_patch(diff).unwrap();
+    assert_eq!(patch.file_path, PathBuf::from("src/lib.rs"));
+}
+
+#[test]
+fn test_case_insensitive_path_resolution() {
+    let dir = tempdir().unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(dir.path().join("src/Main.rs"), "old\n").unwrap();
+    let diff = "--- a/SRC/main.rs\n+++ b/SRC/main.rs\n@@ -1 +1 @@\n-old\n+new\n";
+    let patch = parse_single_patch(diff).unwrap();
+
+    // By default the path must match exactly (on a case-sensitive file system).
+    if !dir.path().join("src/main.rs").exists() {
+        let result = apply_patch_to_file(&patch, dir.path(), ApplyOptions::new());
+        assert!(matches!(result, Err(PatchError::TargetNotFound(_))));
+    }
+
+    let options = ApplyOptions::new().with_path_resolution(PathResolution::CaseInsensitive);
+    let result = apply_patch_to_file(&patch, dir.path(), options.clone()).unwrap();
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/Main.rs")).unwrap(),
+        "new\n"
+    );
+
+    // Windows separators in a programmatically built patch are normalized too.
+    let mut windows_patch = patch.clone();
+    windows_patch.file_path = PathBuf::from("Src\\MAIN.rs");
+    let reverse = options.clone().with_reverse(true);
+    let result = apply_patch_to_file(&windows_patch, dir.path(), reverse).unwrap();
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/Main.rs")).unwrap(),
+        "old\n"
+    );
+
+    // A new file goes into the existing directory, whatever its case.
+    let creation =
+        parse_single_patch("--- /dev/null\n+++ b/SRC/new.rs\n@@ -0,0 +1 @@\n+fn new() {}\n")
+            .unwrap();
+    apply_patch_to_file(&creation, dir.path(), options.clone()).unwrap();
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/new.rs")).unwrap(),
+        "fn new() {}\n"
+    );
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    fs::remove_file(dir.path().join("src/new.rs")).unwrap();
+
+    // Two files that differ only in case make the match ambiguous.
+    fs::write(dir.path().join("src/MAIN.rs"), "old\n").unwrap();
+    if fs::read_dir(dir.path().join("src")).unwrap().count() == 2 {
+        let result = apply_patch_to_file(&patch, dir.path(), options);
+        let Err(PatchError::AmbiguousTargetPath { candidates, .. }) = result else {
+            panic!("expected an ambiguous path error, got {:?}", result);
+        };
+        assert_eq!(
+            candidates,
+            vec![PathBuf::from("src/MAIN.rs"), PathBuf::from("src/Main.rs")]
+        );
+    }
+}
+
+#[test]
+fn test_path_strip_levels_and_auto() {
+    let dir = tempdir().unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(dir.path().join("src/lib.rs"), "old\n").unwrap();
+    let diff = "--- home/user/project/src/lib.rs\n+++ home/user/project/src/lib.rs\n@@ -1 +1 @@\n-old\n+new\n";
+    let patch = parse_single_patch(diff).unwrap();
+
+    let options = ApplyOptions::new().with_path_strip(PathStrip::Levels(3));
+    let result = apply_patch_to_file(&patch, dir.path(), options).unwrap();
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(result.strip_level, 3);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/lib.rs")).unwrap(),
+        "new\n"
+    );
+
+    // Auto finds the same level, and reports it.
+    let options = ApplyOptions::new()
+        .with_path_strip(PathStrip::Auto)
+        .with_reverse(true);
+    let result = apply_patch_to_file(&patch, dir.path(), options).unwrap();
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(result.strip_level, 3);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/lib.rs")).unwrap(),
+        "old\n"
+    );
+
+    // Stripping every component is an error.
+    let options = ApplyOptions::new().with_path_strip(PathStrip::Levels(5));
+    let result = apply_patch_to_file(&patch, dir.path(), options);
+    assert!(matches!(
+        result,
+        Err(PatchError::InvalidPathStrip { level: 5, .. })
+    ));
+
+    // A file creation in auto mode keeps its path as is.
+    let diff = "--- /dev/null\n+++ b/docs/new.md\n@@ -0,0 +1 @@\n+hello\n";
+    let patch = parse_single_patch(diff).unwrap();
+    let options = ApplyOptions::new().with_path_strip(PathStrip::Auto);
+    let result = apply_patch_to_file(&patch, dir.path(), options).unwrap();
+    assert_eq!(result.strip_level, 0);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("docs/new.md")).unwrap(),
+        "hello\n"
+    );
+
+    // Auto mode never considers a stripped path that leaves the target
+    // directory, even if a file exists there.
+    let project = dir.path().join("project");
+    fs::create_dir(&project).unwrap();
+    fs::write(dir.path().join("outside.txt"), "old\n").unwrap();
+    let diff = "--- x/../outside.txt\n+++ x/../outside.txt\n@@ -1 +1 @@\n-old\n+new\n";
+    let patch = parse_single_patch(diff).unwrap();
+    let options = ApplyOptions::new().with_path_strip(PathStrip::Auto);
+    let result = apply_patch_to_file(&patch, &project, options);
+    assert!(
+        matches!(result, Err(PatchError::TargetNotFound(_))),
+        "{result:?}"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("outside.txt")).unwrap(),
+        "old\n"
+    );
+}
+
+#[test]
+fn test_dry_run_reports_hunk_previews() {
+    let dir = tempdir().unwrap();
+    let original = indoc! {"
+        fn alpha() {
+            one();
+            two();
+            three();
+        }
+
+        fn padding_a() {}
+        fn padding_b() {}
+        fn padding_c() {}
+        fn padding_d() {}
+
+        fn beta() {
+            let value = compute_something_useful();
+            println!(\"{}\", value);
+            finish_the_work();
+        }
+    "};
+    fs::write(dir.path().join("lib.rs"), original).unwrap();
+
+    // The first hunk matches exactly; the second has drifted context.
+    let diff = indoc! {r#"
+        --- a/lib.rs
+        +++ b/lib.rs
+        @@ -1,5 +1,5 @@
+         fn alpha() {
+             one();
+        -    two();
+        +    two_and_a_half();
+             three();
+         }
+        @@ -12,5 +12,5 @@
+         fn beta() {
+             let value = compute_something_useful();
+        -    println!("{}", value);
+        +    println!("value = {}", value);
+             finish_work();
+         }
+    "#};
+    let patch = parse_single_patch(diff).unwrap();
+
+    let result = apply_patch_to_file(&patch, dir.path(), ApplyOptions::dry_run()).unwrap();
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(result.hunk_previews.len(), 2);
+
+    let first = &result.hunk_previews[0];
+    assert_eq!(first.hunk_index, 1);
+    assert_eq!(first.match_type, MatchType::Exact);
+    assert_eq!(first.location.start_index, 0);
+    assert!(first.diff_text.starts_with("@@"));
+    assert!(first.diff_text.contains("+    two_and_a_half();"));
+    assert!(!first.diff_text.contains("value ="));
+
+    let second = &result.hunk_previews[1];
+    assert_eq!(second.hunk_index, 2);
+    assert!(matches!(second.match_type, MatchType::Fuzzy { .. }));
+    assert_eq!(second.location.start_index, 11);
+    assert!(second
+        .diff_text
+        .contains("+    println!(\"value = {}\", value);"));
+    assert!(!second.diff_text.contains("two_and_a_half"));
+
+    // The combined diff is still a plain unified diff.
+    let full = result.diff.unwrap();
+    assert!(full.starts_with("--- a/lib.rs\n+++ b/lib.rs\n"));
+    assert!(!full.contains("# hunk"));
+
+    // Previews are only produced for dry runs.
+    let result = apply_patch_to_file(&patch, dir.path(), ApplyOptions::new()).unwrap();
+    assert!(result.written);
+    assert!(result.hunk_previews.is_empty());
+}
+
+#[test]
+fn test_search_budget_stops_full_scan_of_huge_file() {
+    let original: String = (0..100_000)
+        .map(|i| format!("generated_entry_{} = {};\n", i, i * 7))
+        .collect();
+
+    // None of the hunk's lines occur in the file, so no anchor line can narrow
+    // the search and every window of the file would be scored.
+    let mut diff = String::from("--- a/gen.rs\n+++ b/gen.rs\n@@ -500,30 +500,30 @@\n");
+    for i in 0..29 {
+        diff.push_str(&format!(" unrelated_context_line_{}();\n", i));
+    }
+    diff.push_str("-unrelated_context_line_29();\n+replacement();\n");
+    let patch = parse_single_patch(&diff).unwrap();
+
+    let budget = SearchBudget {
+        max_windows: Some(200),
+        ..Default::default()
+    };
+    let options = ApplyOptions::new()
+        .with_search_budget(budget)
+        .with_collect_stats(true);
+    let result = apply_patch_to_content(&patch, Some(&original), &options);
+
+    let failure = result.report.failures().into_iter().next().unwrap();
+    match &failure.reason {
+        HunkApplyError::SearchBudgetExceeded {
+            windows_scored,
+            best_score_so_far,
+            full_scan,
+        } => {
+            assert!(*windows_scored <= 200);
+            assert!(best_score_so_far.is_some_and(|score| score < 0.7));
+            assert!(full_scan);
+        }
+        other => panic!("expected SearchBudgetExceeded, got {:?}", other),
+    }
+    let stats = result.report.stats.unwrap();
+    assert!(stats.hunks[0].full_scan);
+    assert_eq!(result.new_content, original);
+
+    // A time limit stops the search just the same.
+    let budget = SearchBudget {
+        max_duration: Some(Duration::from_millis(50)),
+        ..Default::default()
+    };
+    let options = ApplyOptions::new().with_search_budget(budget);
+    let result = apply_patch_to_content(&patch, Some(&original), &options);
+    let failure = result.report.failures().into_iter().next().unwrap();
+    assert!(matches!(
+        failure.reason,
+        HunkApplyError::SearchBudgetExceeded {
+            full_scan: true,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_hunk_builder_round_trips_with_parsed_diff() {
+    let diff = indoc! {"
+        --- a/src/lib.rs
+        +++ b/src/lib.rs
+        @@ -1,5 +1,6 @@
+         fn main() {
+        -    old_call();
+        +    new_call();
+             keep();
+        +    added();
+         }
+    "};
+    let parsed = parse_single_patch(diff).unwrap();
+
+    let hunk = Hunk::builder()
+        .context("fn main() {")
+        .remove("    old_call();")
+        .add("    new_call();")
+        .context("    keep();")
+        .add("    added();")
+        .context("}")
+        .build();
+    assert_eq!(hunk.old_start_line, None);
+    assert_eq!(hunk.to_string(), parsed.hunks[0].to_string());
+
+    let built = Patch::single_hunk("src/lib.rs", hunk);
+    assert!(built.ends_with_newline);
+    assert_eq!(built.to_string(), parsed.to_string());
+
+    let original = "// header\nfn main() {\n    old_call();\n    keep();\n}\n";
+    let options = ApplyOptions::new();
+    let from_builder = apply_patch_to_content(&built, Some(original), &options);
+    let from_parser = apply_patch_to_content(&parsed, Some(original), &options);
+    assert!(from_builder.report.all_applied_cleanly());
+    assert_eq!(from_builder.new_content, from_parser.new_content);
+    assert_eq!(
+        from_builder.new_content,
+        "// header\nfn main() {\n    new_call();\n    keep();\n    added();\n}\n"
+    );
+
+    // `Hunk::new` covers the common single-change case.
+    let simple = Hunk::new(
+        &["fn main() {"],
+        &["    old_call();"],
+        &["    new_call();"],
+        &["    keep();"],
+    );
+    let parsed = parse_single_patch(
+        "--- a/f\n+++ b/f\n@@ -1,3 +1,3 @@\n fn main() {\n-    old_call();\n+    new_call();\n     keep();\n",
+    )
+    .unwrap();
+    assert_eq!(simple.lines, parsed.hunks[0].lines);
+    let patch = Patch::new("f", vec![simple]);
+    let result = apply_patch_to_content(&patch, Some(original), &options);
+    assert_eq!(
+        result.new_content,
+        "// header\nfn main() {\n    new_call();\n    keep();\n}\n"
+    );
+}
+
+#[test]
+fn test_skip_already_applied_hunks_on_second_run() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("app.py");
+    let original = indoc! {"
+        import os
+
+        def main():
+            print('hello')
+            return 0
+
+        def helper():
+            pass
+    "};
+    fs::write(&path, original).unwrap();
+
+    let diff = indoc! {"
+        --- a/app.py
+        +++ b/app.py
+        @@ -1,2 +1,3 @@
+         import os
+        +import sys
+         
+        @@ -3,3 +4,3 @@
+         def main():
+        -    print('hello')
+        +    print('hello', file=sys.stderr)
+             return 0
+        @@ -7,2 +8,2 @@
+         def helper():
+        -    pass
+        +    return None
+    "};
+    let patch = parse_single_patch(diff).unwrap();
+    let options = ApplyOptions::new().with_skip_already_applied(true);
+
+    let first = apply_patch_to_file(&patch, dir.path(), options.clone()).unwrap();
+    assert!(first.report.all_applied_cleanly());
+    assert!(first
+        .report
+        .hunk_results
+        .iter()
+        .all(|status| matches!(status, HunkApplyStatus::Applied { .. })));
+    let patched = fs::read(&path).unwrap();
+
+    let second = apply_patch_to_file(&patch, dir.path(), options).unwrap();
+    assert!(second.report.all_applied_cleanly());
+    assert_eq!(second.report.hunk_results.len(), 3);
+    assert!(second
+        .report
+        .hunk_results
+        .iter()
+        .all(|status| matches!(status, HunkApplyStatus::AlreadyApplied { .. })));
+    assert_eq!(fs::read(&path).unwrap(), patched);
+
+    // Without the option, the re-run fails the hunks that delete lines and
+    // inserts the pure addition a second time.
+    let third = apply_patch_to_content(
+        &patch,
+        Some(&String::from_utf8(patched).unwrap()),
+        &ApplyOptions::exact(),
+    );
+    assert_eq!(third.report.failure_count(), 2);
+    assert!(third.new_content.matches("import sys").count() == 2);
+}
+
+#[test]
+fn test_hunk_ranges_agree_with_formatted_headers() {
+    // A small deterministic generator, so the property holds for many shapes
+    // of hunk without a dependency on a property-testing crate.
+    let mut seed: u64 = 0x5eed;
+    let mut next = move |bound: u64| {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (seed >> 33) % bound
+    };
+
+    for case in 0..500 {
+        let hunk_count = 1 + next(3) as usize;
+        let mut hunks = Vec::new();
+        let mut old_start = 1 + next(20) as usize;
+        let mut new_start = old_start;
+        for _ in 0..hunk_count {
+            let mut builder = Hunk::builder();
+            for i in 0..1 + next(8) {
+                builder = match next(3) {
+                    0 => builder.context(format!("ctx {} {}", case, i)),
+                    1 => builder.remove(format!("old {} {}", case, i)),
+                    _ => builder.add(format!("new {} {}", case, i)),
+                };
+            }
+            let mut hunk = builder.build();
+            hunk.old_start_line = Some(old_start);
+            hunk.new_start_line = Some(new_start);
+            let (old, new) = (hunk.old_range().unwrap(), hunk.new_range().unwrap());
+            assert_eq!(new.len() as isize - old.len() as isize, hunk.line_delta());
+            old_start = old.end + 1 + next(10) as usize;
+            new_start = (old_start as isize + (new.end as isize - old.end as isize)) as usize;
+            hunks.push(hunk);
+        }
+        let patch = Patch::new("file.txt", hunks);
+        let expected = patch.affected_line_ranges();
+
+        let reparsed = parse_single_patch(&patch.to_string()).unwrap();
+        assert_eq!(reparsed.affected_line_ranges(), expected, "case {}", case);
+        for (hunk, (old, new)) in reparsed.hunks.iter().zip(&expected) {
+            let header = hunk.raw_header.as_deref().unwrap();
+            let (old, new) = (old.clone().unwrap(), new.clone().unwrap());
+            assert!(
+                header.starts_with(&format!(
+                    "@@ -{},{} +{},{} @@",
+                    old.start,
+                    old.len(),
+                    new.start,
+                    new.len()
+                )),
+                "case {}: {}",
+                case,
+                header
+            );
+        }
+    }
+}
+
+#[test]
+fn test_conflict_markers_two_blocks_in_long_file_become_two_small_hunks() {
+    let original: String = (1..=500).map(|i| format!("line {}\n", i)).collect();
+
+    // The conflicted file keeps our side of lines 100 and 400-401.
+    let mut conflicted = String::new();
+    for (i, line) in original.lines().enumerate().map(|(i, l)| (i + 1, l)) {
+        match i {
+            100 => conflicted
+                .push_str("<<<<<<< ours\nline 100\n=======\nline one hundred\n>>>>>>> theirs\n"),
+            400 => conflicted.push_str(
+                "<<<<<<< ours\nline 400\nline 401\n=======\nline four hundred\n>>>>>>> theirs\n",
+            ),
+            401 => {}
+            _ => conflicted.push_str(&format!("{}\n", line)),
+        }
+    }
+
+    let patches = parse_conflict_markers(&conflicted);
+    assert_eq!(patches.len(), 1);
+    let hunks = &patches[0].hunks;
+    assert_eq!(hunks.len(), 2);
+    assert_eq!(
+        hunks[0].get_match_block(),
+        vec!["line 97", "line 98", "line 99", "line 100", "line 101", "line 102", "line 103"]
+    );
+    assert_eq!(hunks[0].added_lines(), vec!["line one hundred"]);
+    assert_eq!(hunks[1].removed_lines(), vec!["line 400", "line 401"]);
+    assert_eq!(hunks[1].get_match_block().len(), 8);
+
+    let narrow = parse_conflict_markers_with_context(&conflicted, 1);
+    assert_eq!(
+        narrow[0].hunks[0].get_match_block(),
+        vec!["line 99", "line 100", "line 101"]
+    );
+
+    let result = apply_patch_to_content(&patches[0], Some(&original), &ApplyOptions::new());
+    assert!(result.report.all_applied_cleanly());
+    let expected = original
+        .replace("line 100\n", "line one hundred\n")
+        .replace("line 400\nline 401\n", "line four hundred\n");
+    assert_eq!(result.new_content, expected);
+}
+
+#[test]
+fn test_conflict_markers_diff3_base_section_is_deleted() {
+    let diff = indoc! {"
+        before
+        <<<<<<< ours
+        ours
+        ||||||| base
+        base
+        =======
+        theirs
+        >>>>>>> theirs
+        after
+    "};
+    let patches = parse_conflict_markers(diff);
+    assert_eq!(patches[0].hunks.len(), 1);
+    let hunk = &patches[0].hunks[0];
+    assert_eq!(hunk.removed_lines(), vec!["ours", "base"]);
+    assert_eq!(hunk.added_lines(), vec!["theirs"]);
+    assert_eq!(hunk.context_lines(), vec!["before", "after"]);
+}
+
+#[test]
+fn test_rebase_stale_patch_exact_applies_to_newer_version() {
+    let v1: String = (1..=60)
+        .map(|i| format!("fn step_{}() {{}}\n", i))
+        .collect();
+    let mut ours = v1.clone();
+    ours = ours.replace("fn step_10() {}\n", "fn step_10() { todo!() }\n");
+    ours = ours.replace("fn step_40() {}\n", "fn step_40() {}\nfn step_40b() {}\n");
+    let patch = Patch::from_texts("steps.rs", &v1, &ours, 3).unwrap();
+    assert_eq!(patch.hunks.len(), 2);
+
+    // Three later revisions of the file: lines added at the top, a context
+    // line reworded, and a block removed between the two hunks.
+    let v2 = format!("// header\n{}", v1);
+    let v3 = v2.replace("fn step_12() {}\n", "fn step_12() { /* reworded */ }\n");
+    let v4 = v3.replace("fn step_20() {}\nfn step_21() {}\nfn step_22() {}\n", "");
+
+    let exact = ApplyOptions::exact();
+    assert!(!apply_patch_to_content(&patch, Some(&v4), &exact)
+        .report
+        .all_applied_cleanly());
+
+    let rebased = rebase_patch(&patch, &v4, &ApplyOptions::new()).unwrap();
+    assert_eq!(rebased.file_path, patch.file_path);
+    assert_eq!(rebased.hunks.len(), 2);
+    assert_eq!(rebased.hunks[0].old_start_line, Some(8));
+    assert!(rebased.hunks[0]
+        .context_lines()
+        .contains(&"fn step_12() { /* reworded */ }"));
+    assert_eq!(rebased.hunks[1].old_start_line, Some(36));
+    assert_eq!(rebased.hunks[1].new_start_line, Some(36));
+
+    let result = apply_patch_to_content(&rebased, Some(&v4), &exact);
+    assert!(result.report.all_applied_cleanly());
+    let expected = v4
+        .replace("fn step_10() {}\n", "fn step_10() { todo!() }\n")
+        .replace("fn step_40() {}\n", "fn step_40() {}\nfn step_40b() {}\n");
+    assert_eq!(result.new_content, expected);
+
+    // The rebased patch survives a round trip through its text form.
+    let reparsed = parse_single_patch(&rebased.to_string()).unwrap();
+    let result = apply_patch_to_content(&reparsed, Some(&v4), &exact);
+    assert_eq!(result.new_content, expected);
+}
+
+#[test]
+fn test_rebase_patch_reports_failing_hunk() {
+    let patch = parse_single_patch(indoc! {"
+        --- a/f
+        +++ b/f
+        @@ -1,2 +1,2 @@
+         a
+        -b
+        +B
+        @@ -10,2 +10,2 @@
+         nowhere
+        -to be found
+        +here
+    "})
+    .unwrap();
+    let err = rebase_patch(&patch, "a\nb\nc\n", &ApplyOptions::new()).unwrap_err();
+    match err {
+        StrictApplyError::PartialApply { report, written } => {
+            assert!(!written);
+            assert_eq!(report.hunk_results.len(), 2);
+            assert_eq!(report.failures()[0].hunk_index, 2);
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[test]
+fn test_path_checks_leave_the_directory_tree_untouched() {
+    fn tree(root: &Path) -> Vec<PathBuf> {
+        let mut entries = Vec::new();
+        let mut pending = vec![root.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            for entry in fs::read_dir(&dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    pending.push(path.clone());
+                }
+                entries.push(path.strip_prefix(root).unwrap().to_path_buf());
+            }
+        }
+        entries.sort();
+        entries
+    }
+
+    let dir = tempdir().unwrap();
+    let base = dir.path().join("base");
+    fs::create_dir_all(base.join("src")).unwrap();
+    fs::write(base.join("src/lib.rs"), "fn a() {}\n").unwrap();
+    let before = tree(dir.path());
+
+    let diff = indoc! {"
+        ```diff
+        --- /dev/null
+        +++ b/brand/new/dir/file.txt
+        @@ -0,0 +1 @@
+        +hello
+        ```
+        ```diff
+        --- /dev/null
+        +++ b/stray/deeper/../../../escape.txt
+        @@ -0,0 +1 @@
+        +hacked
+        ```
+    "};
+    let patches = parse_diffs(diff).unwrap();
+
+    // A dry run previews the new file without creating its directories.
+    let result = apply_patch_to_file(&patches[0], &base, ApplyOptions::dry_run()).unwrap();
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(tree(dir.path()), before);
+
+    // A path that climbs out of the base through new directories is rejected
+    // before anything is created, in a real run as well.
+    for options in [ApplyOptions::dry_run(), ApplyOptions::new()] {
+        let result = apply_patch_to_file(&patches[1], &base, options);
+        assert!(matches!(result, Err(PatchError::PathTraversal(_))));
+        assert_eq!(tree(dir.path()), before);
+    }
+    assert!(matches!(
+        ensure_path_is_safe(&base, Path::new("stray/deeper/../../../escape.txt")),
+        Err(PatchError::PathTraversal(_))
+    ));
+    assert_eq!(tree(dir.path()), before);
+
+    // Only a real run creates the directories, when it writes the file.
+    apply_patch_to_file(&patches[0], &base, ApplyOptions::new()).unwrap();
+    assert_eq!(
+        fs::read_to_string(base.join("brand/new/dir/file.txt")).unwrap(),
+        "hello\n"
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn test_symlinked_parent_directory_outside_base_is_rejected() {
+    let dir = tempdir().unwrap();
+    let base = dir.path().join("base");
+    let outside = dir.path().join("outside");
+    fs::create_dir(&base).unwrap();
+    fs::create_dir(&outside).unwrap();
+    std::os::unix::fs::symlink(&outside, base.join("linked")).unwrap();
+
+    assert!(matches!(
+        ensure_path_is_safe(&base, Path::new("linked/new/file.txt")),
+        Err(PatchError::PathTraversal(_))
+    ));
+
+    let patch = Patch::single_hunk("linked/new/file.txt", Hunk::builder().add("x").build());
+    let result = apply_patch_to_file(&patch, &base, ApplyOptions::new());
+    assert!(matches!(result, Err(PatchError::PathTraversal(_))));
+    assert!(!outside.join("new").exists());
+}
+
+#[test]
+fn test_crlf_file_stays_crlf_after_lf_patch() {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("config.ini");
+    fs::write(&file_path, "[core]\r\nname = old\r\nmode = fast\r\n").unwrap();
+
+    let diff = indoc! {"
+        ```diff
+        --- a/config.ini
+        +++ b/config.ini
+        @@ -1,3 +1,4 @@
+         [core]
+        -name = old
+        +name = new
+        +color = blue
+         mode = fast
+        ```
+    "};
+    let patch = &parse_diffs(diff).unwrap()[0];
+
+    let result = apply_patch_to_file(patch, dir.path(), ApplyOptions::exact()).unwrap();
+    assert!(result.report.all_applied_cleanly());
+    let written = fs::read_to_string(&file_path).unwrap();
+    assert_eq!(
+        written,
+        "[core]\r\nname = new\r\ncolor = blue\r\nmode = fast\r\n"
+    );
+    assert_eq!(
+        written.matches('\n').count(),
+        written.matches("\r\n").count()
+    );
+
+    // The explicit policies override the original style either way.
+    let crlf = "[core]\r\nname = old\r\nmode = fast\r\n";
+    let options = ApplyOptions::exact().with_line_ending(LineEndingPolicy::Lf);
+    let result = apply_patch_to_content(patch, Some(crlf), &options);
+    assert_eq!(
+        result.new_content,
+        "[core]\nname = new\ncolor = blue\nmode = fast\n"
+    );
+
+    let lf = "[core]\nname = old\nmode = fast\n";
+    let options = ApplyOptions::exact().with_line_ending(LineEndingPolicy::Crlf);
+    let result = apply_patch_to_content(patch, Some(lf), &options);
+    assert_eq!(
+        result.new_content,
+        "[core]\r\nname = new\r\ncolor = blue\r\nmode = fast\r\n"
+    );
+
+    // A hunk built from CRLF text matches too.
+    let hunk = Hunk::new(&["[core]\r"], &["name = old\r"], &["name = newer\r"], &[]);
+    let result = apply_patch_to_content(
+        &Patch::single_hunk("config.ini", hunk),
+        Some(crlf),
+        &ApplyOptions::exact(),
+    );
+    assert_eq!(
+        result.new_content,
+        "[core]\r\nname = newer\r\nmode = fast\r\n"
+    );
+}
+
+#[test]
+fn test_hunk_applier_undo_and_reapply_matches_full_apply() {
+    let original: Vec<String> = (1..=40).map(|i| format!("line {}", i)).collect();
+    let mut modified = original.clone();
+    modified[4] = "line five".to_string();
+    modified.insert(20, "inserted after 20".to_string());
+    modified.remove(35);
+    let patch = Patch::from_texts(
+        "f.txt",
+        &(original.join("\n") + "\n"),
+        &(modified.join("\n") + "\n"),
+        3,
+    )
+    .unwrap();
+    assert_eq!(patch.hunks.len(), 3);
+    let options = ApplyOptions::new();
+
+    let mut full = HunkApplier::new(&patch, Some(&original), &options);
+    let full_statuses: Vec<_> = full.by_ref().collect();
+    let expected = full.into_content();
+
+    let mut applier = HunkApplier::new(&patch, Some(&original), &options);
+    let after_first = {
+        applier.next().unwrap();
+        applier.current_lines().to_vec()
+    };
+    applier.next().unwrap();
+    applier.next().unwrap();
+    assert!(applier.next().is_none());
+
+    applier.undo_last().unwrap();
+    applier.undo_last().unwrap();
+    assert_eq!(applier.current_lines(), after_first.as_slice());
+    assert_eq!(applier.remaining_hunks().len(), 2);
+
+    let reapplied: Vec<_> = applier.by_ref().collect();
+    assert_eq!(reapplied, full_statuses[1..]);
+    assert_eq!(applier.into_content(), expected);
+
+    // Undoing everything restores the original, and then there is nothing left.
+    let mut applier = HunkApplier::new(&patch, Some(&original), &options);
+    applier.by_ref().for_each(drop);
+    for _ in 0..3 {
+        applier.undo_last().unwrap();
+    }
+    assert_eq!(applier.current_lines(), original.as_slice());
+    assert_eq!(applier.undo_last(), Err(UndoError::NothingToUndo));
+}
+
+#[test]
+fn test_hunk_applier_undo_after_skip_failure_and_conflict() {
+    let original = vec!["a", "b", "c", "d", "e"];
+    let diff = indoc! {"
+        --- a/f
+        +++ b/f
+        @@ -1,2 +1,2 @@
+         a
+        -b
+        +B
+        @@ -3,2 +3,2 @@
+         missing
+        -context
+        +here
+        @@ -4,2 +4,2 @@
+         d
+        -e
+        +E
+    "};
+    let patch = parse_single_patch(diff).unwrap();
+
+    // A failed step and a skipped step leave the content alone, but undoing
+    // them still rewinds to their hunk.
+    let options = ApplyOptions::exact();
+    let mut applier = HunkApplier::new(&patch, Some(&original), &options);
+    applier.next();
+    assert!(matches!(applier.next(), Some(HunkApplyStatus::Failed(_))));
+    let skipped = applier.skip_next().unwrap().clone();
+    assert_eq!(skipped.added_lines(), vec!["E"]);
+    assert!(applier.skip_next().is_none());
+    let before = applier.current_lines().to_vec();
+    applier.undo_last().unwrap();
+    applier.undo_last().unwrap();
+    assert_eq!(applier.current_lines(), before.as_slice());
+    assert_eq!(applier.remaining_hunks().len(), 2);
+    applier.undo_last().unwrap();
+    assert_eq!(applier.current_lines(), original.as_slice());
+
+    // A hunk inserted as a conflict block is removed again as well.
+    let options = ApplyOptions::exact().with_on_failure(FailureAction::InsertConflictMarkers);
+    let mut applier = HunkApplier::new(&patch, Some(&original), &options);
+    applier.next();
+    assert!(matches!(
+        applier.next(),
+        Some(HunkApplyStatus::AppliedWithConflict { .. })
+    ));
+    applier.next();
+    assert!(applier
+        .current_lines()
+        .contains(&"<<<<<<< patch".to_string()));
+    applier.undo_last().unwrap();
+    applier.undo_last().unwrap();
+    assert_eq!(applier.current_lines(), &["a", "B", "c", "d", "e"]);
+    assert_eq!(
+        applier.next(),
+        Some(HunkApplyStatus::AppliedWithConflict {
+            location: HunkLocation {
+                start_index: 2,
+                length: 5
+            },
+            reason: HunkApplyError::ContextNotFound,
+        })
+    );
+}
+
+#[test]
+fn test_parse_subversion_diff_with_index_lines() {
+    let diff = "Index: src/foo.c\n\
+        ===================================================================\n\
+        --- src/foo.c\t(revision 123)\n\
+        +++ src/foo.c\t(working copy)\n\
+        @@ -1,3 +1,3 @@\n \
+        int main(void) {\n\
+        -    return 1;\n\
+        +    return 0;\n \
+        }\n\
+        Index: include/foo.h\n\
+        ===================================================================\n\
+        --- include/foo.h (revision 123)\n\
+        +++ include/foo.h (working copy)\n\
+        @@ -1,2 +1,3 @@\n \
+        #pragma once\n\
+        +int foo(void);\n \
+        int bar(void);\n";
+
+    assert_eq!(detect_patch(diff), PatchFormat::Unified);
+    let patches = parse_auto(diff).unwrap();
+    assert_eq!(patches.len(), 2);
+    assert_eq!(patches[0].file_path, PathBuf::from("src/foo.c"));
+    assert_eq!(patches[1].file_path, PathBuf::from("include/foo.h"));
+    assert_eq!(
+        patches[0].hunks[0].lines,
+        vec![
+            " int main(void) {",
+            "-    return 1;",
+            "+    return 0;",
+            " }"
+        ]
+    );
+    assert_eq!(patches[1].hunks[0].lines.len(), 3);
+
+    let dir = tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("src")).unwrap();
+    fs::create_dir_all(dir.path().join("include")).unwrap();
+    fs::write(
+        dir.path().join("src/foo.c"),
+        "int main(void) {\n    return 1;\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("include/foo.h"),
+        "#pragma once\nint bar(void);\n",
+    )
+    .unwrap();
+    let results = apply_patches_to_dir(&patches, dir.path(), ApplyOptions::exact());
+    assert!(results.all_succeeded());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("include/foo.h")).unwrap(),
+        "#pragma once\nint foo(void);\nint bar(void);\n"
+    );
+}
+
+#[test]
+fn test_explain_hunk_failure_best_candidate_matches_error_location() {
+    let mut target: Vec<String> = (1..=40).map(|i| format!("let value_{i} = {i};")).collect();
+    target[20] = "let value_21 = compute(21);".to_string();
+    target[21] = "let renamed = 22;".to_string();
+    let diff = indoc! {"
+        --- a/f.rs
+        +++ b/f.rs
+        @@ -20,5 +20,5 @@
+         let value_20 = 20;
+         let value_21 = 21;
+        -let value_22 = 22;
+        +let value_22 = 220;
+         let value_23 = 23;
+         let value_24 = 24;
+    "};
+    let hunk = parse_single_patch(diff).unwrap().hunks.remove(0);
+    let options = ApplyOptions::new().with_fuzz_factor(0.95);
+
+    let error = find_hunk_location_in_lines(&hunk, &target, &options).unwrap_err();
+    let HunkApplyError::FuzzyMatchBelowThreshold {
+        best_score,
+        threshold,
+        location,
+        ..
+    } = error
+    else {
+        panic!("expected a below-threshold failure, got {error:?}");
+    };
+
+    let explanation = explain_hunk_failure(&hunk, &target, &options);
+    let best = explanation.best().unwrap();
+    assert_eq!(best.location, location);
+    assert!((best.score - best_score).abs() < 1e-9);
+    assert_eq!(explanation.threshold, threshold);
+    assert_eq!(
+        best.lines,
+        target[location.start_index..][..location.length].to_vec()
+    );
+    assert!(explanation
+        .diff
+        .contains(&"-let value_22 = 22;".to_string()));
+    assert!(explanation.diff.contains(&"+let renamed = 22;".to_string()));
+    assert!(explanation
+        .diff
+        .contains(&" let value_20 = 20;".to_string()));
+
+    // The other candidates score no higher and never overlap each other.
+    assert!(explanation.candidates.len() > 1);
+    for pair in explanation.candidates.windows(2) {
+        assert!(pair[0].score >= pair[1].score);
+    }
+    for (i, a) in explanation.candidates.iter().enumerate() {
+        for b in &explanation.candidates[i + 1..] {
+            let a_end = a.location.start_index + a.location.length;
+            let b_end = b.location.start_index + b.location.length;
+            assert!(a_end <= b.location.start_index || b_end <= a.location.start_index);
+        }
+    }
+}
+
+#[test]
+fn test_explain_hunk_failure_without_lines_to_match() {
+    let hunk = parse_single_patch("--- /dev/null\n+++ b/new.txt\n@@ -0,0 +1 @@\n+hi\n")
+        .unwrap()
+        .hunks
+        .remove(0);
+    let explanation = explain_hunk_failure(&hunk, &["existing"], &ApplyOptions::new());
+    assert!(explanation.best().is_none());
+    assert!(explanation.diff.is_empty());
+}
+
+#[test]
+fn test_parse_diffs_with_allowed_languages_filters_blocks() {
+    let md = indoc! {r#"
+        Quoted for reference:
+        ```text
+        --- a/old.txt
+        +++ b/old.txt
+        @@ -1 +1 @@
+        -a
+        +b
+        ```
+
+        The fix:
+        ```Diff
+        --- a/fix.txt
+        +++ b/fix.txt
+        @@ -1 +1 @@
+        -c
+        +d
+        ```
+
+        ```rust, patch
+        --- a/lib.rs
+        +++ b/lib.rs
+        @@ -1 +1 @@
+        -e
+        +f
+        ```
+    "#};
+
+    // The existing functions keep scanning every block.
+    assert_eq!(parse_diffs(md).unwrap().len(), 3);
+    assert_eq!(
+        parse_diffs_with(md, &MarkdownParseOptions::default()).unwrap(),
+        parse_diffs(md).unwrap()
+    );
+
+    let options = MarkdownParseOptions {
+        allowed_languages: Some(vec!["diff".to_string(), "patch".to_string()]),
+        ..Default::default()
+    };
+    let patches = parse_diffs_with(md, &options).unwrap();
+    let paths: Vec<_> = patches.iter().map(|p| p.file_path.clone()).collect();
+    assert_eq!(
+        paths,
+        vec![PathBuf::from("fix.txt"), PathBuf::from("lib.rs")]
+    );
+    assert_eq!(parse_auto_with(md, &options).unwrap(), patches);
+}
+
+#[test]
+fn test_parse_diffs_with_require_language_tag_skips_unlabeled_blocks() {
+    let md = indoc! {r#"
+        ```
+        --- a/bare.txt
+        +++ b/bare.txt
+        @@ -1 +1 @@
+        -a
+        +b
+        ```
+        ```diff
+        --- a/labeled.txt
+        +++ b/labeled.txt
+        @@ -1 +1 @@
+        -a
+        +b
+        ```
+    "#};
+    let options = MarkdownParseOptions {
+        require_language_tag: true,
+        ..Default::default()
+    };
+    let patches = parse_diffs_with(md, &options).unwrap();
+    assert_eq!(patches.len(), 1);
+    assert_eq!(patches[0].file_path, PathBuf::from("labeled.txt"));
+
+    // An allow-list also rejects unlabeled blocks.
+    let options = MarkdownParseOptions {
+        allowed_languages: Some(vec!["diff".to_string()]),
+        ..Default::default()
+    };
+    assert_eq!(parse_diffs_with(md, &options).unwrap().len(), 1);
+}
+
+#[test]
+fn test_parse_diffs_with_skips_blocks_under_matching_heading() {
+    let md = indoc! {r#"
+        # Changes
+        ```diff
+        --- a/one.txt
+        +++ b/one.txt
+        @@ -1 +1 @@
+        -a
+        +b
+        ```
+
+        ## Examples
+        ```diff
+        # Not a heading: this line is inside a block.
+        --- a/example.txt
+        +++ b/example.txt
+        @@ -1 +1 @@
+        -a
+        +b
+        ```
+        ```diff
+        --- a/example2.txt
+        +++ b/example2.txt
+        @@ -1 +1 @@
+        -a
+        +b
+        ```
+
+        ## More changes
+        ```diff
+        --- a/two.txt
+        +++ b/two.txt
+        @@ -1 +1 @@
+        -a
+        +b
+        ```
+    "#};
+    let options = MarkdownParseOptions {
+        skip_blocks_after_heading: Some("Examples".to_string()),
+        ..Default::default()
+    };
+    let patches = parse_diffs_with(md, &options).unwrap();
+    let paths: Vec<_> = patches.iter().map(|p| p.file_path.clone()).collect();
+    assert_eq!(
+        paths,
+        vec![PathBuf::from("one.txt"), PathBuf::from("two.txt")]
+    );
+}
+
+#[test]
+fn test_line_map_tracks_added_lines_through_later_hunks() {
+    let original: Vec<String> = (1..=30).map(|i| format!("line {i}")).collect();
+    let diff = indoc! {"
+        --- a/f.txt
+        +++ b/f.txt
+        @@ -2,3 +2,5 @@
+         line 2
+        +first a
+        +first b
+         line 3
+         line 4
+        @@ -10,4 +12,2 @@
+         line 10
+        -line 11
+        -line 12
+         line 13
+        @@ -20,3 +20,5 @@
+         line 20
+        -line 21
+        +third a
+        +third b
+        +third c
+         line 22
+    "};
+    let patch = parse_single_patch(diff).unwrap();
+    let options = ApplyOptions::new().with_build_line_map(true);
+
+    let result = apply_patch_to_lines(&patch, Some(&original), &options);
+    assert!(result.report.all_applied_cleanly());
+    let lines: Vec<&str> = result.new_content.lines().collect();
+    let map = result.report.line_map.unwrap();
+
+    assert_eq!(map.added_line_positions(3), vec![20, 21, 22]);
+    for (position, expected) in map
+        .added_line_positions(3)
+        .into_iter()
+        .zip(["third a", "third b", "third c"])
+    {
+        assert_eq!(lines[position], expected);
+    }
+    assert_eq!(map.final_location(3), Some(19..24));
+    assert_eq!(
+        lines[19..24],
+        ["line 20", "third a", "third b", "third c", "line 22"]
+    );
+
+    assert_eq!(map.added_line_positions(1), vec![2, 3]);
+    assert!(map.added_line_positions(2).is_empty());
+    assert_eq!(map.final_location(2), Some(11..13));
+    assert_eq!(lines[11..13], ["line 10", "line 13"]);
+}
+
+#[test]
+fn test_line_map_shifts_hunk_applied_below_a_later_one() {
+    // The second hunk's context only exists above the first hunk, so the lines
+    // it adds move the first hunk's changes down.
+    let original = vec!["alpha", "beta", "gamma", "x", "y", "z"];
+    let diff = indoc! {"
+        --- a/f.txt
+        +++ b/f.txt
+        @@ -4,3 +4,4 @@
+         x
+        +inserted after x
+         y
+         z
+        @@ -1,3 +1,5 @@
+         alpha
+        +one
+        +two
+         beta
+         gamma
+    "};
+    let patch = parse_single_patch(diff).unwrap();
+    let options = ApplyOptions::new().with_build_line_map(true);
+
+    let result = apply_patch_to_lines(&patch, Some(&original), &options);
+    assert!(result.report.all_applied_cleanly());
+    let lines: Vec<&str> = result.new_content.lines().collect();
+    let map = result.report.line_map.unwrap();
+
+    assert_eq!(map.added_line_positions(1), vec![6]);
+    assert_eq!(lines[6], "inserted after x");
+    assert_eq!(map.final_location(1), Some(5..9));
+    assert_eq!(map.added_line_positions(2), vec![1, 2]);
+
+    // Without the option, no map is built.
+    let result = apply_patch_to_lines(&patch, Some(&original), &ApplyOptions::new());
+    assert!(result.report.line_map.is_none());
+}
+
+#[test]
+fn test_context_lines_without_leading_space_parse_like_git_output() {
+    // What `git diff` produces for the change.
+    let git_diff = "--- a/src/main.rs\n+++ b/src/main.rs\n@@ -1,7 +1,7 @@\n use std::env;\n \n fn main() {\n-    run(1);\n+    run(2);\n }\n \n fn run(_: u32) {}\n";
+    // The same diff as an LLM typically writes it: context lines lost their
+    // leading space and blank context lines are empty.
+    let mangled = "--- a/src/main.rs\n+++ b/src/main.rs\n@@ -1,7 +1,7 @@\nuse std::env;\n\nfn main() {\n-    run(1);\n+    run(2);\n}\n\nfn run(_: u32) {}\n";
+
+    let expected = parse_patches(git_diff).unwrap();
+    assert_eq!(parse_patches(mangled).unwrap(), expected);
+    let from_markdown = parse_diffs(&format!("```diff\n{}```\n", mangled)).unwrap();
+    assert_eq!(from_markdown[0].hunks, expected[0].hunks);
+
+    let original = "use std::env;\n\nfn main() {\n    run(1);\n}\n\nfn run(_: u32) {}\n";
+    let result = apply_patch_to_content(&from_markdown[0], Some(original), &ApplyOptions::exact());
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(
+        result.new_content,
+        "use std::env;\n\nfn main() {\n    run(2);\n}\n\nfn run(_: u32) {}\n"
+    );
+}
+
+#[test]
+fn test_strict_parsing_ends_hunk_at_unprefixed_line() {
+    let diff = "--- a/f.txt\n+++ b/f.txt\n@@ -1,4 +1,4 @@\n a\n\n-b\n+c\nd\ntrailing commentary\n";
+
+    let strict = DiffParseOptions {
+        lenient_context: false,
+        ..Default::default()
+    };
+    let patches = parse_patches_with(diff, &strict).unwrap();
+    // Blank lines are still context; the first unprefixed text ends the hunk.
+    assert_eq!(patches[0].hunks[0].lines, vec![" a", " ", "-b", "+c"]);
+
+    let lenient = parse_patches_with(diff, &DiffParseOptions::default()).unwrap();
+    assert_eq!(lenient, parse_patches(diff).unwrap());
+    assert_eq!(
+        lenient[0].hunks[0].lines,
+        vec![" a", " ", "-b", "+c", " d", " trailing commentary"]
+    );
+}
+
+#[test]
+fn test_new_line_hint_resolves_ambiguity_when_old_hint_is_wrong() {
+    let lines: Vec<String> = (1..=20)
+        .map(|i| match i {
+            3 | 15 => "return None".to_string(),
+            _ => format!("line {}", i),
+        })
+        .collect();
+    let content = lines.join("\n") + "\n";
+
+    // The old line number sits exactly between the two candidates, but the
+    // new line number points at the second one.
+    let diff = "--- a/f.py\n+++ b/f.py\n@@ -9,1 +15,1 @@\n-return None\n+return 0\n";
+    let hunk = parse_patches(diff).unwrap().remove(0).hunks.remove(0);
+    let options = ApplyOptions::exact();
+    let (location, _) = find_hunk_location(&hunk, &content, &options).unwrap();
+    assert_eq!(location.start_index, 14);
+
+    // Without the new line number, the candidates are a true tie.
+    let mut without_new_hint = hunk.clone();
+    without_new_hint.new_start_line = None;
+    assert!(matches!(
+        find_hunk_location(&without_new_hint, &content, &options),
+        Err(HunkApplyError::AmbiguousExactMatch(candidates)) if candidates == vec![2, 14]
+    ));
+}
+
+#[test]
+fn test_absolute_patch_paths_are_rejected_unless_allowed() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("hosts"), "old\n").unwrap();
+    let cases = [
+        ("a//hosts", "/hosts"),
+        ("/hosts", "/hosts"),
+        ("C:\\hosts", "C:/hosts"),
+        ("//server/share/hosts", "//server/share/hosts"),
+    ];
+    for (written, parsed) in cases {
+        let diff = format!(
+            "--- {}\n+++ {}\n@@ -1 +1 @@\n-old\n+new\n",
+            written, written
+        );
+        let patch = parse_single_patch(&diff).unwrap();
+        // Parsing keeps the path as written, so the caller can inspect it.
+        assert_eq!(patch.file_path, PathBuf::from(parsed));
+
+        let result = apply_patch_to_file(&patch, dir.path(), ApplyOptions::new());
+        match result {
+            Err(PatchError::AbsolutePathRejected { path }) => assert_eq!(path, patch.file_path),
+            other => panic!(
+                "{}: expected AbsolutePathRejected, got {:?}",
+                written, other
+            ),
+        }
+        assert_eq!(
+            fs::read_to_string(dir.path().join("hosts")).unwrap(),
+            "old\n"
+        );
+
+        let options = ApplyOptions::new()
+            .with_allow_absolute_paths(true)
+            .with_dry_run(true);
+        let result = apply_patch_to_file(&patch, dir.path(), options).unwrap();
+        assert!(result.report.all_applied_cleanly(), "{}", written);
+    }
+
+    let patch = parse_single_patch("--- /hosts\n+++ /hosts\n@@ -1 +1 @@\n-old\n+new\n").unwrap();
+    let options = ApplyOptions::new().with_allow_absolute_paths(true);
+    apply_patch_to_file(&patch, dir.path(), options).unwrap();
+    assert_eq!(
+        fs::read_to_string(dir.path().join("hosts")).unwrap(),
+        "new\n"
+    );
+}
+
+#[test]
+fn test_patches_to_string_round_trips_through_parse_patches() {
+    let plain = indoc! {"
+        --- a/src/lib.rs
+        +++ b/src/lib.rs
+        @@ -1,2 +1,2 @@ mod a;
+         mod a;
+        -mod b;
+        +mod c;
+        \\ No newline at end of file
+        --- /dev/null
+        +++ b/src/c.rs
+        @@ -0,0 +1,1 @@
+        +pub fn c() {}
+        --- a/src/b.rs
+        +++ /dev/null
+        @@ -1,1 +0,0 @@
+        -pub fn b() {}
+    "};
+    let git = indoc! {"
+        diff --git a/README.md b/docs/README.md
+        similarity index 90%
+        rename from README.md
+        rename to docs/README.md
+        index 1234567..89abcde 100644
+        --- a/README.md
+        +++ b/docs/README.md
+        @@ -1 +1 @@
+        -# Old
+        +# New
+        diff --git a/new.txt b/new.txt
+        new file mode 100644
+        index 0000000..89abcde
+        --- /dev/null
+        +++ b/new.txt
+        @@ -0,0 +1 @@
+        +hello
+        diff --git a/run.sh b/run.sh
+        old mode 100644
+        new mode 100755
+    "};
+    let original = DiffStyle {
+        header: HeaderStyle::Original,
+        ..Default::default()
+    };
+    let git_original = DiffStyle {
+        header: HeaderStyle::Original,
+        ..DiffStyle::git()
+    };
+    for (diff, style) in [(plain, original), (git, git_original)] {
+        let patches = parse_patches(diff).unwrap();
+        let text = patches_to_string(&patches, style);
+        assert_eq!(text, diff);
+        assert_eq!(parse_patches(&text).unwrap(), patches);
+    }
+
+    // With recomputed headers and added Git headers, the changes are the same.
+    let patches = parse_patches(plain).unwrap();
+    let reparsed = parse_patches(&patches_to_string(&patches, DiffStyle::git())).unwrap();
+    assert_eq!(reparsed.len(), patches.len());
+    for (reparsed, patch) in reparsed.iter().zip(&patches) {
+        assert_eq!(reparsed.file_path, patch.file_path);
+        assert_eq!(reparsed.deletes_file, patch.deletes_file);
+        assert_eq!(reparsed.ends_with_newline, patch.ends_with_newline);
+        let lines = |p: &Patch| p.hunks.iter().map(|h| h.lines.clone()).collect::<Vec<_>>();
+        assert_eq!(lines(reparsed), lines(patch));
+    }
+}
+
+#[test]
+fn test_git_style_patches_pass_git_apply_check() {
+    let has_git = std::process::Command::new("git")
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success());
+    if !has_git {
+        eprintln!("git is not available; skipping.");
+        return;
+    }
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("edit.txt"), "one\ntwo\nthree\n").unwrap();
+    fs::write(dir.path().join("gone.txt"), "bye\n").unwrap();
+    fs::write(dir.path().join("tail.txt"), "keep\nend\n").unwrap();
+    let patches = vec![
+        Patch::from_texts("edit.txt", "one\ntwo\nthree\n", "one\n2\nthree\n", 3).unwrap(),
+        Patch::from_texts("new.txt", "", "hello\n", 3).unwrap(),
+        Patch::from_texts("gone.txt", "bye\n", "", 3).unwrap(),
+        Patch::from_texts("tail.txt", "keep\nend\n", "keep\nend", 3).unwrap(),
+    ];
+    let diff_path = dir.path().join("changes.diff");
+    let file = fs::File::create(&diff_path).unwrap();
+    write_patches(&patches, file, DiffStyle::git()).unwrap();
+
+    let output = std::process::Command::new("git")
+        .args(["apply", "--check", "-v"])
+        .arg(&diff_path)
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "git apply --check failed:\n{}\n{}",
+        String::from_utf8_lossy(&output.stderr),
+        fs::read_to_string(&diff_path).unwrap()
+    );
+}
+
+#[test]
+fn test_apply_patch_to_content_matches_owned_lines_path() {
+    // `apply_patch_to_content` keeps untouched lines borrowed from the input;
+    // `apply_patch_in_place` works on owned lines. Both must agree exactly.
+    let content =
+        "fn a() {\n    one();\n}\n\nfn b() {\n    two();\n}\n\nfn c() {\n    three();\n}\n";
+    let cases = [
+        (
+            "@@ -1,3 +1,3 @@\n fn a() {\n-    one();\n+    uno();\n }\n@@ -9,3 +9,4 @@\n fn c() {\n     three();\n+    four();\n }\n",
+            ApplyOptions::exact(),
+        ),
+        // Drifted indentation needs the robust reconstruction path.
+        (
+            "@@ -5,4 +5,4 @@\n fn b() {\n  two();\n-}\n+} // end\n \n",
+            ApplyOptions::new(),
+        ),
+        (
+            "@@ -1,2 +1,2 @@\n-fn missing() {\n+fn found() {\n     nothing();\n",
+            ApplyOptions::new().with_on_failure(FailureAction::InsertConflictMarkers),
+        ),
+        (
+            "@@ -1,11 +1,11 @@\n fn a() {\n-    one();\n+    1();\n }\n \n fn x() {\n     gone();\n     away();\n }\n \n fn c() {\n-    three();\n+    3();\n }\n",
+            ApplyOptions::new()
+                .with_fuzz_factor(0.9)
+                .with_split_hunks(true)
+                .with_build_line_map(true),
+        ),
+    ];
+    for (hunks, options) in cases {
+        let diff = format!("--- a/f.rs\n+++ b/f.rs\n{}", hunks);
+        let patch = parse_single_patch(&diff).unwrap();
+
+        let borrowed = apply_patch_to_content(&patch, Some(content), &options);
+        let mut lines: Vec<String> = content.lines().map(String::from).collect();
+        let owned = apply_patch_in_place(&patch, &mut lines, &options);
+
+        assert_eq!(borrowed.report, owned, "{}", hunks);
+        assert_eq!(borrowed.new_content, lines.join("\n") + "\n", "{}", hunks);
+    }
+}
+
+#[test]
+fn test_apply_patch_to_path_ignores_the_patch_path() {
+    let dir = tempdir().unwrap();
+    let vendor = dir.path().join("vendor");
+    fs::create_dir(&vendor).unwrap();
+    let target = vendor.join("foo.py");
+    fs::write(&target, "def f():\n    return 1\n").unwrap();
+
+    // The patch names `lib/foo.py`, which does not exist anywhere.
+    let patch = parse_single_patch(indoc! {"
+        --- a/lib/foo.py
+        +++ b/lib/foo.py
+        @@ -1,2 +1,2 @@
+         def f():
+        -    return 1
+        +    return 2
+    "})
+    .unwrap();
+
+    // A dry run returns a diff and leaves the file alone.
+    let result = apply_patch_to_path(&patch, &target, ApplyOptions::dry_run()).unwrap();
+    assert!(result.report.all_applied_cleanly());
+    assert!(!result.written);
+    let diff = result.diff.unwrap();
+    assert!(diff.contains("-    return 1\n+    return 2\n"), "{}", diff);
+    assert_eq!(
+        fs::read_to_string(&target).unwrap(),
+        "def f():\n    return 1\n"
+    );
+
+    let result = apply_patch_to_path(&patch, &target, ApplyOptions::new()).unwrap();
+    assert!(result.report.all_applied_cleanly());
+    assert!(result.written);
+    assert_eq!(
+        fs::read_to_string(&target).unwrap(),
+        "def f():\n    return 2\n"
+    );
+    assert!(!dir.path().join("lib").exists());
+
+    // A second run no longer matches, which the strict variant reports as an error.
+    let strict = try_apply_patch_to_path(&patch, &target, ApplyOptions::exact());
+    assert!(matches!(strict, Err(StrictApplyError::PartialApply { .. })));
+}
+
+#[test]
+fn test_apply_patch_to_path_creates_missing_file() {
+    let dir = tempdir().unwrap();
+    let target = dir.path().join("new/nested/hello.txt");
+    let patch = parse_single_patch(indoc! {"
+        --- /dev/null
+        +++ b/somewhere/else.txt
+        @@ -0,0 +1,2 @@
+        +hello
+        +world
+    "})
+    .unwrap();
+
+    let result = apply_patch_to_path(&patch, &target, ApplyOptions::new()).unwrap();
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(fs::read_to_string(&target).unwrap(), "hello\nworld\n");
+    assert!(!dir.path().join("somewhere").exists());
+
+    // A patch that is not a creation needs the file to exist.
+    let modify = parse_single_patch("--- a/x.txt\n+++ b/x.txt\n@@ -1 +1 @@\n-a\n+b\n").unwrap();
+    let missing = dir.path().join("missing.txt");
+    let err = apply_patch_to_path(&modify, &missing, ApplyOptions::new()).unwrap_err();
+    assert!(matches!(err, PatchError::TargetNotFound(_)), "{:?}", err);
+}
+
+#[test]
+fn test_apply_patch_to_path_rejects_directories() {
+    let dir = tempdir().unwrap();
+    let patch = parse_single_patch("--- a/x.txt\n+++ b/x.txt\n@@ -1 +1 @@\n-a\n+b\n").unwrap();
+
+    let err = apply_patch_to_path(&patch, dir.path(), ApplyOptions::new()).unwrap_err();
+    assert!(
+        matches!(&err, PatchError::TargetIsDirectory { path } if path == dir.path()),
+        "{:?}",
+        err
+    );
+    let strict = try_apply_patch_to_path(&patch, dir.path(), ApplyOptions::new());
+    assert!(matches!(
+        strict,
+        Err(StrictApplyError::Patch(
+            PatchError::TargetIsDirectory { .. }
+        ))
+    ));
+}
+
+#[test]
+fn test_file_replacement_blocks_update_only_labeled_files() {
+    let dir = tempdir().unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(
+        dir.path().join("src/config.rs"),
+        "pub const RETRIES: u32 = 3;\npub const VERBOSE: bool = false;\n",
+    )
+    .unwrap();
+    fs::write(dir.path().join("usage.rs"), "untouched\n").unwrap();
+
+    let doc = indoc! {r#"
+        Here is the updated config:
+
+        ### src/config.rs
+        ```rust
+        pub const RETRIES: u32 = 5;
+        pub const VERBOSE: bool = false;
+        ```
+
+        You can use it like this:
+
+        ```rust
+        let retries = config::RETRIES;
+        ```
+    "#};
+    let replacements = parse_file_blocks(doc);
+    assert_eq!(replacements.len(), 1);
+    assert_eq!(replacements[0].path, PathBuf::from("src/config.rs"));
+    assert_eq!(replacements[0].source_block.as_ref().unwrap().start_line, 4);
+
+    // A dry run reports a diff of just the changed line.
+    let batch = apply_file_replacements_to_dir(&replacements, dir.path(), ApplyOptions::dry_run());
+    assert!(batch.all_succeeded());
+    let diff = batch.results[0].1.as_ref().unwrap().diff.clone().unwrap();
+    assert!(diff.contains("-pub const RETRIES: u32 = 3;\n+pub const RETRIES: u32 = 5;\n"));
+    assert!(!diff.contains("-pub const VERBOSE"));
+
+    let batch = apply_file_replacements_to_dir(&replacements, dir.path(), ApplyOptions::new());
+    assert!(batch.all_succeeded());
+    assert_eq!(batch.origins[0].as_ref().unwrap().start_line, 4);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/config.rs")).unwrap(),
+        "pub const RETRIES: u32 = 5;\npub const VERBOSE: bool = false;\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("usage.rs")).unwrap(),
+        "untouched\n"
+    );
+}
+
+#[test]
+fn test_parse_file_blocks_label_forms() {
+    let doc = indoc! {r#"
+        **`path/to/bold.py`**
+        ```python
+        x = 1
+        ```
+
+        ```js
+        // filename: web/app.js
+        console.log("hi");
+        ```
+
+        ```toml title="Cargo.toml"
+        [package]
+        ```
+
+        **File:** `docs/notes.md`
+        ```
+        # Notes
+        ```
+
+        ## Step 1.
+        ```sh
+        cargo build
+        ```
+
+        ### Examples
+        ```diff
+        --- a/x.txt
+        +++ b/x.txt
+        @@ -1 +1 @@
+        -a
+        +b
+        ```
+
+        ### empty.txt
+        ```
+        ```
+    "#};
+    let replacements = parse_file_blocks(doc);
+    let found: Vec<(&str, &str)> = replacements
+        .iter()
+        .map(|r| (r.path.to_str().unwrap(), r.new_content.as_str()))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("path/to/bold.py", "x = 1\n"),
+            ("web/app.js", "console.log(\"hi\");\n"),
+            ("Cargo.toml", "[package]\n"),
+            ("docs/notes.md", "# Notes\n"),
+        ]
+    );
+}
+
+#[test]
+fn test_file_replacement_creates_missing_file() {
+    let dir = tempdir().unwrap();
+    let replacements = vec![FileReplacement {
+        path: PathBuf::from("new/module.rs"),
+        new_content: "pub fn new() {}\n".to_string(),
+        source_block: None,
+    }];
+
+    let batch = apply_file_replacements_to_dir(&replacements, dir.path(), ApplyOptions::new());
+    assert!(batch.all_succeeded());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("new/module.rs")).unwrap(),
+        "pub fn new() {}\n"
+    );
+
+    // Paths outside the target directory are rejected like any patch path.
+    let escaping = vec![FileReplacement {
+        path: PathBuf::from("../outside.rs"),
+        ..replacements[0].clone()
+    }];
+    let batch = apply_file_replacements_to_dir(&escaping, dir.path(), ApplyOptions::new());
+    assert!(matches!(
+        batch.results[0].1,
+        Err(PatchError::PathTraversal(_))
+    ));
+}
+
+#[test]
+fn test_strict_parsing_reports_malformed_input_with_line_numbers() {
+    let header = "--- a/f.txt\n+++ b/f.txt\n";
+    // Each case builds its expected error from the offset of the diff in the input.
+    type Expected = fn(usize) -> ParseError;
+    let cases: [(&str, Expected); 4] = [
+        ("@@ -x,y +1,2 @@\n-a\n+b\n", |offset| {
+            ParseError::MalformedHunkHeader {
+                line: offset + 3,
+                header: "@@ -x,y +1,2 @@".to_string(),
+            }
+        }),
+        ("@@ -1,3 +1,3 @@\n a\n-b\n+B\nc\n", |offset| {
+            ParseError::UnexpectedLinePrefix {
+                line: offset + 7,
+                content: "c".to_string(),
+            }
+        }),
+        (
+            "@@ -1,3 +1,3 @@\n a\n-b\n+B\n@@ -10,2 +10,2 @@\n-x\n+X\n y\n",
+            |offset| ParseError::TruncatedHunk {
+                line: offset + 3,
+                header: "@@ -1,3 +1,3 @@".to_string(),
+            },
+        ),
+        (
+            "--- a/g.txt\n+++ b/g.txt\n@@ -1 +1 @@\n-a\n+b\n",
+            |offset| ParseError::EmptyPatchSection { line: offset + 1 },
+        ),
+    ];
+    for (body, expected) in cases {
+        let diff = format!("{}{}", header, body);
+        assert_eq!(parse_patches_strict(&diff), Err(expected(0)), "{}", diff);
+
+        // Inside a Markdown block, line numbers still count from the top.
+        let markdown = format!("Here is the fix.\n\n```diff\n{}```\n", diff);
+        assert_eq!(
+            parse_patches_strict(&markdown),
+            Err(expected(3)),
+            "{}",
+            markdown
+        );
+
+        // The lenient parser still accepts the same input.
+        assert!(parse_patches(&diff).is_ok(), "{}", diff);
+    }
+
+    // Well-formed input parses the same either way.
+    let good = format!(
+        "{}@@ -1,2 +1,2 @@ fn main\n a\n-b\n+B\n\\ No newline at end of file\n",
+        header
+    );
+    assert_eq!(
+        parse_patches_strict(&good).unwrap(),
+        parse_patches(&good).unwrap()
+    );
+}
+
+#[test]
+fn test_per_line_scoring_is_consistent_across_hunk_sizes() {
+    // A small hunk for a function that no longer exists. Its context only shares
+    // its shape with another function, which the ratio score accepts anyway.
+    let small = "--- a/f.rs\n+++ b/f.rs\n@@ -1,3 +1,3 @@\n fn beta() {\n-    let y = 1;\n+    let y = 2;\n }\n";
+    let small = parse_single_patch(small).unwrap();
+    let content = "fn alpha() {\n    let x = 1;\n}\n";
+
+    let ratio = apply_patch_to_content(&small, Some(content), &ApplyOptions::new());
+    assert_eq!(ratio.new_content, "fn alpha() {\n    let y = 2;\n}\n");
+
+    let per_line = ApplyOptions::new().with_scoring(ScoringMode::PerLine);
+    let result = apply_patch_to_content(&small, Some(content), &per_line);
+    assert_eq!(result.new_content, content);
+    assert!(matches!(
+        result.report.hunk_results[0],
+        HunkApplyStatus::Failed(HunkApplyError::FuzzyMatchBelowThreshold { .. })
+    ));
+
+    // A large hunk whose context has one line that is entirely different in the
+    // file and a few lines with small edits still clears a strict threshold.
+    let mut original: Vec<String> = (0..40)
+        .map(|i| format!("    let value_{i} = compute({i}, \"item {i}\");"))
+        .collect();
+    let mut diff = String::from("--- a/g.rs\n+++ b/g.rs\n@@ -1,40 +1,40 @@\n");
+    for (i, line) in original.iter().enumerate() {
+        if i == 20 {
+            diff.push_str(&format!("-{line}\n+    let value_20 = 0;\n"));
+        } else {
+            diff.push_str(&format!(" {line}\n"));
+        }
+    }
+    original[5] = "    return Err(Error::Unsupported);".to_string();
+    original[12] = original[12].replace("compute", "compute_v2");
+    original[33] = original[33].replace("item", "entry");
+    let content = original.join("\n") + "\n";
+    let large = parse_single_patch(&diff).unwrap();
+
+    let options = ApplyOptions::new()
+        .with_scoring(ScoringMode::PerLine)
+        .with_fuzz_factor(0.9);
+    let result = apply_patch_to_content(&large, Some(&content), &options);
+    assert!(result.report.all_applied_cleanly());
+    assert!(result.new_content.contains("    let value_20 = 0;\n"));
+    match &result.report.hunk_results[0] {
+        HunkApplyStatus::Applied {
+            match_type: MatchType::Fuzzy { score, mode },
+            ..
+        } => {
+            assert_eq!(*mode, ScoringMode::PerLine);
+            // Only the replaced line fails the per-line bar.
+            assert!((score - 39.0 / 40.0).abs() < 1e-9, "score {score}");
+        }
+        other => panic!("unexpected status: {other:?}"),
+    }
+
+    // The default mode still records itself on fuzzy matches.
+    match &ratio.report.hunk_results[0] {
+        HunkApplyStatus::Applied {
+            match_type: MatchType::Fuzzy { mode, .. },
+            ..
+        } => assert_eq!(*mode, ScoringMode::Ratio),
+        other => panic!("unexpected status: {other:?}"),
+    }
+}
+
+#[test]
+fn test_batch_result_counts_and_summary() {
+    use mpatch::{ApplyResult, BatchResult, PatchResult};
+
+    let patch_result = |hunk_results: Vec<HunkApplyStatus>| PatchResult {
+        report: ApplyResult {
+            hunk_results,
+            stats: None,
+            line_map: None,
+        },
+        diff: None,
+        written: true,
+        reject_file: None,
+        removed: false,
+        strip_level: 1,
+        hunk_previews: vec![],
+        backup_path: None,
+        options_used: ApplyOptions::new(),
+    };
+    let applied = || HunkApplyStatus::Applied {
+        location: HunkLocation {
+            start_index: 0,
+            length: 1,
+        },
+        match_type: MatchType::Exact,
+        replaced_lines: vec![],
+        interference: None,
+        byte_range: None,
+    };
+    let failed = || HunkApplyStatus::Failed(HunkApplyError::ContextNotFound);
+
+    let batch = BatchResult {
+        results: vec![
+            (
+                PathBuf::from("clean.rs"),
+                Ok(patch_result(vec![
+                    applied(),
+                    HunkApplyStatus::SkippedNoChanges,
+                ])),
+            ),
+            (
+                PathBuf::from("partial.rs"),
+                Ok(patch_result(vec![failed(), applied(), failed(), failed()])),
+            ),
+            (
+                PathBuf::from("missing.rs"),
+                Err(PatchError::TargetNotFound(PathBuf::from("missing.rs"))),
+            ),
+            (
+                PathBuf::from("also_clean.rs"),
+                Ok(patch_result(vec![applied()])),
+            ),
+            (
+                PathBuf::from("later.rs"),
+                Err(PatchError::NotAttempted {
+                    path: PathBuf::from("later.rs"),
+                }),
+            ),
+        ],
+        origins: vec![None; 5],
+    };
+
+    assert!(!batch.all_succeeded());
+    assert!(!batch.all_applied_cleanly());
+    assert_eq!(
+        batch.clean_files(),
+        vec![&PathBuf::from("clean.rs"), &PathBuf::from("also_clean.rs")]
+    );
+    let partial = batch.partial_failures();
+    assert_eq!(partial.len(), 1);
+    assert_eq!(partial[0].0, &PathBuf::from("partial.rs"));
+    let indices: Vec<usize> = partial[0].1.iter().map(|f| f.hunk_index).collect();
+    assert_eq!(indices, vec![1, 3, 4]);
+
+    // The hunks of patches with a hard error were never attempted.
+    assert_eq!(batch.total_hunks(), 7);
+    assert_eq!(batch.applied_hunks(), 4);
+    assert_eq!(batch.failed_hunks(), 3);
+
+    assert_eq!(
+        batch.to_string(),
+        format!(
+            "5 files: 2 clean, 1 partial (3 hunks failed), 1 hard error, 1 skipped\n  partial: partial.rs (hunks 1, 3, 4 failed)\n  error: missing.rs: {}",
+            PatchError::TargetNotFound(PathBuf::from("missing.rs"))
+        )
+    );
+
+    // Without hard errors, `all_succeeded` holds even though hunks failed.
+    let soft = BatchResult {
+        results: batch
+            .results
+            .into_iter()
+            .filter(|(_, res)| res.is_ok())
+            .collect(),
+        origins: vec![None; 3],
+    };
+    assert!(soft.all_succeeded());
+    assert!(!soft.all_applied_cleanly());
+
+    let clean = BatchResult {
+        results: vec![(PathBuf::from("a.rs"), Ok(patch_result(vec![applied()])))],
+        origins: vec![None],
+    };
+    assert!(clean.all_applied_cleanly());
+    assert!(clean.partial_failures().is_empty());
+    assert_eq!(
+        clean.to_string(),
+        "1 file: 1 clean, 0 partial (0 hunks failed), 0 hard errors"
+    );
+}
+
+#[test]
+fn test_parse_diffs_with_context_infers_paths_for_headerless_blocks() {
+    let response = indoc! {r#"
+        First, update the entry point:
+
+        ```diff
+        --- a/src/main.rs
+        +++ b/src/main.rs
+        @@ -1,3 +1,3 @@
+         fn main() {
+        -    run(1);
+        +    run(2);
+         }
+        ```
+
+        In `src/utils.rs`, change:
+
+        ```diff
+        @@ -1,2 +1,2 @@
+         pub fn helper() -> u32 {
+        -    1
+        +    2
+        ```
+
+        Finally, add a new file called config.toml:
+        ```
+        @@ -0,0 +1,2 @@
+        +[settings]
+        +retries = 3
+        ```
+
+        And this block names no file:
+        ```diff
+        @@ -1 +1 @@
+        -x
+        +y
+        ```
+    "#};
+
+    // Without inference, the header-less blocks are dropped.
+    let plain = parse_diffs(response).unwrap();
+    assert_eq!(plain.len(), 1);
+
+    let patches = parse_diffs_with_context(response, &PathInferenceOptions::default()).unwrap();
+    let summary: Vec<(&str, bool)> = patches
+        .iter()
+        .map(|p| (p.file_path.to_str().unwrap(), p.path_inferred))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("src/main.rs", false),
+            ("src/utils.rs", true),
+            ("config.toml", true),
+        ]
+    );
+    assert_eq!(patches[0], plain[0]);
+    assert_eq!(
+        patches[1].hunks[0].lines,
+        vec![" pub fn helper() -> u32 {", "-    1", "+    2"]
+    );
+    assert_eq!(patches[1].hunks[0].old_start_line, Some(1));
+    assert!(patches[2].is_creation());
+    // The origin points at the fenced block itself.
+    let origin = patches[1].source_block.as_ref().unwrap();
+    assert_eq!((origin.start_line, origin.end_line), (15, 20));
+
+    // Both inferred patches apply.
+    let dir = tempdir().unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(
+        dir.path().join("src/main.rs"),
+        "fn main() {\n    run(1);\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("src/utils.rs"),
+        "pub fn helper() -> u32 {\n    1\n}\n",
+    )
+    .unwrap();
+    let batch = apply_patches_to_dir(&patches, dir.path(), ApplyOptions::new());
+    assert!(batch.all_applied_cleanly(), "{}", batch);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/utils.rs")).unwrap(),
+        "pub fn helper() -> u32 {\n    2\n}\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("config.toml")).unwrap(),
+        "[settings]\nretries = 3\n"
+    );
+
+    // The path must be within the lookback window.
+    let options = PathInferenceOptions {
+        lookback_lines: 1,
+        ..Default::default()
+    };
+    let patches = parse_diffs_with_context(response, &options).unwrap();
+    let paths: Vec<&str> = patches
+        .iter()
+        .map(|p| p.file_path.to_str().unwrap())
+        .collect();
+    assert_eq!(paths, vec!["src/main.rs", "config.toml"]);
+}
+
+#[test]
+fn test_fuzzy_errors_carry_candidate_lines() {
+    // Below threshold: the candidate is the rejected region of the target.
+    let target: Vec<&str> = (1..=30)
+        .map(|i| match i {
+            12 => "let value_12 = twelve();",
+            13 => "let value_13 = thirteen();",
+            _ => "filler",
+        })
+        .collect();
+    let hunk = parse_single_patch(indoc! {"
+        --- a/f.rs
+        +++ b/f.rs
+        @@ -11,4 +11,4 @@
+         filler
+         let value_12 = 12;
+        -let value_13 = 13;
+        +let value_13 = 130;
+         filler
+    "})
+    .unwrap()
+    .hunks
+    .remove(0);
+    let options = ApplyOptions::new().with_fuzz_factor(0.95);
+    let error = find_hunk_location_in_lines(&hunk, &target, &options).unwrap_err();
+    let HunkApplyError::FuzzyMatchBelowThreshold {
+        ref location,
+        ref candidate_lines,
+        ..
+    } = error
+    else {
+        panic!("expected a below-threshold failure, got {error:?}");
+    };
+    assert_eq!(
+        candidate_lines.as_slice(),
+        &target[location.start_index..location.start_index + location.length]
+    );
+    assert!(candidate_lines.contains(&"let value_12 = twelve();".to_string()));
+    assert!(
+        error
+            .to_string()
+            .contains("(candidate: \"filler\", \"let value_12 = twelve();\""),
+        "{error}"
+    );
+
+    // Ambiguous: every tied candidate is kept, in the order of the locations.
+    let content =
+        "section one\ncommon line\nDIFFERENT A\n\nsection two\ncommon line\nDIFFERENT B\n";
+    let target: Vec<&str> = content.lines().collect();
+    let hunk = parse_single_patch(indoc! {"
+        --- a/test.txt
+        +++ b/test.txt
+        @@ -3,3 +3,3 @@
+         section
+        -common line
+        +changed line
+         DIFFERENT
+    "})
+    .unwrap()
+    .hunks
+    .remove(0);
+    let options = ApplyOptions::new().with_fuzz_factor(0.5);
+    let error = find_hunk_location_in_lines(&hunk, &target, &options).unwrap_err();
+    let HunkApplyError::AmbiguousFuzzyMatch {
+        locations,
+        candidate_lines,
+    } = error
+    else {
+        panic!("expected an ambiguous fuzzy match, got {error:?}");
+    };
+    assert_eq!(locations.len(), 2);
+    assert_eq!(candidate_lines.len(), locations.len());
+    for (&(start, len), lines) in locations.iter().zip(&candidate_lines) {
+        assert_eq!(lines.as_slice(), &target[start..start + len]);
+    }
+}
+
+#[test]
+fn test_virtual_workspace_chains_creation_and_modification_in_memory() {
+    let dir = tempdir().unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(dir.path().join("src/lib.rs"), "mod old;\n").unwrap();
+    fs::write(dir.path().join("src/old.rs"), "// unused\n").unwrap();
+
+    let diff = indoc! {r#"
+        ```diff
+        --- /dev/null
+        +++ b/src/new.rs
+        @@ -0,0 +1,3 @@
+        +pub fn answer() -> u32 {
+        +    41
+        +}
+        --- a/src/lib.rs
+        +++ b/src/lib.rs
+        @@ -1 +1 @@
+        -mod old;
+        +mod new;
+        ```
+
+        Then bump the answer:
+
+        ```diff
+        --- a/src/new.rs
+        +++ b/src/new.rs
+        @@ -1,3 +1,3 @@
+         pub fn answer() -> u32 {
+        -    41
+        +    42
+         }
+        --- a/src/old.rs
+        +++ /dev/null
+        @@ -1 +0,0 @@
+        -// unused
+        --- a/../outside.txt
+        +++ b/../outside.txt
+        @@ -0,0 +1 @@
+        +escaped
+        ```
+    "#};
+    let patches = parse_diffs(diff).unwrap();
+    assert_eq!(patches.len(), 5);
+
+    let mut workspace = VirtualWorkspace::from_dir(dir.path());
+    let batch = workspace.apply_patches(&patches, &ApplyOptions::new());
+    for (path, result) in &batch.results[..4] {
+        let result = result.as_ref().unwrap();
+        assert!(result.report.all_applied_cleanly(), "{}", path.display());
+        assert!(!result.written);
+    }
+    assert!(matches!(
+        batch.results[4].1,
+        Err(PatchError::PathTraversal(_))
+    ));
+    assert_eq!(
+        workspace.get("src/new.rs"),
+        Some("pub fn answer() -> u32 {\n    42\n}\n")
+    );
+
+    let changes = workspace.into_changes();
+    assert_eq!(
+        changes,
+        vec![
+            (
+                PathBuf::from("src/lib.rs"),
+                FileChange::Modified {
+                    old: "mod old;\n".to_string(),
+                    new: "mod new;\n".to_string(),
+                }
+            ),
+            (
+                PathBuf::from("src/new.rs"),
+                FileChange::Created {
+                    content: "pub fn answer() -> u32 {\n    42\n}\n".to_string(),
+                }
+            ),
+            (
+                PathBuf::from("src/old.rs"),
+                FileChange::Deleted {
+                    old: "// unused\n".to_string(),
+                }
+            ),
+        ]
+    );
+
+    // Nothing reached the disk.
+    assert!(!dir.path().join("src/new.rs").exists());
+    assert!(dir.path().join("src/old.rs").exists());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/lib.rs")).unwrap(),
+        "mod old;\n"
+    );
+    assert!(!dir.path().parent().unwrap().join("outside.txt").exists());
+}
+
+#[test]
+fn test_context_diff_with_one_sided_hunks_reproduces_new_file() {
+    // Output of `diff -c old.txt new.txt` followed by `diff -c o2 n2`. When a
+    // hunk only removes or only adds lines, `diff -c` leaves the other
+    // section empty, and the last file has no trailing newline.
+    let diff = "\
+*** old.txt\tFri Oct 16 18:01:48 2026
+--- new.txt\tFri Oct 16 18:01:48 2026
+***************
+*** 1,5 ****
+  a
+- b
+  c
+  d
+  e
+--- 1,4 ----
+***************
+*** 12,14 ****
+--- 11,14 ----
+  l
+  m
+  n
++ new
+*** o2\tFri Oct 16 18:01:48 2026
+--- n2\tFri Oct 16 18:01:48 2026
+***************
+*** 1,2 ****
+--- 1,3 ----
+  x
+  y
++ z
+\\ No newline at end of file
+";
+    assert_eq!(detect_patch(diff), PatchFormat::Context);
+    let patches = parse_auto(diff).unwrap();
+    assert_eq!(patches, parse_context_diff(diff).unwrap());
+    assert_eq!(patches.len(), 2);
+    assert_eq!(
+        patches[0].hunks[0].lines,
+        vec![" a", "-b", " c", " d", " e"]
+    );
+    assert_eq!(patches[0].hunks[1].lines, vec![" l", " m", " n", "+new"]);
+    assert_eq!(patches[0].hunks[1].old_start_line, Some(12));
+    assert_eq!(patches[0].hunks[1].new_start_line, Some(11));
+
+    let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\nm\nn\n";
+    let result = apply_patch_to_content(&patches[0], Some(old), &ApplyOptions::exact());
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(
+        result.new_content,
+        "a\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\nm\nn\nnew\n"
+    );
+
+    let result = apply_patch_to_content(&patches[1], Some("x\ny\n"), &ApplyOptions::exact());
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(result.new_content, "x\ny\nz");
+}
+
+#[test]
+fn test_find_hunk_candidates_and_apply_at_chosen_occurrence() {
+    let hunk = Hunk {
+        lines: vec![
+            " fn reset(&mut self) {".to_string(),
+            "-    self.count = 0;".to_string(),
+            "+    self.count = 1;".to_string(),
+            " }".to_string(),
+        ],
+        old_start_line: None,
+        new_start_line: None,
+        raw_header: None,
+        old_count: None,
+        new_count: None,
+    };
+    let original: Vec<String> = [
+        "impl A {",
+        "fn reset(&mut self) {",
+        "    self.count = 0;",
+        "}",
+        "}",
+        "impl B {",
+        "fn reset(&mut self) {",
+        "    self.count = 0;",
+        "}",
+        "}",
+        "impl C {",
+        "fn reset(&mut self) {",
+        "    self.count = 0;",
+        "}",
+        "}",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    let options = ApplyOptions::exact();
+
+    // The normal search gives up...
+    assert!(matches!(
+        find_hunk_location_in_lines(&hunk, &original, &options),
+        Err(HunkApplyError::AmbiguousExactMatch(_))
+    ));
+
+    // ...but every occurrence can be listed.
+    let candidates = find_hunk_candidates(&hunk, &original, &options);
+    let starts: Vec<usize> = candidates.iter().map(|c| c.location.start_index).collect();
+    assert_eq!(starts, vec![1, 6, 11]);
+    assert!(candidates
+        .iter()
+        .all(|c| matches!(c.match_type, MatchType::Exact) && c.score == 1.0));
+
+    let mut lines = original.clone();
+    let status = apply_hunk_at(&hunk, &mut lines, candidates[1].location, &options);
+    assert!(matches!(
+        status,
+        HunkApplyStatus::Applied {
+            match_type: MatchType::Exact,
+            ..
+        }
+    ));
+    let changed: Vec<usize> = (0..lines.len())
+        .filter(|&i| lines[i] != original[i])
+        .collect();
+    assert_eq!(changed, vec![7]);
+    assert_eq!(lines[7], "    self.count = 1;");
+
+    // A fuzzy candidate is applied with the target's own context lines.
+    let target: Vec<String> = ["fn reset(&mut self) { // clear", "    self.count = 0;", "}"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    let options = ApplyOptions::new().with_fuzz_factor(0.5);
+    let candidates = find_hunk_candidates(&hunk, &target, &options);
+    assert!(matches!(candidates[0].match_type, MatchType::Fuzzy { .. }));
+    let mut lines = target.clone();
+    let status = apply_hunk_at(&hunk, &mut lines, candidates[0].location, &options);
+    assert!(matches!(
+        status,
+        HunkApplyStatus::Applied {
+            match_type: MatchType::Fuzzy { .. },
+            ..
+        }
+    ));
+    assert_eq!(
+        lines,
+        vec!["fn reset(&mut self) { // clear", "    self.count = 1;", "}"]
+    );
+
+    // A location past the end is refused.
+    let mut lines = target.clone();
+    let status = apply_hunk_at(
+        &hunk,
+        &mut lines,
+        HunkLocation {
+            start_index: 2,
+            length: 3,
+        },
+        &options,
+    );
+    assert!(matches!(
+        status,
+        HunkApplyStatus::Failed(HunkApplyError::LocationOverrideMismatch { found: None, .. })
+    ));
+    assert_eq!(lines, target);
+}
+
+#[test]
+fn test_truncated_hunk_detected_and_optionally_rejected() {
+    // The header declares 4 old and 5 new lines, but the hunk was cut short
+    // after its first addition.
+    let diff = indoc! {"
+        --- a/list.txt
+        +++ b/list.txt
+        @@ -1,4 +1,5 @@
+         one
+         two
+        +two and a half
+    "};
+    let patch = &parse_patches(diff).unwrap()[0];
+    let hunk = &patch.hunks[0];
+    assert_eq!(hunk.old_count, Some(4));
+    assert_eq!(hunk.new_count, Some(5));
+    assert!(hunk.is_truncated());
+
+    let original = "one\ntwo\nthree\nfour\n";
+
+    // By default the partial change is applied as before.
+    let result = apply_patch_to_content(patch, Some(original), &ApplyOptions::new());
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(
+        result.new_content,
+        "one\ntwo\ntwo and a half\nthree\nfour\n"
+    );
+
+    // With the option set, the hunk fails and the content is left alone.
+    let options = ApplyOptions::new().with_reject_truncated_hunks(true);
+    let result = apply_patch_to_content(patch, Some(original), &options);
+    assert!(matches!(
+        result.report.hunk_results[0],
+        HunkApplyStatus::Failed(HunkApplyError::TruncatedHunk {
+            declared: (4, 5),
+            actual: (2, 3),
+        })
+    ));
+    assert_eq!(result.new_content, original);
+
+    // A complete hunk is not flagged, and neither is one without counts.
+    let complete = indoc! {"
+        --- a/list.txt
+        +++ b/list.txt
+        @@ -1,2 +1,3 @@
+         one
+         two
+        +two and a half
+    "};
+    let patch = &parse_patches(complete).unwrap()[0];
+    assert!(!patch.hunks[0].is_truncated());
+    let result = apply_patch_to_content(patch, Some(original), &options);
+    assert!(result.report.all_applied_cleanly());
+
+    let bare = "--- a/list.txt\n+++ b/list.txt\n@@ -1 +1 @@\n-one\n+uno\n";
+    let hunk = &parse_patches(bare).unwrap()[0].hunks[0];
+    assert_eq!((hunk.old_count, hunk.new_count), (Some(1), Some(1)));
+    assert!(!hunk.is_truncated());
+}
+
+#[test]
+fn test_backup_keeps_original_content() {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("src/config.txt");
+    fs::create_dir_all(file_path.parent().unwrap()).unwrap();
+    fs::write(&file_path, "name = old\nport = 80\n").unwrap();
+    let patch = parse_single_patch(indoc! {"
+        ```diff
+        --- a/src/config.txt
+        +++ b/src/config.txt
+        @@ -1,2 +1,2 @@
+        -name = old
+        +name = new
+         port = 80
+        ```
+    "})
+    .unwrap();
+    let backup_path = dir.path().join("src/config.txt.orig");
+
+    // A dry run never writes a backup.
+    let options = ApplyOptions::new().with_backup(BackupMode::default());
+    let result =
+        apply_patch_to_file(&patch, dir.path(), options.clone().with_dry_run(true)).unwrap();
+    assert_eq!(result.backup_path, None);
+    assert!(!backup_path.exists());
+
+    let result = apply_patch_to_file(&patch, dir.path(), options.clone()).unwrap();
+    assert!(result.report.all_applied_cleanly());
+    let reported = result.backup_path.expect("a backup should be reported");
+    assert_eq!(
+        fs::canonicalize(&reported).unwrap(),
+        fs::canonicalize(&backup_path).unwrap()
+    );
+    assert_eq!(
+        fs::read_to_string(&backup_path).unwrap(),
+        "name = old\nport = 80\n"
+    );
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "name = new\nport = 80\n"
+    );
+
+    // An existing backup is kept unless overwriting is allowed.
+    let revert = patch.invert();
+    let result = apply_patch_to_file(&revert, dir.path(), options.clone()).unwrap();
+    assert_eq!(
+        fs::canonicalize(
+            result
+                .backup_path
+                .expect("the kept backup should be reported")
+        )
+        .unwrap(),
+        fs::canonicalize(&backup_path).unwrap()
+    );
+    assert_eq!(
+        fs::read_to_string(&backup_path).unwrap(),
+        "name = old\nport = 80\n"
+    );
+    fs::write(&backup_path, "stale\n").unwrap();
+    let result = apply_patch_to_file(
+        &patch,
+        dir.path(),
+        options.with_overwrite_existing_backup(true),
+    )
+    .unwrap();
+    assert!(result.backup_path.is_some());
+    assert_eq!(
+        fs::read_to_string(&backup_path).unwrap(),
+        "name = old\nport = 80\n"
+    );
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "name = new\nport = 80\n"
+    );
+
+    // The directory mode mirrors the relative path under the backup root.
+    let backup_root = tempdir().unwrap();
+    let options =
+        ApplyOptions::new().with_backup(BackupMode::Directory(backup_root.path().to_path_buf()));
+    let result = apply_patch_to_file(&revert, dir.path(), options).unwrap();
+    let mirrored = backup_root.path().join("src/config.txt");
+    assert_eq!(result.backup_path.as_deref(), Some(mirrored.as_path()));
+    assert_eq!(
+        fs::read_to_string(&mirrored).unwrap(),
+        "name = new\nport = 80\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_backup_refuses_to_write_through_a_symlink() {
+    let dir = tempdir().unwrap();
+    let outside = tempdir().unwrap();
+    let victim = outside.path().join("victim.txt");
+    fs::write(&victim, "untouched\n").unwrap();
+    fs::write(dir.path().join("f.txt"), "old\n").unwrap();
+    std::os::unix::fs::symlink(&victim, dir.path().join("f.txt.orig")).unwrap();
+    let patch = parse_single_patch("--- a/f.txt\n+++ b/f.txt\n@@ -1 +1 @@\n-old\n+new\n").unwrap();
+
+    for overwrite in [false, true] {
+        let options = ApplyOptions::new()
+            .with_backup(BackupMode::default())
+            .with_overwrite_existing_backup(overwrite);
+        let result = apply_patch_to_file(&patch, dir.path(), options);
+        assert!(matches!(result, Err(PatchError::Io { .. })), "{:?}", result);
+        assert_eq!(fs::read_to_string(&victim).unwrap(), "untouched\n");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("f.txt")).unwrap(),
+            "old\n"
+        );
+    }
+}
+
+#[test]
+fn test_backup_skipped_for_new_files_and_failures_surface_as_io() {
+    let dir = tempdir().unwrap();
+    let options = ApplyOptions::new().with_backup(BackupMode::Suffix(".bak".to_string()));
+
+    // Creating a file has nothing to back up.
+    let creation = parse_single_patch(indoc! {"
+        ```diff
+        --- /dev/null
+        +++ b/new.txt
+        @@ -0,0 +1 @@
+        +hello
+        ```
+    "})
+    .unwrap();
+    let result = apply_patch_to_file(&creation, dir.path(), options.clone()).unwrap();
+    assert!(result.written);
+    assert_eq!(result.backup_path, None);
+    assert!(!dir.path().join("new.txt.bak").exists());
+
+    // A backup root that cannot be created fails the patch before the target
+    // is touched.
+    let blocker = dir.path().join("blocker");
+    fs::write(&blocker, "not a directory").unwrap();
+    let patch = parse_single_patch(indoc! {"
+        ```diff
+        --- a/new.txt
+        +++ b/new.txt
+        @@ -1 +1 @@
+        -hello
+        +goodbye
+        ```
+    "})
+    .unwrap();
+    let options = ApplyOptions::new().with_backup(BackupMode::Directory(blocker.join("root")));
+    let err = apply_patch_to_file(&patch, dir.path(), options).unwrap_err();
+    assert!(matches!(err, PatchError::Io { .. }));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("new.txt")).unwrap(),
+        "hello\n"
+    );
+}
+
+#[test]
+fn test_anchor_heuristic_matches_full_scan_on_random_inputs() {
+    // A small xorshift generator keeps the inputs reproducible.
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut next = move |bound: usize| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % bound as u64) as usize
+    };
+    let common = [
+        "}",
+        "    },",
+        "    log::info!(\"tick\");",
+        "    return Ok(());",
+        "",
+    ];
+    let options = ApplyOptions::new().with_fuzz_factor(0.5);
+
+    for round in 0..24 {
+        // Common lines repeat everywhere; the rest come from a pool small
+        // enough that some of them repeat a few times.
+        let target: Vec<String> = (0..60 + next(60))
+            .map(|_| {
+                if next(10) < 4 {
+                    common[next(common.len())].to_string()
+                } else {
+                    let n = next(40);
+                    format!("    let value_{n} = compute({n});")
+                }
+            })
+            .collect();
+
+        let len = 4 + next(5);
+        let start = next(target.len() - len);
+        let mut lines: Vec<String> = target[start..start + len]
+            .iter()
+            .map(|line| format!(" {line}"))
+            .collect();
+        // Drift some context so that only the fuzzy search can place the hunk.
+        for _ in 0..1 + next(2) {
+            let i = next(len);
+            lines[i] = format!(" {} // edited", &lines[i][1..]);
+        }
+        lines.insert(len / 2, "+    inserted();".to_string());
+        let hunk = Hunk {
+            lines,
+            old_start_line: None,
+            new_start_line: None,
+            raw_header: None,
+            old_count: None,
+            new_count: None,
+        };
+
+        let anchored = find_hunk_location_in_lines(&hunk, &target, &options);
+        mpatch::test_hooks::set_force_full_scan(true);
+        let scanned = find_hunk_location_in_lines(&hunk, &target, &options);
+        mpatch::test_hooks::set_force_full_scan(false);
+        assert_eq!(
+            format!("{anchored:?}"),
+            format!("{scanned:?}"),
+            "round {round}: hunk {:?}",
+            hunk.lines
+        );
+    }
+}
+
+/// Output of `git diff` after editing both files of a two-file repository,
+/// with the blob hashes of the committed versions on its `index` lines.
+const GIT_DIFF_WITH_INDEX: &str = r#"diff --git a/Cargo.toml b/Cargo.toml
+index 4a4b577..96cbc84 100644
+--- a/Cargo.toml
++++ b/Cargo.toml
+@@ -1,3 +1,3 @@
+ [package]
+ name = "demo"
+-version = "0.1.0"
++version = "0.2.0"
+diff --git a/main.rs b/main.rs
+index 7527576..2e6431c 100644
+--- a/main.rs
++++ b/main.rs
+@@ -1,3 +1,3 @@
+ fn main() {
+-    println!("hello");
++    println!("hello, world");
+ }
+"#;
+
+#[test]
+fn test_verify_preimage_against_git_index_hashes() {
+    let patches = parse_patches(GIT_DIFF_WITH_INDEX).unwrap();
+    assert_eq!(patches[0].old_blob(), Some("4a4b577"));
+    assert_eq!(patches[0].new_blob(), Some("96cbc84"));
+    assert_eq!(patches[1].old_blob(), Some("7527576"));
+
+    // Cargo.toml is the committed version; main.rs has been edited since.
+    let setup = || {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("main.rs"),
+            "// edited\nfn main() {\n    println!(\"hello\");\n}\n",
+        )
+        .unwrap();
+        dir
+    };
+
+    // Without verification, or with warnings only, both files are patched.
+    for policy in [PreimagePolicy::Ignore, PreimagePolicy::WarnOnMismatch] {
+        let dir = setup();
+        let options = ApplyOptions::new().with_verify_preimage(policy);
+        let batch = apply_patches_to_dir(&patches, dir.path(), options);
+        assert!(batch.all_applied_cleanly(), "{policy:?}");
+    }
+
+    // Rejecting leaves the edited file untouched and names both hashes.
+    let dir = setup();
+    let options = ApplyOptions::new().with_verify_preimage(PreimagePolicy::Reject);
+    // A verified file is patched at the hunk's line numbers without a search.
+    let result = apply_patch_to_file(
+        &patches[0],
+        dir.path(),
+        options.clone().with_collect_stats(true),
+    )
+    .unwrap();
+    assert!(result.report.all_applied_cleanly());
+    let stats = result.report.stats.unwrap();
+    assert_eq!(
+        stats.hunks[0].strategy,
+        Some(SearchStrategy::LocationOverride)
+    );
+    assert!(fs::read_to_string(dir.path().join("Cargo.toml"))
+        .unwrap()
+        .contains("0.2.0"));
+
+    let err = apply_patch_to_file(&patches[1], dir.path(), options.clone()).unwrap_err();
+    match err {
+        PatchError::PreimageMismatch {
+            expected, actual, ..
+        } => {
+            assert_eq!(expected, "7527576");
+            assert_eq!(actual, "bce655ccd716e78cdf0b873b981c4fb51ccbe83e");
+        }
+        other => panic!("expected a pre-image mismatch, got {other:?}"),
+    }
+    assert!(fs::read_to_string(dir.path().join("main.rs"))
+        .unwrap()
+        .starts_with("// edited"));
+
+    // In reverse, the patched file is checked against the new blob hash.
+    let result = apply_patch_to_file(&patches[0], dir.path(), options.with_reverse(true)).unwrap();
+    assert!(result.report.all_applied_cleanly());
+    assert!(fs::read_to_string(dir.path().join("Cargo.toml"))
+        .unwrap()
+        .contains("0.1.0"));
+}
+
+/// A line buffer that only hands out owned lines and records every splice made
+/// into it, standing in for an editor's rope.
+struct RecordingBuffer {
+    lines: Vec<String>,
+    splices: Vec<(std::ops::Range<usize>, usize)>,
+}
+
+impl LineSource for RecordingBuffer {
+    fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    fn line(&self, index: usize) -> std::borrow::Cow<'_, str> {
+        std::borrow::Cow::Owned(self.lines[index].clone())
+    }
+}
+
+impl LineSink for RecordingBuffer {
+    fn replace_lines(&mut self, range: std::ops::Range<usize>, new_lines: Vec<String>) {
+        self.splices.push((range.clone(), new_lines.len()));
+        self.lines.splice(range, new_lines);
+    }
+}
+
+#[test]
+fn test_apply_patch_to_source_splices_once_per_applied_hunk() {
+    let original: Vec<String> = (1..=30).map(|i| format!("line {}", i)).collect();
+    let diff = indoc! {r#"
+        ```diff
+        --- a/file.txt
+        +++ b/file.txt
+        @@ -2,3 +2,4 @@
+         line 2
+         line 3
+        +inserted after 3
+         line 4
+        @@ -10,3 +11,2 @@
+         line 10
+        -line 11
+         line 12
+        @@ -15,3 +15,3 @@
+         line 15
+        -no such line
+        +never applied
+         line 17
+        @@ -20,3 +20,3 @@
+         line 20
+        -line 21
+        +line twenty-one
+         line 22
+        ```
+    "#};
+    let patch = parse_single_patch(diff).unwrap();
+    let options = ApplyOptions::exact();
+
+    let expected = apply_patch_to_lines(&patch, Some(&original), &options);
+    let mut buffer = RecordingBuffer {
+        lines: original.clone(),
+        splices: Vec::new(),
+    };
+    let report = apply_patch_to_source(&patch, &mut buffer, &options);
+
+    assert_eq!(report.hunk_results, expected.report.hunk_results);
+    assert_eq!(report.failures().len(), 1);
+    assert_eq!(buffer.lines.join("\n") + "\n", expected.new_content);
+    // One splice per applied hunk, covering the lines it matched, each relative
+    // to the buffer as the splices before it left it.
+    assert_eq!(buffer.splices, vec![(1..4, 4), (10..13, 2), (19..22, 3)]);
+
+    // The plain `Vec<String>` implementation gives the same result.
+    let mut lines = original;
+    apply_patch_to_source(&patch, &mut lines, &options);
+    assert_eq!(lines, buffer.lines);
+}
+
+#[test]
+fn test_unterminated_fence_marks_patches_as_possibly_truncated() {
+    // A response cut off right after a removed line: the replacement is missing.
+    let cut_off = indoc! {r#"
+        Here is the fix:
+
+        ```diff
+        --- a/src/lib.rs
+        +++ b/src/lib.rs
+        @@ -1,3 +1,3 @@
+         fn main() {
+        -    println!("old");
+    "#};
+    let patches = parse_diffs(cut_off).unwrap();
+    assert_eq!(patches.len(), 1);
+    assert!(patches[0].is_possibly_truncated());
+    assert_eq!(
+        patches[0].hunks[0].removed_lines(),
+        vec!["    println!(\"old\");"]
+    );
+    let origin = patches[0].source_block.as_ref().unwrap();
+    assert!(origin.unterminated);
+    assert_eq!(origin.start_line, 3);
+
+    let (report_patches, report) = parse_auto_with_report(cut_off).unwrap();
+    assert_eq!(report_patches, patches);
+    assert_eq!(
+        report.warnings,
+        vec![
+            ParseWarning::UnterminatedCodeBlock { line: 3 },
+            ParseWarning::UnfinishedHunk { line: 8 },
+        ]
+    );
+
+    // Cut off after an added line: the block is still flagged, but the hunk
+    // does not end on a dangling removal.
+    let cut_after_addition = cut_off.to_string() + "+    println!(\"new\");\n";
+    let (patches, report) = parse_auto_with_report(&cut_after_addition).unwrap();
+    assert!(patches[0].is_possibly_truncated());
+    assert_eq!(
+        report.warnings,
+        vec![ParseWarning::UnterminatedCodeBlock { line: 3 }]
+    );
+
+    // A closed block ending in a pure deletion is complete.
+    let closed = cut_off.to_string() + "```\n";
+    let (patches, report) = parse_auto_with_report(&closed).unwrap();
+    assert!(!patches[0].is_possibly_truncated());
+    assert!(!patches[0].source_block.as_ref().unwrap().unterminated);
+    assert!(report.warnings.is_empty());
+}
+
+#[test]
+fn test_validate_relative_path_decisions() {
+    let accepted = [
+        ("src/main.rs", "src/main.rs"),
+        ("./src/./main.rs", "src/main.rs"),
+        ("a/../b", "b"),
+        ("a/b/../../c", "c"),
+        ("a//b/", "a/b"),
+        ("a\\..\\b", "b"),
+        // Lookalikes of `.` and `..` are ordinary names.
+        ("\u{2024}\u{2024}/x", "\u{2024}\u{2024}/x"),
+        ("\u{ff0e}\u{ff0e}/x", "\u{ff0e}\u{ff0e}/x"),
+        ("a/\u{2025}/b", "a/\u{2025}/b"),
+        ("...", "..."),
+        ("..a/b", "..a/b"),
+        // Only exact device names are reserved.
+        ("CONSOLE.txt", "CONSOLE.txt"),
+        ("aux_data/file", "aux_data/file"),
+        ("COM10", "COM10"),
+        ("LPT0.log", "LPT0.log"),
+    ];
+    for (input, expected) in accepted {
+        assert_eq!(
+            validate_relative_path(Path::new(input)),
+            Ok(PathBuf::from(expected)),
+            "{:?}",
+            input
+        );
+    }
+
+    let escapes = [
+        "..",
+        "./..",
+        "a/../..",
+        "a/./../..",
+        "a/../../b",
+        "..\\x",
+        "a\\..\\..\\b",
+    ];
+    for input in escapes {
+        assert!(
+            matches!(
+                validate_relative_path(Path::new(input)),
+                Err(PathValidationError::EscapesRoot { .. })
+            ),
+            "{:?}",
+            input
+        );
+    }
+    for input in [
+        "/etc/passwd",
+        "\\x",
+        "C:/Windows",
+        "c:relative",
+        "//server/share/f",
+    ] {
+        assert!(
+            matches!(
+                validate_relative_path(Path::new(input)),
+                Err(PathValidationError::Absolute { .. })
+            ),
+            "{:?}",
+            input
+        );
+    }
+    for input in [
+        "con",
+        "a/NUL",
+        "prn.txt",
+        "Aux.tar.gz",
+        "com1",
+        "LPT9.log",
+        "CON ",
+        "COM\u{b9}",
+        "conin$",
+    ] {
+        assert!(
+            matches!(
+                validate_relative_path(Path::new(input)),
+                Err(PathValidationError::ReservedName { .. })
+            ),
+            "{:?}",
+            input
+        );
+    }
+    for input in ["", ".", "./", "a/..", "a/b/../.."] {
+        assert!(
+            matches!(
+                validate_relative_path(Path::new(input)),
+                Err(PathValidationError::Empty { .. })
+            ),
+            "{:?}",
+            input
+        );
+    }
+    assert!(matches!(
+        validate_relative_path(Path::new("a\0/../../b")),
+        Err(PathValidationError::NulByte { .. })
+    ));
+
+    // Every path built from these components is accepted exactly when no `..`
+    // climbs above the root and something is left, whichever separator is used.
+    let parts = ["a", "b", ".", "..", ""];
+    for len in 1..=4 {
+        for mut n in 0..parts.len().pow(len) {
+            let mut components = Vec::new();
+            for _ in 0..len {
+                components.push(parts[n % parts.len()]);
+                n /= parts.len();
+            }
+            let mut depth: Vec<&str> = Vec::new();
+            let mut escaped = false;
+            for component in &components {
+                match *component {
+                    "" | "." => {}
+                    ".." => escaped |= depth.pop().is_none(),
+                    name => depth.push(name),
+                }
+            }
+            for separator in ["/", "\\"] {
+                let input = components.join(separator);
+                let result = validate_relative_path(Path::new(&input));
+                if input.starts_with(separator) {
+                    assert!(
+                        matches!(result, Err(PathValidationError::Absolute { .. })),
+                        "{:?}",
+                        input
+                    );
+                } else if escaped {
+                    assert!(
+                        matches!(result, Err(PathValidationError::EscapesRoot { .. })),
+                        "{:?}",
+                        input
+                    );
+                } else if depth.is_empty() {
+                    assert!(
+                        matches!(result, Err(PathValidationError::Empty { .. })),
+                        "{:?}",
+                        input
+                    );
+                } else {
+                    assert_eq!(result, Ok(PathBuf::from(depth.join("/"))), "{:?}", input);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_apply_patch_to_file_validates_paths_before_touching_the_filesystem() {
+    let dir = tempdir().unwrap();
+    let creation = |path: &str| {
+        let mut patch =
+            parse_single_patch("--- /dev/null\n+++ b/x\n@@ -0,0 +1 @@\n+new\n").unwrap();
+        patch.file_path = PathBuf::from(path);
+        patch
+    };
+
+    let err = apply_patch_to_file(
+        &creation("new_dir/../../escape.txt"),
+        dir.path(),
+        ApplyOptions::new(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, PatchError::PathTraversal(_)), "{:?}", err);
+
+    let err = apply_patch_to_file(&creation("new_dir/a\0b"), dir.path(), ApplyOptions::new())
+        .unwrap_err();
+    assert!(
+        matches!(
+            err,
+            PatchError::InvalidPath(PathValidationError::NulByte { .. })
+        ),
+        "{:?}",
+        err
+    );
+    assert!(!dir.path().join("new_dir").exists());
+
+    // Device names are only reserved on Windows; elsewhere they are plain files.
+    let result = apply_patch_to_file(&creation("aux.c"), dir.path(), ApplyOptions::new());
+    if cfg!(windows) {
+        assert!(matches!(
+            result,
+            Err(PatchError::InvalidPath(
+                PathValidationError::ReservedName { .. }
+            ))
+        ));
+    } else {
+        assert!(result.unwrap().report.all_applied_cleanly());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("aux.c")).unwrap(),
+            "new\n"
+        );
+    }
+}
+
+#[test]
+fn test_batch_overrides_give_each_patch_its_own_options() {
+    let dir = tempdir().unwrap();
+    // Both files have drifted from the patch: their bodies are re-indented.
+    let original = "fn setup() {\n        let a = 1;\n        let b = 2;\n        let c = 3;\n}\n";
+    fs::create_dir(dir.path().join("generated")).unwrap();
+    fs::write(dir.path().join("generated/api.rs"), original).unwrap();
+    fs::write(dir.path().join("critical.rs"), original).unwrap();
+    let hunk = indoc! {"
+        @@ -1,5 +1,5 @@
+         fn setup() {
+             let a = 1;
+        -    let b = 2;
+        +    let b = 20;
+             let c = 3;
+         }
+    "};
+    let diff = format!(
+        "--- a/generated/api.rs\n+++ b/generated/api.rs\n{hunk}--- a/critical.rs\n+++ b/critical.rs\n{hunk}"
+    );
+    let patches = parse_patches(&diff).unwrap();
+    let default_options = ApplyOptions::new();
+
+    let batch = apply_patches_to_dir_with_overrides(
+        &patches,
+        dir.path(),
+        default_options.clone(),
+        &|patch| (patch.file_path == Path::new("critical.rs")).then(ApplyOptions::exact),
+    );
+
+    let fuzzy = batch.results[0].1.as_ref().unwrap();
+    assert!(fuzzy.report.all_applied_cleanly());
+    assert_eq!(fuzzy.options_used, default_options);
+    assert!(fs::read_to_string(dir.path().join("generated/api.rs"))
+        .unwrap()
+        .contains("let b = 20;"));
+
+    let exact = batch.results[1].1.as_ref().unwrap();
+    assert!(!exact.report.all_applied_cleanly());
+    assert_eq!(exact.options_used, ApplyOptions::exact());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("critical.rs")).unwrap(),
+        original
+    );
+
+    // Without overrides, every patch gets the default options.
+    fs::write(dir.path().join("critical.rs"), original).unwrap();
+    let batch = apply_patches_to_dir(&patches[1..], dir.path(), default_options.clone());
+    let result = batch.results[0].1.as_ref().unwrap();
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(result.options_used, default_options);
+}
+
+#[test]
+fn test_hunk_lines_without_header_start_a_hunk() {
+    let original = "fn main() {\n    let x = 1;\n    println!(\"{}\", x);\n}\n";
+    let diff = indoc! {r#"
+        ```diff
+        --- a/src/main.rs
+        +++ b/src/main.rs
+         fn main() {
+        -    let x = 1;
+        +    let x = 2;
+             println!("{}", x);
+        ```
+    "#};
+    let (patches, report) = parse_auto_with_report(diff).unwrap();
+    assert_eq!(patches.len(), 1);
+    assert_eq!(patches[0].hunks.len(), 1);
+    let hunk = &patches[0].hunks[0];
+    assert_eq!(hunk.old_start_line, None);
+    assert_eq!(hunk.raw_header, None);
+    assert_eq!(hunk.removed_lines(), vec!["    let x = 1;"]);
+    assert_eq!(hunk.added_lines(), vec!["    let x = 2;"]);
+    assert_eq!(
+        report.warnings,
+        vec![ParseWarning::MissingHunkHeader { line: 4 }]
+    );
+
+    // The patch is no longer a silent no-op.
+    let result = apply_patch_to_content(&patches[0], Some(original), &ApplyOptions::exact());
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(
+        result.new_content,
+        "fn main() {\n    let x = 2;\n    println!(\"{}\", x);\n}\n"
+    );
+
+    // A real `@@` header after headerless lines starts a second hunk.
+    let mixed = "--- a/f\n+++ b/f\n-a\n+b\n@@ -5 +5 @@\n-c\n+d\n";
+    let patches = parse_patches(mixed).unwrap();
+    assert_eq!(patches[0].hunks.len(), 2);
+    assert_eq!(patches[0].hunks[0].lines, vec!["-a", "+b"]);
+    assert_eq!(patches[0].hunks[1].old_start_line, Some(5));
+    // So are lines under a `@@` header without line numbers.
+    let bare = parse_patches("--- a/f\n+++ b/f\n@@\n-a\n+b\n").unwrap();
+    assert_eq!(bare[0].hunks[0].lines, vec!["-a", "+b"]);
+
+    // Headers with no change lines still give no hunks, and so no patch.
+    assert!(parse_patches("--- a/f\n+++ b/f\n").unwrap().is_empty());
+    // Nor do headers followed by context lines alone.
+    let (patches, report) = parse_auto_with_report("--- a/f\n+++ b/f\n a\n b\n").unwrap();
+    assert!(patches.iter().all(|p| p.hunks.is_empty()));
+    assert!(report.warnings.is_empty());
+    // The strict parser does not guess.
+    assert!(parse_patches_strict("--- a/f\n+++ b/f\n-a\n+b\n").is_err());
+}
+
+#[test]
+fn test_byte_ranges_point_at_the_replaced_lines_of_the_original() {
+    let patch = parse_single_patch(indoc! {"
+        --- a/f.txt
+        +++ b/f.txt
+        @@ -1,2 +1,3 @@
+         alpha
+        +inserted
+         beta
+        @@ -4,3 +5,2 @@
+         delta
+        -epsilon
+         zeta
+        @@ -7,2 +7,2 @@
+         eta
+        -theta
+        +THETA
+    "})
+    .unwrap();
+    let options = ApplyOptions::exact().with_compute_byte_ranges(true);
+
+    // CRLF endings, no newline at the end, and LF endings with one.
+    let crlf = "alpha\r\nbeta\r\ngamma\r\ndelta\r\nepsilon\r\nzeta\r\neta\r\ntheta";
+    let lf = "alpha\nbeta\ngamma\ndelta\nepsilon\nzeta\neta\ntheta\n";
+    for (original, ending) in [(crlf, "\r\n"), (lf, "\n")] {
+        let result = apply_patch_to_content(&patch, Some(original), &options);
+        assert!(result.report.all_applied_cleanly());
+        let mut ranges = Vec::new();
+        for status in &result.report.hunk_results {
+            let HunkApplyStatus::Applied {
+                replaced_lines,
+                byte_range,
+                ..
+            } = status
+            else {
+                panic!("unexpected status {:?}", status);
+            };
+            let range = byte_range.clone().expect("a byte range for every hunk");
+            assert_eq!(&original[range.clone()], replaced_lines.join(ending));
+            ranges.push(range);
+        }
+        // The later hunks are reported in the original's offsets, not
+        // shifted by the line the first one inserted.
+        let starts: Vec<usize> = ranges.iter().map(|r| r.start).collect();
+        let line_start = |line: &str| original.find(&format!("\n{}", line)).map_or(0, |i| i + 1);
+        let expected = ["alpha", "delta", "eta"].map(line_start);
+        assert_eq!(starts, expected);
+        assert_eq!(ranges[2].end, original.trim_end().len());
+
+        // The helper gives the same answer from the lines alone.
+        let lines: Vec<&str> = original.lines().collect();
+        let location = HunkLocation {
+            start_index: 3,
+            length: 3,
+        };
+        assert_eq!(location.byte_range(&lines, ending.len()), ranges[1]);
+    }
+
+    // Nothing is computed unless asked for.
+    let result = apply_patch_to_content(&patch, Some(lf), &ApplyOptions::exact());
+    assert!(result.report.hunk_results.iter().all(|status| matches!(
+        status,
+        HunkApplyStatus::Applied {
+            byte_range: None,
+            ..
+        }
+    )));
+}
+
+#[test]
+fn test_repeated_hunk_is_skipped_as_a_duplicate() {
+    let original = [
+        "fn main() {",
+        "    let x = 1;",
+        "    println!(\"{}\", x);",
+        "}",
+    ];
+    let hunk = "@@ -1,3 +1,3 @@\n fn main() {\n-    let x = 1;\n+    let x = 2;\n     println!(\"{}\", x);\n";
+    let once = parse_single_patch(&format!("--- a/main.rs\n+++ b/main.rs\n{}", hunk)).unwrap();
+    let twice =
+        parse_single_patch(&format!("--- a/main.rs\n+++ b/main.rs\n{}{}", hunk, hunk)).unwrap();
+    assert_eq!(twice.hunks.len(), 2);
+    let options = ApplyOptions::new();
+
+    let expected = apply_patch_to_lines(&once, Some(&original), &options);
+    let result = apply_patch_to_lines(&twice, Some(&original), &options);
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(result.report.success_count(), 2);
+    assert!(matches!(
+        result.report.hunk_results[0],
+        HunkApplyStatus::Applied { .. }
+    ));
+    assert_eq!(
+        result.report.hunk_results[1],
+        HunkApplyStatus::SkippedDuplicate {
+            first_applied_index: 1
+        }
+    );
+    assert_eq!(result.new_content, expected.new_content);
+
+    // A repeated insertion does not land a second time next to the first.
+    let insertion = "@@ -1,2 +1,3 @@\n a\n+b\n c\n";
+    let patch =
+        parse_single_patch(&format!("--- a/f\n+++ b/f\n{}{}", insertion, insertion)).unwrap();
+    let result = apply_patch_to_lines(&patch, Some(&["a", "c"]), &options);
+    assert!(result.report.all_applied_cleanly());
+    assert_eq!(result.new_content, "a\nb\nc\n");
+
+    // A repeat that fuzzily matches a second, drifted copy of the code is a
+    // genuine change there, not a duplicate.
+    let two_copies = [
+        "fn first() {",
+        "    let config = load();",
+        "    config.validate_everything_now();",
+        "    run(config);",
+        "}",
+        "fn second() {",
+        "    let config = load();",
+        "    config.validate_everything_now(); ",
+        "    run(config);",
+        "}",
+    ];
+    let change = "@@ -2,3 +2,3 @@\n     let config = load();\n-    config.validate_everything_now();\n+    config.check();\n     run(config);\n";
+    let patch =
+        parse_single_patch(&format!("--- a/f.rs\n+++ b/f.rs\n{}{}", change, change)).unwrap();
+    let result = apply_patch_to_lines(&patch, Some(&two_copies), &options);
+    assert!(matches!(
+        result.report.hunk_results[1],
+        HunkApplyStatus::Applied {
+            location: HunkLocation { start_index: 6, .. },
+            ..
+        }
+    ));
+    assert_eq!(result.new_content.matches("config.check();").count(), 2);
+
+    // A copy of a hunk that failed is not excused.
+    let stale = hunk.replace("let x = 1", "let x = 9");
+    let failing =
+        parse_single_patch(&format!("--- a/main.rs\n+++ b/main.rs\n{}{}", stale, stale)).unwrap();
+    let result = apply_patch_to_lines(&failing, Some(&original), &ApplyOptions::exact());
+    assert_eq!(result.report.failure_count(), 2);
+
+    // Repeats can also be dropped before applying.
+    let mut deduped = twice.clone();
+    deduped.dedup_hunks();
+    assert_eq!(deduped.hunks, once.hunks);
+}
+
+#[test]
+fn test_patches_from_dirs_round_trip_reproduces_the_new_tree() {
+    fn read_tree(root: &Path) -> std::collections::BTreeMap<PathBuf, Vec<u8>> {
+        let mut files = std::collections::BTreeMap::new();
+        let mut pending = vec![root.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if fs::symlink_metadata(&path).unwrap().is_symlink() {
+                    continue;
+                } else if path.is_dir() {
+                    pending.push(path);
+                } else {
+                    let relative = path.strip_prefix(root).unwrap().to_path_buf();
+                    files.insert(relative, fs::read(&path).unwrap());
+                }
+            }
+        }
+        files
+    }
+
+    let old = tempdir().unwrap();
+    let new = tempdir().unwrap();
+    let write = |root: &Path, path: &str, content: &[u8]| {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    };
+    write(old.path(), "README.md", b"# Demo\n\nOld intro.\n");
+    write(new.path(), "README.md", b"# Demo\n\nNew intro.\n");
+    write(old.path(), "src/lib.rs", b"pub fn a() {}\n");
+    write(new.path(), "src/lib.rs", b"pub fn a() {}\npub fn b() {}");
+    write(old.path(), "src/same.rs", b"unchanged\n");
+    write(new.path(), "src/same.rs", b"unchanged\n");
+    write(new.path(), "src/nested/deep/new.rs", b"fn new() {}\n");
+    write(old.path(), "obsolete.txt", b"gone\n");
+    write(old.path(), "empty.txt", b"");
+    write(old.path(), "icon.png", b"\x89PNG\0\x01");
+    write(new.path(), "icon.png", b"\x89PNG\0\x01");
+    write(new.path(), "target/debug/build.log", b"ignored\n");
+    write(new.path(), "src/debug.log", b"also ignored\n");
+    #[cfg(unix)]
+    std::os::unix::fs::symlink("README.md", new.path().join("link.md")).unwrap();
+
+    let options = DirDiffOptions::new()
+        .with_ignore("target/")
+        .with_ignore("*.log");
+    let patches = patches_from_dirs(old.path(), new.path(), 3, options.clone()).unwrap();
+    let paths: Vec<_> = patches
+        .iter()
+        .map(|p| p.file_path.to_string_lossy().replace('\\', "/"))
+        .collect();
+    assert_eq!(
+        paths,
+        [
+            "README.md",
+            "empty.txt",
+            "obsolete.txt",
+            "src/lib.rs",
+            "src/nested/deep/new.rs"
+        ]
+    );
+    assert!(patches[1].deletes_file && patches[2].deletes_file);
+
+    let batch = apply_patches_to_dir(&patches, old.path(), ApplyOptions::exact());
+    assert!(batch.all_succeeded(), "{:?}", batch.results);
+    let mut expected = read_tree(new.path());
+    expected.retain(|path, _| {
+        !path.starts_with("target") && path.extension().is_none_or(|ext| ext != "log")
+    });
+    assert_eq!(read_tree(old.path()), expected);
+
+    // Binary files that differ are reported, or rejected on request.
+    write(new.path(), "icon.png", b"\x89PNG\0\x02");
+    let (patches, report) =
+        patches_from_dirs_with_report(old.path(), new.path(), 3, options.clone()).unwrap();
+    assert!(patches.is_empty());
+    assert_eq!(report.binary_files, vec![PathBuf::from("icon.png")]);
+    let err = patches_from_dirs(
+        old.path(),
+        new.path(),
+        3,
+        options.with_binary_files(BinaryFilePolicy::Fail),
+    )
+    .unwrap_err();
+    assert!(matches!(err, PatchError::BinaryFile { path } if path == Path::new("icon.png")));
+}